@@ -92,7 +92,8 @@ document.addEventListener('DOMContentLoaded', async () => {
         .setExempt(newExempt)
         .accounts({
           dappConfig: dappConfigPda,
-          currentExempt: walletAdapter.publicKey,
+          admin: walletAdapter.publicKey,
+          roleGrant: null, // the connected wallet must be the owner
         })
         .rpc();
       statusDiv.textContent = `Exempt address set to: ${newExempt.toString()}`;
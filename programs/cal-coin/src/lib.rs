@@ -1,13 +1,15 @@
 use anchor_lang::prelude::*;
 use anchor_lang::prelude::InterfaceAccount;
 use anchor_lang::system_program;
+use anchor_lang::solana_program::keccak;
+use anchor_lang::Discriminator;
 use anchor_spl::{
-    token_2022::{self as token_2022, mint_to, transfer as token_transfer, ID as TOKEN_2022_PROGRAM_ID},
+    token_2022::{self as token_2022, mint_to, ID as TOKEN_2022_PROGRAM_ID},
     associated_token::AssociatedToken,
     token_interface::{Mint, Token2022, TokenAccount},
 };
 use solana_gateway::Gateway;
-use anchor_spl::token_2022::{MintTo, Transfer};
+use anchor_spl::token_2022::{Burn, MintTo, TransferChecked};
 use std::str::FromStr;
 
 declare_id!("AFrYiV7fCPEVCbCXktrmGW9YuNPboaPUmFWTca3UTqZp");
@@ -16,11 +18,41 @@ declare_id!("AFrYiV7fCPEVCbCXktrmGW9YuNPboaPUmFWTca3UTqZp");
 // Constants
 // ------------------------------------------------------------------------------------------------
 
-/// Seed prefix for mint authority PDA.
-const MINT_AUTHORITY_SEED_V1: &[u8] = b"cal_coin_mint_authority_v1";
+/// Seed for the mint authority PDA created by `initialize_mint`.
+const MINT_AUTHORITY_SEED: &[u8] = b"mint_authority";
 
-/// Seed prefix for stake vault ATA PDA.
-const STAKE_VAULT_SEED: &[u8] = b"stake_vault";
+/// Seed for the global stake pool PDA (also the authority of the pool vault).
+const STAKE_POOL_SEED: &[u8] = b"stake_pool";
+/// Seed prefix of the retired per-user stake vault authority: [seed, user].
+/// Only used to drain those vaults in `migrate_stake_account`.
+const LEGACY_STAKE_VAULT_SEED: &[u8] = b"stake_vault";
+
+/// Seed prefix for timelocked admin operations: [seed, id_le].
+const QUEUED_OP_SEED: &[u8] = b"queued_op";
+/// Minimum delay between queueing an admin operation and executing it.
+const MIN_TIMELOCK_DELAY: i64 = 2 * 24 * 60 * 60; // 2 days
+/// A queued operation not executed within this window after its ETA goes stale.
+const TIMELOCK_GRACE_PERIOD: i64 = 14 * 24 * 60 * 60; // 14 days
+/// Queued op ids with this bit set belong to passed proposals (id = bit | proposal id).
+const PROPOSAL_OP_ID_BIT: u64 = 1 << 63;
+
+/// Seed prefix for role grants: [seed, role, grantee].
+const ROLE_SEED: &[u8] = b"role";
+
+/// Seed for the stCAL liquid staking receipt mint PDA.
+const ST_MINT_SEED: &[u8] = b"st_cal_mint";
+
+/// Seed for the instant-unstake liquidity buffer vault PDA.
+const BUFFER_VAULT_SEED: &[u8] = b"buffer_vault";
+
+/// Seed prefix for airdrop distributors: [seed, id_le].
+const DISTRIBUTOR_SEED: &[u8] = b"distributor";
+
+/// Seed prefix for vesting schedules: [seed, beneficiary].
+const VESTING_SEED: &[u8] = b"vesting";
+
+/// Seed prefix for per-user stake positions: [seed, user, index_le].
+const STAKE_POSITION_SEED: &[u8] = b"stake_position";
 
 /// 3 days in seconds (for claim delay in unstaking).
 const UNSTAKE_DELAY_SECONDS: i64 = 2 * 24 * 60 * 60; // 2 days
@@ -28,9 +60,34 @@ const UNSTAKE_DELAY_SECONDS: i64 = 2 * 24 * 60 * 60; // 2 days
 /// Minimum stake: 1,800 tokens = 1_800 * 10^9 (9 decimals).
 const MIN_STAKE_LAMPORTS: u64 = 1_800 * 10u64.pow(9);
 
-/// APY 5.55% expressed as numerator/denominator for integer math.
-const APY_NUMERATOR: u128 = 555;     // 5.55% → 0.0555 = 555 / 10000
-const APY_DENOMINATOR: u128 = 10000;
+/// Basis-point denominator for boosts, penalties and fees.
+const BPS_DENOMINATOR: u64 = 10_000;
+
+/// Number of lock tiers; tier 0 is the unlocked, unboosted default.
+const MAX_LOCK_TIERS: usize = 5;
+/// Number of configurable faucet streak multiplier tiers.
+const MAX_STREAK_TIERS: usize = 4;
+/// Number of configurable stake-weighted faucet boost tiers.
+const MAX_STAKE_BOOST_TIERS: usize = 4;
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+/// Seed for the governance config PDA; proposals and votes hang off it.
+const GOVERNANCE_SEED: &[u8] = b"governance";
+const PROPOSAL_SEED: &[u8] = b"proposal";
+const VOTE_SEED: &[u8] = b"vote";
+
+/// Seed prefix for per-user slash history PDAs.
+const SLASH_HISTORY_SEED: &[u8] = b"slash_history";
+/// Slash entries kept per user (ring buffer).
+const SLASH_HISTORY_LEN: usize = 8;
+
+/// Fixed-point scale for `reward_per_token_stored`.
+const REWARD_PRECISION: u128 = 1_000_000_000_000;
+
+/// A user with no claim or stake activity for this long may be flagged
+const INACTIVITY_PERIOD_SECONDS: i64 = 365 * 24 * 60 * 60;
+/// Notice given to a flagged user before the owner may sweep their accounts
+const DEREGISTER_NOTICE_SECONDS: i64 = 30 * 24 * 60 * 60;
 // 3 days in seconds
 const MAX_ACCUMULATION_SECONDS: i64 = 3 * 24 * 60 * 60; // 259200
 
@@ -39,9 +96,6 @@ const USER_RATE_PER_SEC: u64 = 20_833;
 /// Exempt user accrual: 42× the normal rate.
 const EXEMPT_RATE_PER_SEC: u64 = 910_170;
 
-/// Seconds in one year, for APR calculation.
-const SECONDS_PER_YEAR: u128 = 365 * 24 * 60 * 60;
-
 // ------------------------------------------------------------------------------------------------
 // Program Entrypoint
 // ------------------------------------------------------------------------------------------------
@@ -50,7 +104,8 @@ const SECONDS_PER_YEAR: u128 = 365 * 24 * 60 * 60;
 pub mod cal_coin {
     use super::*;
 
-    /// Initialize the dapp configuration.
+    /// Initialize the dapp configuration. Only the program's upgrade
+    /// authority may do this; it becomes the owner.
     pub fn initialize_dapp(ctx: Context<InitializeDapp>) -> Result<()> {
         let cfg = &mut ctx.accounts.dapp_config;
 
@@ -64,17 +119,76 @@ pub mod cal_coin {
         cfg.mint_authority_bump = 0;              // filled in phase 2
         cfg.initialized = false;
 
-        // Initialize supply controls
+        // Initialize supply controls; nothing reserved for airdrops or vesting
+        // yet, nor burned
         cfg.total_minted = 0;
         cfg.max_supply = 1_000_000_000_000_000;   // example max supply in microtokens
+        cfg.reserved_supply = 0;
+        cfg.total_burned = 0;
+        cfg.burn_reopens_supply = false;
+        cfg.paused = false;
 
         // Start claim‐counter at zero
         cfg.total_claims = 0;
 
+        // Faucet rates (governance can change these later)
+        cfg.user_rate_per_sec = USER_RATE_PER_SEC;
+        cfg.exempt_rate_per_sec = EXEMPT_RATE_PER_SEC;
+
+        // Treasury and slasher default to the owner
+        cfg.treasury = cfg.owner;
+        cfg.slasher = cfg.owner;
+
+        // Daily claim streak multipliers
+        cfg.streak_tiers = StreakTier::defaults();
+
+        // Faucet boost for stakers
+        cfg.stake_boost_tiers = StakeBoostTier::defaults();
+
+        // Admin changes wait out the timelock from the start
+        cfg.timelock_enforced = true;
+
         msg!("Dapp config stored; run initialize_mint next.");
         Ok(())
     }
 
+    /// Rebuild the deployed `DappConfig` in the current layout, keeping its
+    /// values and giving every newer field its `initialize_dapp` default.
+    /// Owner only; the owner pays the extra rent.
+    pub fn migrate_dapp_config(ctx: Context<MigrateDappConfig>) -> Result<()> {
+        let info = ctx.accounts.dapp_config.to_account_info();
+        require!(
+            info.data_len() == LegacyDappConfig::LEN,
+            ErrorCode::AlreadyMigrated
+        );
+        let legacy = {
+            let data = info.try_borrow_data()?;
+            require!(
+                data[..8] == DappConfig::DISCRIMINATOR,
+                ErrorCode::InvalidAccountLayout
+            );
+            let owner = &data[DappConfig::OWNER_OFFSET..DappConfig::OWNER_OFFSET + 32];
+            require!(
+                owner == ctx.accounts.owner.key().as_ref(),
+                ErrorCode::NotAuthorized
+            );
+            LegacyDappConfig::deserialize(&mut &data[8..])?
+        };
+
+        grow_account(
+            &info,
+            &ctx.accounts.owner,
+            &ctx.accounts.system_program,
+            DappConfig::LEN,
+        )?;
+        legacy
+            .into_config()
+            .try_serialize(&mut &mut info.try_borrow_mut_data()?[..])?;
+
+        msg!("Dapp config migrated => len={}", DappConfig::LEN);
+        Ok(())
+    }
+
     /// Phase 2: Create the SPL Token 2022 mint with the PDA as authority.
     pub fn initialize_mint(
         ctx: Context<InitializeMint>,
@@ -82,6 +196,7 @@ pub mod cal_coin {
     ) -> Result<()> {
         let cfg = &mut ctx.accounts.dapp_config;
         require!(!cfg.initialized, ErrorCode::AlreadyInitialized);
+        require_role(cfg, &ctx.accounts.payer.key(), Role::Admin, &ctx.accounts.role_grant)?;
 
         // Save PDA bump and mint address
         cfg.mint_authority_bump = ctx.bumps.mint_authority;
@@ -96,116 +211,57 @@ pub mod cal_coin {
     }
 
     /// One-time registration for a user, creates their UserPda and StakeAccount.
+    ///
+    /// Optional: `claim` and `stake` also register first-time users.
     pub fn register_user(ctx: Context<RegisterUser>) -> Result<()> {
         let cfg = &ctx.accounts.dapp_config;
         let user_key = ctx.accounts.user.key();
 
-        if user_key != cfg.exempt_address {
-            // Use on-chain gatekeeper_network instead of hardcoded literal
-            let gatekeeper_network = cfg.gatekeeper_network;
-            Gateway::verify_gateway_token_account_info(
-                &ctx.accounts.gateway_token.to_account_info(),
-                &user_key,
-                &gatekeeper_network,
-                None,
-            )
-            .map_err(|_e| {
-                msg!("Gateway token verification failed");
-                error!(ErrorCode::GatewayCheckFailed)
-            })?;
-        } else {
-            msg!("User is exempt => skipping gateway check in register_user");
-        }
-
-        let user_pda = &mut ctx.accounts.user_pda;
-        user_pda.authority = user_key;
-        user_pda.last_claimed_timestamp = 0;
-        user_pda.claimed_so_far = 0;
-
-        // Initialize their StakeAccount
-        let stake = &mut ctx.accounts.stake_account;
-        stake.authority = user_key;
-        stake.stake_amount = 0;
-        stake.last_reward_timestamp = Clock::get()?.unix_timestamp;
-        stake.pending_withdrawal_amount = 0;
-        stake.withdraw_request_timestamp = 0;
+        verify_gateway(cfg, &ctx.accounts.gateway_token, &user_key, "register_user")?;
+        init_user_accounts(
+            &mut ctx.accounts.user_pda,
+            &mut ctx.accounts.stake_account,
+            user_key,
+            Clock::get()?.unix_timestamp,
+        );
 
         msg!(
             "Registered user => user_pda={}, authority={}",
-            user_pda.key(),
+            ctx.accounts.user_pda.key(),
             user_key
         );
         Ok(())
     }
 
-    /// Claim faucet tokens (same as before).
+    /// Claim faucet tokens (same as before). Registers first-time users, who
+    /// start accruing from that moment and so receive nothing on this call.
     pub fn claim(ctx: Context<Claim>) -> Result<()> {
         let cfg = &mut ctx.accounts.dapp_config;
         let user_key = ctx.accounts.user.key();
         let user_pda = &mut ctx.accounts.user_pda;
 
-        if user_key != cfg.exempt_address {
-            let gatekeeper_network = cfg.gatekeeper_network;
-            Gateway::verify_gateway_token_account_info(
-                &ctx.accounts.gateway_token.to_account_info(),
-                &user_key,
-                &gatekeeper_network,
-                None,
-            )
-            .map_err(|_e| {
-                msg!("Gateway token verification failed");
-                error!(ErrorCode::GatewayCheckFailed)
-            })?;
-        } else {
-            msg!("User is exempt => skipping gateway check in claim");
+        verify_gateway(cfg, &ctx.accounts.gateway_token, &user_key, "claim")?;
+        if user_pda.authority == Pubkey::default() {
+            register_on_first_use(
+                user_pda,
+                &mut ctx.accounts.stake_account,
+                user_key,
+                Clock::get()?.unix_timestamp,
+            );
+            msg!("Registered user on first claim => authority={}", user_key);
+            return Ok(());
         }
 
-        // Update the total_claims counter
-        cfg.total_claims = cfg
-            .total_claims
-            .checked_add(1)
-            .ok_or(ErrorCode::IssuanceRateTooHigh)?;
-        
         let now = Clock::get()?.unix_timestamp;
-        let raw_elapsed = now.saturating_sub(user_pda.last_claimed_timestamp);
-        require!(raw_elapsed >= 60, ErrorCode::CooldownNotMet);
-
-        // Cap elapsed at 3 days (259,200 seconds)
-        let elapsed = std::cmp::min(raw_elapsed, MAX_ACCUMULATION_SECONDS);
-
-        // Determine per-second rate: exempt = 42×, else normal
-        let tokens_per_second_micro = if user_key == cfg.exempt_address {
-            EXEMPT_RATE_PER_SEC
-        } else {
-            USER_RATE_PER_SEC
-        };
-
-        let minted_amount = tokens_per_second_micro
-            .checked_mul(elapsed as u64)
-            .ok_or(ErrorCode::IssuanceRateTooHigh)?;
+        let stake_power = voting_power(&ctx.accounts.stake_account, now)?;
+        let minted_amount = accrue_faucet(cfg, user_pda, stake_power, user_key, now, "Claim")?;
         if minted_amount == 0 {
-            msg!("No tokens to mint; skipping claim.");
             return Ok(());
         }
 
-        // Enforce global max supply cap
-        let new_total = cfg
-            .total_minted
-            .checked_add(minted_amount)
-            .ok_or(ErrorCode::IssuanceRateTooHigh)?;
-        require!(new_total <= cfg.max_supply, ErrorCode::SupplyExceeded);
-        cfg.total_minted = new_total;
-
-        // Track per-user total claimed
-        let new_user_total = user_pda
-            .claimed_so_far
-            .checked_add(minted_amount)
-            .ok_or(ErrorCode::IssuanceRateTooHigh)?;
-        user_pda.claimed_so_far = new_user_total;
-
         // Perform the mint via CPI, with PDA signer
         let bump = cfg.mint_authority_bump;
-        let seeds = &[MINT_AUTHORITY_SEED_V1, &[bump]];
+        let seeds = &[MINT_AUTHORITY_SEED, &[bump]];
         let signer_seeds = &[&seeds[..]];
         let cpi_ctx = CpiContext::new_with_signer(
             ctx.accounts.token_program.to_account_info(),
@@ -217,312 +273,4732 @@ pub mod cal_coin {
             signer_seeds,
         );
         token_2022::mint_to(cpi_ctx, minted_amount)?;
-
-        user_pda.last_claimed_timestamp = now;
-        msg!(
-            "Claim => user={}, minted={} μtokens, elapsed={}s, last_claimed={}",
-            user_key,
-            minted_amount,
-            elapsed,
-            now
-        );
         Ok(())
     }
 
-    /// Change the exempt address.
-    pub fn set_exempt(ctx: Context<SetExempt>, new_exempt: Pubkey) -> Result<()> {
+    /// Claim faucet tokens straight into the pool vault as an unlocked stake.
+    /// MIN_STAKE_LAMPORTS applies to the resulting stake, not to this deposit.
+    /// Like `claim`, a call that registers the user mints nothing.
+    pub fn claim_and_stake(ctx: Context<ClaimAndStake>) -> Result<()> {
         let cfg = &mut ctx.accounts.dapp_config;
-        let signer_key = ctx.accounts.current_exempt.key();
+        let user_key = ctx.accounts.user.key();
+        let user_pda = &mut ctx.accounts.user_pda;
+        let stake_acc: &mut StakeAccount = &mut ctx.accounts.stake_account;
+        let now = Clock::get()?.unix_timestamp;
 
-        if cfg.exempt_address == Pubkey::default() {
-            // First-time set: only owner can define it.
-            require!(signer_key == cfg.owner, ErrorCode::NotAuthorized);
-        } else {
-            // Once an exempt exists, only exempt or owner can update.
-            require!(
-                signer_key == cfg.exempt_address || signer_key == cfg.owner,
-                ErrorCode::NotExemptAddress
-            );
+        verify_gateway(cfg, &ctx.accounts.gateway_token, &user_key, "claim_and_stake")?;
+        if user_pda.authority == Pubkey::default() {
+            register_on_first_use(user_pda, stake_acc, user_key, now);
+            msg!("Registered user on first claim => authority={}", user_key);
+            return Ok(());
         }
 
-        cfg.exempt_address = new_exempt;
-        msg!("Exempt address updated => new_exempt={}", new_exempt);
-        Ok(())
-    }
-
-    /// Stake a given amount of tokens. Must be ≥ MIN_STAKE_LAMPORTS.
-    pub fn stake(ctx: Context<Stake>, amount: u64) -> Result<()> {
-        let stake_acc = &mut ctx.accounts.stake_account;
-        let user_key = ctx.accounts.user.key();
-
-        // Must stake at least the minimum
-        require!(amount >= MIN_STAKE_LAMPORTS, ErrorCode::StakeTooSmall);
+        let stake_power = voting_power(stake_acc, now)?;
+        let minted_amount =
+            accrue_faucet(cfg, user_pda, stake_power, user_key, now, "Claim and stake")?;
+        if minted_amount == 0 {
+            return Ok(());
+        }
+        require!(
+            stake_acc.stake_amount.saturating_add(minted_amount) >= MIN_STAKE_LAMPORTS,
+            ErrorCode::StakeTooSmall
+        );
 
-        // Transfer tokens from user ATA to stake vault PDA
-        let cpi_ctx = CpiContext::new(
+        // Mint into the pool vault instead of the user's ATA
+        let bump = cfg.mint_authority_bump;
+        let seeds = &[MINT_AUTHORITY_SEED, &[bump]];
+        let signer_seeds = &[&seeds[..]];
+        let cpi_ctx = CpiContext::new_with_signer(
             ctx.accounts.token_program.to_account_info(),
-            Transfer {
-                from: ctx.accounts.user_ata.to_account_info(),
-                to: ctx.accounts.stake_vault.to_account_info(),
-                authority: ctx.accounts.user.to_account_info(),
+            MintTo {
+                mint: ctx.accounts.token_mint.to_account_info(),
+                to: ctx.accounts.pool_vault.to_account_info(),
+                authority: ctx.accounts.mint_authority.to_account_info(),
             },
+            signer_seeds,
         );
-        token_2022::transfer(cpi_ctx, amount)?;
-
-        // Compute rewards on existing stake and compound
-        let now = Clock::get()?.unix_timestamp;
-        if stake_acc.stake_amount > 0 {
-            let elapsed = (now.saturating_sub(stake_acc.last_reward_timestamp)) as u128;
-            let principal = stake_acc.stake_amount as u128;
-            let reward = principal
-                .checked_mul(APY_NUMERATOR)
-                .unwrap()
-                .checked_mul(elapsed)
-                .unwrap()
-                .checked_div(APY_DENOMINATOR.checked_mul(SECONDS_PER_YEAR).unwrap())
-                .unwrap() as u64;
-            // Compound reward into stake balance
-            stake_acc.stake_amount = stake_acc
-                .stake_amount
-                .checked_add(reward)
-                .ok_or(ErrorCode::ArithmeticError)?;
-        }
+        token_2022::mint_to(cpi_ctx, minted_amount)?;
 
-        // Add newly staked tokens
-        stake_acc.stake_amount = stake_acc
-            .stake_amount
-            .checked_add(amount)
-            .ok_or(ErrorCode::ArithmeticError)?;
-        stake_acc.last_reward_timestamp = now;
+        credit_stake(&mut ctx.accounts.stake_pool, stake_acc, minted_amount, 0, now)?;
 
         msg!(
-            "Stake => user={}, amount_staked={}, new_total={}",
+            "Claim and stake => user={}, staked={}, new_total={}",
             user_key,
-            amount,
+            minted_amount,
             stake_acc.stake_amount
         );
         Ok(())
     }
 
-    /// Request to unstake all tokens. After 2 days, user can call `claim_stake`.
-    pub fn request_unstake(ctx: Context<RequestUnstake>) -> Result<()> {
-        let stake_acc = &mut ctx.accounts.stake_account;
-        let user_key = ctx.accounts.user.key();
-        let now = Clock::get()?.unix_timestamp;
-
-        require!(stake_acc.stake_amount > 0, ErrorCode::NothingToUnstake);
+    /// Create airdrop distributor `id` for a Merkle `root` over
+    /// `(index, claimant, amount)` leaves. `total_amount` is reserved under
+    /// `max_supply` until claimed or clawed back. Treasurer only.
+    pub fn create_distributor(
+        ctx: Context<CreateDistributor>,
+        id: u64,
+        root: [u8; 32],
+        total_amount: u64,
+        num_leaves: u32,
+        expires_at: i64,
+    ) -> Result<()> {
+        let cfg = &mut ctx.accounts.dapp_config;
+        require_role(cfg, &ctx.accounts.owner.key(), Role::Treasurer, &ctx.accounts.role_grant)?;
         require!(
-            stake_acc.pending_withdrawal_amount == 0,
-            ErrorCode::UnstakeAlreadyRequested
+            total_amount > 0 && num_leaves > 0 && expires_at > Clock::get()?.unix_timestamp,
+            ErrorCode::InvalidDistributor
         );
 
-        // Compute rewards up to now
-        let elapsed = (now.saturating_sub(stake_acc.last_reward_timestamp)) as u128;
-        let principal = stake_acc.stake_amount as u128;
-        let reward = principal
-            .checked_mul(APY_NUMERATOR)
-            .unwrap()
-            .checked_mul(elapsed)
-            .unwrap()
-            .checked_div(APY_DENOMINATOR.checked_mul(SECONDS_PER_YEAR).unwrap())
-            .unwrap() as u64;
-
-        // Total to withdraw = stake + reward
-        let total_withdraw = stake_acc
-            .stake_amount
-            .checked_add(reward)
-            .ok_or(ErrorCode::ArithmeticError)?;
+        cfg.reserve_supply(total_amount)?;
 
-        stake_acc.pending_withdrawal_amount = total_withdraw;
-        stake_acc.stake_amount = 0;
-        stake_acc.withdraw_request_timestamp = now;
-        stake_acc.last_reward_timestamp = now;
+        let distributor = &mut ctx.accounts.distributor;
+        distributor.id = id;
+        distributor.root = root;
+        distributor.total_amount = total_amount;
+        distributor.claimed_amount = 0;
+        distributor.expires_at = expires_at;
+        distributor.num_leaves = num_leaves;
+        distributor.claimed_bitmap = vec![0u8; Distributor::bitmap_len(num_leaves)];
 
         msg!(
-            "Unstake requested => user={}, amount_pending={}, can_claim_at={}",
-            user_key,
-            total_withdraw,
-            now + UNSTAKE_DELAY_SECONDS
+            "Distributor created => id={}, total={}, leaves={}, expires_at={}",
+            id,
+            total_amount,
+            num_leaves,
+            expires_at
         );
         Ok(())
     }
 
-    /// After 2 days from unstake request, claim tokens back to user's ATA.
-    pub fn claim_stake(ctx: Context<ClaimStake>) -> Result<()> {
-        let stake_acc = &mut ctx.accounts.stake_account;
-        let user_key = ctx.accounts.user.key();
-        let now = Clock::get()?.unix_timestamp;
+    /// Claim leaf `index` of distributor `id` with a Merkle proof. Tokens are
+    /// minted to the claimant out of the distributor's reservation.
+    pub fn claim_airdrop(
+        ctx: Context<ClaimAirdrop>,
+        id: u64,
+        index: u32,
+        amount: u64,
+        proof: Vec<[u8; 32]>,
+    ) -> Result<()> {
+        let cfg = &mut ctx.accounts.dapp_config;
+        let distributor = &mut ctx.accounts.distributor;
+        let claimant = ctx.accounts.claimant.key();
 
         require!(
-            stake_acc.pending_withdrawal_amount > 0,
-            ErrorCode::NothingToClaim
+            Clock::get()?.unix_timestamp < distributor.expires_at,
+            ErrorCode::DistributorExpired
         );
+        require!(index < distributor.num_leaves, ErrorCode::InvalidProof);
+        require!(!distributor.is_claimed(index), ErrorCode::AlreadyClaimed);
+
+        let leaf = keccak::hashv(&[
+            &index.to_le_bytes(),
+            claimant.as_ref(),
+            &amount.to_le_bytes(),
+        ])
+        .0;
         require!(
-            now >= stake_acc.withdraw_request_timestamp + UNSTAKE_DELAY_SECONDS,
-            ErrorCode::UnstakeDelayNotMet
+            verify_merkle_proof(&proof, distributor.root, leaf),
+            ErrorCode::InvalidProof
         );
 
-        let amount = stake_acc.pending_withdrawal_amount;
+        let claimed = distributor
+            .claimed_amount
+            .checked_add(amount)
+            .ok_or(ErrorCode::ArithmeticError)?;
+        require!(claimed <= distributor.total_amount, ErrorCode::InvalidProof);
+        distributor.claimed_amount = claimed;
+        distributor.set_claimed(index);
 
-        // Transfer from stake vault PDA to user ATA (vault authority is PDA signer)
-        // grab the user Pubkey once, so `.as_ref()` borrows from a named variable
-        let user_key = ctx.accounts.user.key();
-        let (_vault_authority, vault_bump) =
-            Pubkey::find_program_address(&[STAKE_VAULT_SEED, user_key.as_ref()], ctx.program_id);
-        let vault_seeds = &[STAKE_VAULT_SEED, user_key.as_ref(), &[vault_bump]];
-        let signer_seeds = &[&vault_seeds[..]];
+        // Move the amount from reserved to minted supply
+        cfg.mint_reserved(amount)?;
+
+        let bump = cfg.mint_authority_bump;
+        let seeds = &[MINT_AUTHORITY_SEED, &[bump]];
+        let signer_seeds = &[&seeds[..]];
         let cpi_ctx = CpiContext::new_with_signer(
             ctx.accounts.token_program.to_account_info(),
-            Transfer {
-                from: ctx.accounts.stake_vault.to_account_info(),
-                to: ctx.accounts.user_ata.to_account_info(),
-                authority: ctx.accounts.vault_authority.to_account_info(),
+            MintTo {
+                mint: ctx.accounts.token_mint.to_account_info(),
+                to: ctx.accounts.claimant_ata.to_account_info(),
+                authority: ctx.accounts.mint_authority.to_account_info(),
             },
             signer_seeds,
         );
-        token_2022::transfer(cpi_ctx, amount)?;
-
-        // Reset pending fields
-        stake_acc.pending_withdrawal_amount = 0;
-        stake_acc.withdraw_request_timestamp = 0;
-        stake_acc.last_reward_timestamp = now;
+        token_2022::mint_to(cpi_ctx, amount)?;
 
         msg!(
-            "Claimed stake => user={}, amount_returned={}",
-            user_key,
+            "Airdrop claimed => distributor={}, index={}, claimant={}, amount={}",
+            id,
+            index,
+            claimant,
             amount
         );
         Ok(())
     }
-}
 
-// ------------------------------------------------------------------------------------------------
-//  STATE ACCOUNTS
-// ------------------------------------------------------------------------------------------------
+    /// After expiry, release the unclaimed reservation back under
+    /// `max_supply` and close the distributor. Treasurer only.
+    pub fn clawback(ctx: Context<Clawback>, id: u64) -> Result<()> {
+        let cfg = &mut ctx.accounts.dapp_config;
+        require_role(cfg, &ctx.accounts.owner.key(), Role::Treasurer, &ctx.accounts.role_grant)?;
 
-#[account]
-pub struct DappConfig {
-    pub gatekeeper_network: Pubkey,  // Gatekeeper network ID.
-    pub token_mint: Pubkey,          // Token mint used for distribution.
-    pub mint_authority_bump: u8,     // Bump for mint authority PDA.
-    pub exempt_address: Pubkey,      // Exempt address.
-    pub owner: Pubkey,               // Owner of the dapp.
-    pub initialized: bool,           // Flag to block re-initializing.
+        let distributor = &ctx.accounts.distributor;
+        require!(
+            Clock::get()?.unix_timestamp >= distributor.expires_at,
+            ErrorCode::DistributorNotExpired
+        );
 
-    // Supply control fields
-    pub total_minted: u64,           // Total microtokens minted so far.
-    pub max_supply: u64,             // Maximum microtoken supply.
+        let unclaimed = distributor.total_amount - distributor.claimed_amount;
+        cfg.release_reservation(unclaimed)?;
 
-    // Claim counter
-    pub total_claims: u64,           // Counts how many times `claim` was invoked
-}
+        msg!(
+            "Distributor clawed back => id={}, unclaimed={}",
+            id,
+            unclaimed
+        );
+        Ok(())
+    }
 
-impl DappConfig {
-    // 8 discriminator + 32 + 32 + 1 + 32 + 32 + 1 + 8 + 8 + 8 = 170 bytes
-    pub const LEN: usize = 8 + 32 + 32 + 1 + 32 + 32 + 1 + 8 + 8 + 8;
-}
+    /// Create a vesting schedule for `beneficiary`. `total` is reserved under
+    /// `max_supply` now and minted as it is released. Treasurer only.
+    pub fn create_vesting(
+        ctx: Context<CreateVesting>,
+        beneficiary: Pubkey,
+        total: u64,
+        start: i64,
+        cliff_secs: i64,
+        duration_secs: i64,
+        revocable: bool,
+    ) -> Result<()> {
+        let cfg = &mut ctx.accounts.dapp_config;
+        require_role(cfg, &ctx.accounts.owner.key(), Role::Treasurer, &ctx.accounts.role_grant)?;
+        require!(
+            total > 0 && duration_secs > 0 && (0..=duration_secs).contains(&cliff_secs),
+            ErrorCode::InvalidVestingSchedule
+        );
+        cfg.reserve_supply(total)?;
 
-#[account]
-pub struct MintAuthority {
-    pub bump: u8,
-}
+        let vesting = &mut ctx.accounts.vesting;
+        vesting.beneficiary = beneficiary;
+        vesting.total = total;
+        vesting.released = 0;
+        vesting.start = start;
+        vesting.cliff_secs = cliff_secs;
+        vesting.duration_secs = duration_secs;
+        vesting.revocable = revocable;
+        vesting.revoked = false;
 
-impl MintAuthority {
-    pub const LEN: usize = 8 + 1;
-}
+        msg!(
+            "Vesting created => beneficiary={}, total={}, start={}, cliff={}s, duration={}s, revocable={}",
+            beneficiary,
+            total,
+            start,
+            cliff_secs,
+            duration_secs,
+            revocable
+        );
+        Ok(())
+    }
 
-/// Per-user account tracking faucet claims.
-#[account]
-pub struct UserPda {
-    pub authority: Pubkey,
-    pub last_claimed_timestamp: i64,
-    pub claimed_so_far: u64,
-}
+    /// Mint the vested-but-unreleased amount to the beneficiary.
+    pub fn release_vested(ctx: Context<ReleaseVested>) -> Result<()> {
+        let cfg = &mut ctx.accounts.dapp_config;
+        let vesting = &mut ctx.accounts.vesting;
+        let now = Clock::get()?.unix_timestamp;
 
-impl UserPda {
-    // 8 discriminator + 32 + 8 + 8 = 56 bytes
-    pub const LEN: usize = 8 + 32 + 8 + 8;
-}
+        let amount = vesting.vested_amount(now)?.saturating_sub(vesting.released);
+        require!(amount > 0, ErrorCode::NothingVested);
 
-/// Per-user staking account.
-#[account]
-pub struct StakeAccount {
-    pub authority: Pubkey,             // User who owns this stake
-    pub stake_amount: u64,             // Currently staked balance
-    pub last_reward_timestamp: i64,    // Last time rewards were calculated
-    pub pending_withdrawal_amount: u64,// Amount locked for withdrawal
-    pub withdraw_request_timestamp: i64,// When unstake was requested
-}
+        // Move the amount from reserved to minted supply
+        cfg.mint_reserved(amount)?;
+        vesting.released += amount;
 
-impl StakeAccount {
-    // 8 discriminator + 32 + 8 + 8 + 8 + 8 = 72 bytes
-    pub const LEN: usize = 8 + 32 + 8 + 8 + 8 + 8;
-}
+        let bump = cfg.mint_authority_bump;
+        let seeds = &[MINT_AUTHORITY_SEED, &[bump]];
+        let signer_seeds = &[&seeds[..]];
+        let cpi_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            MintTo {
+                mint: ctx.accounts.token_mint.to_account_info(),
+                to: ctx.accounts.beneficiary_ata.to_account_info(),
+                authority: ctx.accounts.mint_authority.to_account_info(),
+            },
+            signer_seeds,
+        );
+        token_2022::mint_to(cpi_ctx, amount)?;
 
-// ------------------------------------------------------------------------------------------------
-//  ACCOUNTS FOR INSTRUCTIONS
-// ------------------------------------------------------------------------------------------------
+        msg!(
+            "Vesting released => beneficiary={}, amount={}, released={}/{}",
+            vesting.beneficiary,
+            amount,
+            vesting.released,
+            vesting.total
+        );
+        Ok(())
+    }
 
-#[derive(Accounts)]
-pub struct InitializeDapp<'info> {
-    #[account(
-        init,
-        payer = payer,
-        space = DappConfig::LEN,
-        seeds = [b"dapp_config"],
-        bump
-    )]
-    pub dapp_config: Account<'info, DappConfig>,
+    /// Stop a revocable schedule. What has vested so far stays releasable;
+    /// the unvested remainder is never minted and its reservation is freed.
+    /// Treasurer only.
+    pub fn revoke_vesting(ctx: Context<RevokeVesting>) -> Result<()> {
+        let cfg = &mut ctx.accounts.dapp_config;
+        require_role(cfg, &ctx.accounts.owner.key(), Role::Treasurer, &ctx.accounts.role_grant)?;
 
-    #[account(mut)]
-    pub payer: Signer<'info>,
+        let vesting = &mut ctx.accounts.vesting;
+        require!(vesting.revocable && !vesting.revoked, ErrorCode::VestingNotRevocable);
 
-    pub system_program: Program<'info, System>,
-    pub rent: Sysvar<'info, Rent>,
-}
+        let vested = vesting.vested_amount(Clock::get()?.unix_timestamp)?;
+        let forfeited = vesting.total - vested;
+        cfg.release_reservation(forfeited)?;
+        vesting.total = vested;
+        vesting.revoked = true;
+
+        msg!(
+            "Vesting revoked => beneficiary={}, vested={}, forfeited={}",
+            vesting.beneficiary,
+            vested,
+            forfeited
+        );
+        Ok(())
+    }
+
+    /// Close the caller's UserPda and StakeAccount and refund their rent.
+    /// Requires no stake, no pending withdrawal and no open positions.
+    ///
+    /// Stakes live in the shared pool vault, so there is no per-user vault
+    /// left to close here.
+    pub fn deregister_user(ctx: Context<DeregisterUser>) -> Result<()> {
+        require_empty_stake(&ctx.accounts.stake_account)?;
+
+        msg!("Deregistered user => authority={}", ctx.accounts.user.key());
+        Ok(())
+    }
+
+    /// Start the notice period for a user with no activity in
+    /// INACTIVITY_PERIOD_SECONDS. Moderator only; a claim clears the flag.
+    pub fn flag_inactive_user(ctx: Context<FlagInactiveUser>) -> Result<()> {
+        let cfg = &ctx.accounts.dapp_config;
+        require_role(cfg, &ctx.accounts.owner.key(), Role::Moderator, &ctx.accounts.role_grant)?;
+
+        let now = Clock::get()?.unix_timestamp;
+        let user_pda = &mut ctx.accounts.user_pda;
+        let last_active = user_pda
+            .last_claimed_timestamp
+            .max(ctx.accounts.stake_account.last_reward_timestamp);
+        require!(
+            now >= last_active + INACTIVITY_PERIOD_SECONDS,
+            ErrorCode::UserStillActive
+        );
+        require_empty_stake(&ctx.accounts.stake_account)?;
+
+        user_pda.inactive_flagged_at = now;
+        emit!(InactivityNotice {
+            user: user_pda.authority,
+            sweepable_at: now + DEREGISTER_NOTICE_SECONDS,
+        });
+
+        msg!(
+            "Inactive user flagged => user={}, sweepable_at={}",
+            user_pda.authority,
+            now + DEREGISTER_NOTICE_SECONDS
+        );
+        Ok(())
+    }
+
+    /// After the notice period, close a flagged user's accounts. Rent goes
+    /// back to the user's wallet, not the owner. Moderator only.
+    pub fn sweep_inactive_user(ctx: Context<SweepInactiveUser>) -> Result<()> {
+        let cfg = &ctx.accounts.dapp_config;
+        require_role(cfg, &ctx.accounts.owner.key(), Role::Moderator, &ctx.accounts.role_grant)?;
+
+        let user_pda = &ctx.accounts.user_pda;
+        let now = Clock::get()?.unix_timestamp;
+        require!(user_pda.inactive_flagged_at > 0, ErrorCode::UserStillActive);
+        require!(
+            now >= user_pda.inactive_flagged_at + DEREGISTER_NOTICE_SECONDS,
+            ErrorCode::NoticePeriodNotMet
+        );
+        require_empty_stake(&ctx.accounts.stake_account)?;
+
+        msg!("Swept inactive user => authority={}", user_pda.authority);
+        Ok(())
+    }
+
+    /// Change the exempt address. Admin only; once the timelock is enforced
+    /// this must go through `queue_admin_op`.
+    pub fn set_exempt(ctx: Context<SetExempt>, new_exempt: Pubkey) -> Result<()> {
+        let cfg = &mut ctx.accounts.dapp_config;
+        require_role(cfg, &ctx.accounts.admin.key(), Role::Admin, &ctx.accounts.role_grant)?;
+        require!(!cfg.timelock_enforced, ErrorCode::TimelockRequired);
+
+        cfg.exempt_address = new_exempt;
+        msg!("Exempt address updated => new_exempt={}", new_exempt);
+        Ok(())
+    }
+
+    /// Configure streak tier `index`. Tiers must stay sorted by `min_days`
+    /// and tier 0 always applies from day 0. Param manager only.
+    pub fn set_streak_tier(ctx: Context<UpdateFaucet>, index: u8, tier: StreakTier) -> Result<()> {
+        let cfg = &mut ctx.accounts.dapp_config;
+        require_role(cfg, &ctx.accounts.owner.key(), Role::ParamManager, &ctx.accounts.role_grant)?;
+        require!(!cfg.timelock_enforced, ErrorCode::TimelockRequired);
+
+        cfg.set_streak_tier(index, tier)
+    }
+
+    /// Configure stake boost tier `index`. Tiers must stay sorted by
+    /// `min_stake` and tier 0 always applies from zero stake. Param manager only.
+    pub fn set_stake_boost_tier(
+        ctx: Context<UpdateFaucet>,
+        index: u8,
+        tier: StakeBoostTier,
+    ) -> Result<()> {
+        let cfg = &mut ctx.accounts.dapp_config;
+        require_role(cfg, &ctx.accounts.owner.key(), Role::ParamManager, &ctx.accounts.role_grant)?;
+        require!(!cfg.timelock_enforced, ErrorCode::TimelockRequired);
+
+        cfg.set_stake_boost_tier(index, tier)
+    }
+
+    /// Burn `amount` from the caller's token account.
+    pub fn burn(ctx: Context<BurnTokens>, amount: u64) -> Result<()> {
+        require!(amount > 0, ErrorCode::NothingToBurn);
+
+        let cpi_ctx = CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            Burn {
+                mint: ctx.accounts.token_mint.to_account_info(),
+                from: ctx.accounts.authority_ata.to_account_info(),
+                authority: ctx.accounts.authority.to_account_info(),
+            },
+        );
+        token_2022::burn(cpi_ctx, amount)?;
+
+        let cfg = &mut ctx.accounts.dapp_config;
+        cfg.record_burn(amount)?;
+
+        msg!(
+            "Burned => from={}, amount={}, total_burned={}",
+            ctx.accounts.authority.key(),
+            amount,
+            cfg.total_burned
+        );
+        Ok(())
+    }
+
+    /// Burn tokens the treasury bought back. Signed by the treasury wallet.
+    pub fn buyback_burn(ctx: Context<BurnTokens>, amount: u64) -> Result<()> {
+        require!(
+            ctx.accounts.authority.key() == ctx.accounts.dapp_config.treasury,
+            ErrorCode::NotAuthorized
+        );
+        require!(amount > 0, ErrorCode::NothingToBurn);
+
+        let cpi_ctx = CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            Burn {
+                mint: ctx.accounts.token_mint.to_account_info(),
+                from: ctx.accounts.authority_ata.to_account_info(),
+                authority: ctx.accounts.authority.to_account_info(),
+            },
+        );
+        token_2022::burn(cpi_ctx, amount)?;
+
+        let cfg = &mut ctx.accounts.dapp_config;
+        cfg.record_burn(amount)?;
+
+        msg!(
+            "Buyback burned => amount={}, total_burned={}",
+            amount,
+            cfg.total_burned
+        );
+        Ok(())
+    }
+
+    /// Net supply, returned to the caller for explorers and simulations.
+    /// This is the mint's own supply, so it also covers tokens this config
+    /// never counted, such as the commission pre-mint of
+    /// `initialize_dapp_and_mint`; the log shows that untracked part.
+    pub fn net_supply(ctx: Context<NetSupply>) -> Result<u64> {
+        let cfg = &ctx.accounts.dapp_config;
+        let net = ctx.accounts.token_mint.supply;
+        let tracked = cfg.total_minted.saturating_sub(cfg.total_burned);
+        msg!(
+            "Net supply => minted={}, burned={}, untracked={}, net={}",
+            cfg.total_minted,
+            cfg.total_burned,
+            net as i128 - tracked as i128,
+            net
+        );
+        Ok(net)
+    }
+
+    /// When enabled, burned tokens no longer count against `max_supply`.
+    /// It can only be disabled while everything minted and reserved still
+    /// fits under the cap. Param manager only.
+    pub fn set_burn_reopens_supply(ctx: Context<UpdateFaucet>, enabled: bool) -> Result<()> {
+        let cfg = &mut ctx.accounts.dapp_config;
+        require_role(cfg, &ctx.accounts.owner.key(), Role::ParamManager, &ctx.accounts.role_grant)?;
+        require!(!cfg.timelock_enforced, ErrorCode::TimelockRequired);
+
+        cfg.set_burn_reopens(enabled)?;
+        msg!("Burn reopens supply => {}", enabled);
+        Ok(())
+    }
+
+    /// Grant `role` to `grantee`. Admin only; once the timelock is enforced
+    /// this must go through `queue_admin_op`.
+    pub fn grant_role(ctx: Context<GrantRole>, role: Role, grantee: Pubkey) -> Result<()> {
+        let cfg = &ctx.accounts.dapp_config;
+        let admin = ctx.accounts.admin.key();
+        require_role(cfg, &admin, Role::Admin, &ctx.accounts.role_grant)?;
+        require!(!cfg.timelock_enforced, ErrorCode::TimelockRequired);
+
+        let grant = &mut ctx.accounts.new_grant;
+        grant.role = role;
+        grant.grantee = grantee;
+        grant.granted_by = admin;
+        grant.granted_at = Clock::get()?.unix_timestamp;
+
+        emit!(RoleChanged { role, grantee, admin, granted: true });
+        msg!("Role granted => role={:?}, grantee={}", role, grantee);
+        Ok(())
+    }
+
+    /// Revoke `role` from `grantee`, refunding the grant's rent to the caller.
+    /// Admin only; once the timelock is enforced this must go through
+    /// `queue_admin_op`, which gives a guardian the chance to cancel.
+    pub fn revoke_role(ctx: Context<RevokeRole>, role: Role, grantee: Pubkey) -> Result<()> {
+        let cfg = &ctx.accounts.dapp_config;
+        let admin = ctx.accounts.admin.key();
+        require_role(cfg, &admin, Role::Admin, &ctx.accounts.role_grant)?;
+        require!(!cfg.timelock_enforced, ErrorCode::TimelockRequired);
+
+        emit!(RoleChanged { role, grantee, admin, granted: false });
+        msg!("Role revoked => role={:?}, grantee={}", role, grantee);
+        Ok(())
+    }
+
+    /// Halt or resume all new issuance (faucet, airdrops, vesting). Pauser
+    /// only. Not timelocked, so an incident can be stopped right away.
+    pub fn set_paused(ctx: Context<UpdateFaucet>, paused: bool) -> Result<()> {
+        let cfg = &mut ctx.accounts.dapp_config;
+        require_role(cfg, &ctx.accounts.owner.key(), Role::Pauser, &ctx.accounts.role_grant)?;
+
+        cfg.paused = paused;
+        msg!("Issuance paused => {}", paused);
+        Ok(())
+    }
+
+    /// Change the treasury wallet. Admin only.
+    pub fn set_treasury(ctx: Context<SetTreasury>, new_treasury: Pubkey) -> Result<()> {
+        let cfg = &mut ctx.accounts.dapp_config;
+        require_role(cfg, &ctx.accounts.owner.key(), Role::Admin, &ctx.accounts.role_grant)?;
+        require!(!cfg.timelock_enforced, ErrorCode::TimelockRequired);
+
+        cfg.treasury = new_treasury;
+        msg!("Treasury updated => new_treasury={}", new_treasury);
+        Ok(())
+    }
+
+    /// Create the global stake pool and its vault. Admin only.
+    pub fn initialize_stake_pool(ctx: Context<InitializeStakePool>, reward_rate: u64) -> Result<()> {
+        let cfg = &ctx.accounts.dapp_config;
+        require_role(cfg, &ctx.accounts.owner.key(), Role::Admin, &ctx.accounts.role_grant)?;
+
+        let pool = &mut ctx.accounts.stake_pool;
+        pool.bump = ctx.bumps.stake_pool;
+        pool.vault = ctx.accounts.pool_vault.key();
+        pool.total_staked = 0;
+        pool.reward_rate = reward_rate;
+        pool.reward_per_token_stored = 0;
+        pool.last_update_timestamp = Clock::get()?.unix_timestamp;
+        pool.reward_budget = 0;
+        pool.total_weight = 0;
+        pool.lock_tiers = [
+            LockTier { duration_secs: 0, boost_bps: 10_000 },
+            LockTier { duration_secs: 30 * SECONDS_PER_DAY, boost_bps: 11_000 },
+            LockTier { duration_secs: 90 * SECONDS_PER_DAY, boost_bps: 12_500 },
+            LockTier { duration_secs: 180 * SECONDS_PER_DAY, boost_bps: 15_000 },
+            LockTier { duration_secs: 365 * SECONDS_PER_DAY, boost_bps: 20_000 },
+        ];
+        pool.early_exit_penalty_bps = 0; // early exit disallowed
+        pool.st_mint = Pubkey::default();   // set by initialize_liquid_staking
+        pool.liquid_principal = 0;
+        pool.liquid_reward_debt = 0;
+        pool.buffer_vault = Pubkey::default(); // set by initialize_buffer
+        pool.exit_fee_bps = 0;
+        pool.buffer_owed = 0;
+
+        msg!(
+            "Stake pool created => vault={}, reward_rate={}/s",
+            pool.vault,
+            reward_rate
+        );
+        Ok(())
+    }
+
+    /// Change the per-second emission rate. Accrual up to now uses the old rate.
+    pub fn set_reward_rate(ctx: Context<UpdateStakePool>, reward_rate: u64) -> Result<()> {
+        let cfg = &mut ctx.accounts.dapp_config;
+        require_role(cfg, &ctx.accounts.owner.key(), Role::ParamManager, &ctx.accounts.role_grant)?;
+        require!(!cfg.timelock_enforced, ErrorCode::TimelockRequired);
+
+        ParamChange::SetRewardRate(reward_rate).apply(cfg, &mut ctx.accounts.stake_pool)
+    }
+
+    /// Configure lock tier `index` (1..MAX_LOCK_TIERS). Existing stakes keep
+    /// the boost and lock end they were created with.
+    pub fn set_lock_tier(ctx: Context<UpdateStakePool>, index: u8, tier: LockTier) -> Result<()> {
+        let cfg = &mut ctx.accounts.dapp_config;
+        require_role(cfg, &ctx.accounts.owner.key(), Role::ParamManager, &ctx.accounts.role_grant)?;
+        require!(!cfg.timelock_enforced, ErrorCode::TimelockRequired);
+
+        ParamChange::SetLockTier { index, tier }.apply(cfg, &mut ctx.accounts.stake_pool)
+    }
+
+    /// Penalty charged on unstaking before `lock_end`; 0 disallows early exit.
+    pub fn set_early_exit_penalty(ctx: Context<UpdateStakePool>, penalty_bps: u16) -> Result<()> {
+        let cfg = &mut ctx.accounts.dapp_config;
+        require_role(cfg, &ctx.accounts.owner.key(), Role::ParamManager, &ctx.accounts.role_grant)?;
+        require!(!cfg.timelock_enforced, ErrorCode::TimelockRequired);
+
+        ParamChange::SetEarlyExitPenalty(penalty_bps).apply(cfg, &mut ctx.accounts.stake_pool)
+    }
+
+    /// Top up the reward budget. Anyone may fund; tokens go to the pool vault.
+    pub fn fund_rewards(ctx: Context<FundRewards>, amount: u64) -> Result<()> {
+        let cpi_ctx = CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            TransferChecked {
+                from: ctx.accounts.funder_ata.to_account_info(),
+                mint: ctx.accounts.token_mint.to_account_info(),
+                to: ctx.accounts.pool_vault.to_account_info(),
+                authority: ctx.accounts.funder.to_account_info(),
+            },
+        );
+        token_2022::transfer_checked(cpi_ctx, amount, ctx.accounts.token_mint.decimals)?;
+
+        let pool = &mut ctx.accounts.stake_pool;
+        pool.update(Clock::get()?.unix_timestamp)?;
+        pool.reward_budget = pool
+            .reward_budget
+            .checked_add(amount)
+            .ok_or(ErrorCode::ArithmeticError)?;
+
+        msg!(
+            "Rewards funded => amount={}, budget={}",
+            amount,
+            pool.reward_budget
+        );
+        Ok(())
+    }
+
+    /// Stake a given amount of tokens. Must be ≥ MIN_STAKE_LAMPORTS.
+    ///
+    /// `lock_tier` > 0 locks the whole stake until `now + duration` for a
+    /// boosted share of rewards. A lower tier than the active lock simply
+    /// tops up the existing lock. Registers first-time users.
+    ///
+    /// With `liquid` set the deposit goes to the liquid sub-pool instead and
+    /// the user receives stCAL at the current exchange rate (liquid_principal
+    /// / stCAL supply); liquid stake cannot be locked.
+    pub fn stake(ctx: Context<Stake>, amount: u64, lock_tier: u8, liquid: bool) -> Result<()> {
+        let stake_acc: &mut StakeAccount = &mut ctx.accounts.stake_account;
+        let pool = &mut ctx.accounts.stake_pool;
+        let user_key = ctx.accounts.user.key();
+
+        if ctx.accounts.user_pda.authority == Pubkey::default() {
+            let cfg = &ctx.accounts.dapp_config;
+            verify_gateway(cfg, &ctx.accounts.gateway_token, &user_key, "stake")?;
+            register_on_first_use(
+                &mut ctx.accounts.user_pda,
+                stake_acc,
+                user_key,
+                Clock::get()?.unix_timestamp,
+            );
+            msg!("Registered user on first stake => authority={}", user_key);
+        }
+
+        // Must stake at least the minimum
+        require!(amount >= MIN_STAKE_LAMPORTS, ErrorCode::StakeTooSmall);
+        require!((lock_tier as usize) < MAX_LOCK_TIERS, ErrorCode::InvalidLockTier);
+        require!(!liquid || lock_tier == 0, ErrorCode::InvalidLockTier);
+
+        // Transfer tokens from user ATA to the pool vault
+        let cpi_ctx = CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            TransferChecked {
+                from: ctx.accounts.user_ata.to_account_info(),
+                mint: ctx.accounts.token_mint.to_account_info(),
+                to: ctx.accounts.pool_vault.to_account_info(),
+                authority: ctx.accounts.user.to_account_info(),
+            },
+        );
+        token_2022::transfer_checked(cpi_ctx, amount, ctx.accounts.token_mint.decimals)?;
+
+        if liquid {
+            let (st_mint, user_st_ata) = match (&ctx.accounts.st_mint, &ctx.accounts.user_st_ata) {
+                (Some(mint), Some(ata)) => (mint, ata),
+                _ => return err!(ErrorCode::MissingLiquidAccounts),
+            };
+            pool.compound_liquid(Clock::get()?.unix_timestamp)?;
+            let minted = pool.st_for_deposit(amount, st_mint.supply)?;
+            require!(minted > 0, ErrorCode::StakeTooSmall);
+            pool.adjust_liquid(amount, 0)?;
+
+            let pool_seeds = &[STAKE_POOL_SEED, &[pool.bump]];
+            let signer_seeds = &[&pool_seeds[..]];
+            let cpi_ctx = CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                MintTo {
+                    mint: st_mint.to_account_info(),
+                    to: user_st_ata.to_account_info(),
+                    authority: pool.to_account_info(),
+                },
+                signer_seeds,
+            );
+            token_2022::mint_to(cpi_ctx, minted)?;
+
+            msg!(
+                "Liquid stake => user={}, amount={}, st_minted={}",
+                user_key,
+                amount,
+                minted
+            );
+            return Ok(());
+        }
+
+        credit_stake(pool, stake_acc, amount, lock_tier, Clock::get()?.unix_timestamp)?;
+
+        msg!(
+            "Stake => user={}, amount_staked={}, new_total={}",
+            user_key,
+            amount,
+            stake_acc.stake_amount
+        );
+        Ok(())
+    }
+
+    /// Stake `amount` from the funder's ATA for the StakeAccount's owner. The
+    /// deposit opens a new unlocked position for the beneficiary, so their
+    /// existing stake, lock and withdraw authority are never touched; the
+    /// funder pays the position's rent.
+    pub fn stake_for(ctx: Context<StakeFor>, amount: u64) -> Result<()> {
+        require!(amount >= MIN_STAKE_LAMPORTS, ErrorCode::StakeTooSmall);
+
+        let cpi_ctx = CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            TransferChecked {
+                from: ctx.accounts.funder_ata.to_account_info(),
+                mint: ctx.accounts.token_mint.to_account_info(),
+                to: ctx.accounts.pool_vault.to_account_info(),
+                authority: ctx.accounts.funder.to_account_info(),
+            },
+        );
+        token_2022::transfer_checked(cpi_ctx, amount, ctx.accounts.token_mint.decimals)?;
+
+        let position: &mut StakePosition = &mut ctx.accounts.position;
+        fill_position(
+            &mut ctx.accounts.stake_pool,
+            &mut ctx.accounts.stake_account,
+            position,
+            amount,
+            0,
+            Clock::get()?.unix_timestamp,
+        )?;
+
+        msg!(
+            "Stake for => funder={}, beneficiary={}, index={}, amount={}",
+            ctx.accounts.funder.key(),
+            position.authority,
+            position.index,
+            amount
+        );
+        Ok(())
+    }
+
+    /// Set (or clear with `Pubkey::default()`) the key allowed to request
+    /// unstake on the caller's behalf. Withdrawals still go to the owner.
+    pub fn set_withdraw_authority(
+        ctx: Context<SetWithdrawAuthority>,
+        withdraw_authority: Pubkey,
+    ) -> Result<()> {
+        ctx.accounts.stake_account.withdraw_authority = withdraw_authority;
+        msg!(
+            "Withdraw authority => user={}, delegate={}",
+            ctx.accounts.user.key(),
+            withdraw_authority
+        );
+        Ok(())
+    }
+
+    /// Pay accrued rewards to the user's ATA without touching the stake.
+    pub fn harvest_rewards(ctx: Context<HarvestRewards>) -> Result<()> {
+        let stake_acc = &mut ctx.accounts.stake_account;
+        let pool = &mut ctx.accounts.stake_pool;
+        let user_key = ctx.accounts.user.key();
+        let now = Clock::get()?.unix_timestamp;
+
+        pool.update(now)?;
+        let reward = pool.pending_reward(stake_acc.weight()?, stake_acc.reward_debt)?;
+        require!(reward > 0, ErrorCode::NothingToClaim);
+
+        let pool_seeds = &[STAKE_POOL_SEED, &[pool.bump]];
+        let signer_seeds = &[&pool_seeds[..]];
+        let cpi_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            TransferChecked {
+                from: ctx.accounts.pool_vault.to_account_info(),
+                mint: ctx.accounts.token_mint.to_account_info(),
+                to: ctx.accounts.user_ata.to_account_info(),
+                authority: pool.to_account_info(),
+            },
+            signer_seeds,
+        );
+        token_2022::transfer_checked(cpi_ctx, reward, ctx.accounts.token_mint.decimals)?;
+
+        stake_acc.reward_debt = pool.reward_debt_for(stake_acc.weight()?)?;
+        stake_acc.last_reward_timestamp = now;
+
+        msg!("Harvest => user={}, reward={}", user_key, reward);
+        Ok(())
+    }
+
+    /// Restake accrued rewards into `stake_amount`.
+    pub fn compound(ctx: Context<Compound>) -> Result<()> {
+        let stake_acc: &mut StakeAccount = &mut ctx.accounts.stake_account;
+        let pool = &mut ctx.accounts.stake_pool;
+        let user_key = ctx.accounts.user.key();
+        let now = Clock::get()?.unix_timestamp;
+
+        pool.update(now)?;
+        let old_weight = stake_acc.weight()?;
+        let reward = compound_rewards(pool, stake_acc)?;
+        require!(reward > 0, ErrorCode::NothingToClaim);
+        reweight(pool, stake_acc, old_weight)?;
+        stake_acc.last_reward_timestamp = now;
+
+        msg!(
+            "Compound => user={}, reward={}, new_total={}",
+            user_key,
+            reward,
+            stake_acc.stake_amount
+        );
+        Ok(())
+    }
+
+    /// Drop the boost of a main stake whose lock has ended, so it stops
+    /// earning (and voting with) boosted weight. Permissionless; the owner's
+    /// accrued rewards are compounded.
+    pub fn poke(ctx: Context<Poke>) -> Result<()> {
+        let stake_acc: &mut StakeAccount = &mut ctx.accounts.stake_account;
+        let now = Clock::get()?.unix_timestamp;
+        require!(
+            expire_lock(&mut ctx.accounts.stake_pool, stake_acc, now)?,
+            ErrorCode::LockStillActive
+        );
+        stake_acc.last_reward_timestamp = now;
+
+        msg!(
+            "Poke => user={}, weight={}",
+            stake_acc.authority,
+            stake_acc.stake_amount
+        );
+        Ok(())
+    }
+
+    /// `poke` for a single stake position.
+    pub fn poke_position(ctx: Context<PokePosition>, index: u64) -> Result<()> {
+        let position: &mut StakePosition = &mut ctx.accounts.position;
+        let now = Clock::get()?.unix_timestamp;
+        let (old_amount, old_weight) = (position.amount, position.weight()?);
+        require!(
+            expire_lock(&mut ctx.accounts.stake_pool, position, now)?,
+            ErrorCode::LockStillActive
+        );
+        position.last_reward_timestamp = now;
+        ctx.accounts.stake_account.track_position(
+            old_amount,
+            old_weight,
+            position.amount,
+            position.weight()?,
+        )?;
+
+        msg!(
+            "Poke position => user={}, index={}, weight={}",
+            position.authority,
+            index,
+            position.amount
+        );
+        Ok(())
+    }
+
+    /// Open a new independent position at the next per-user index.
+    pub fn open_position(ctx: Context<OpenPosition>, amount: u64, lock_tier: u8) -> Result<()> {
+        require!(amount >= MIN_STAKE_LAMPORTS, ErrorCode::StakeTooSmall);
+        require!((lock_tier as usize) < MAX_LOCK_TIERS, ErrorCode::InvalidLockTier);
+
+        let cpi_ctx = CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            TransferChecked {
+                from: ctx.accounts.user_ata.to_account_info(),
+                mint: ctx.accounts.token_mint.to_account_info(),
+                to: ctx.accounts.pool_vault.to_account_info(),
+                authority: ctx.accounts.user.to_account_info(),
+            },
+        );
+        token_2022::transfer_checked(cpi_ctx, amount, ctx.accounts.token_mint.decimals)?;
+
+        let position: &mut StakePosition = &mut ctx.accounts.position;
+        fill_position(
+            &mut ctx.accounts.stake_pool,
+            &mut ctx.accounts.stake_account,
+            position,
+            amount,
+            lock_tier,
+            Clock::get()?.unix_timestamp,
+        )?;
+
+        msg!(
+            "Position opened => user={}, index={}, amount={}, lock_end={}",
+            position.authority,
+            position.index,
+            amount,
+            position.lock_end
+        );
+        Ok(())
+    }
+
+    /// Add tokens to an existing position; its lock is unchanged.
+    pub fn top_up_position(ctx: Context<TopUpPosition>, index: u64, amount: u64) -> Result<()> {
+        require!(amount > 0, ErrorCode::StakeTooSmall);
+
+        let cpi_ctx = CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            TransferChecked {
+                from: ctx.accounts.user_ata.to_account_info(),
+                mint: ctx.accounts.token_mint.to_account_info(),
+                to: ctx.accounts.pool_vault.to_account_info(),
+                authority: ctx.accounts.user.to_account_info(),
+            },
+        );
+        token_2022::transfer_checked(cpi_ctx, amount, ctx.accounts.token_mint.decimals)?;
+
+        let stake_acc = &mut ctx.accounts.stake_account;
+        let pool = &mut ctx.accounts.stake_pool;
+        let position: &mut StakePosition = &mut ctx.accounts.position;
+        let now = Clock::get()?.unix_timestamp;
+        pool.update(now)?;
+
+        let (old_amount, old_weight) = (position.amount, position.weight()?);
+        compound_rewards(pool, position)?;
+        if now >= position.lock_end {
+            position.clear_lock();
+        }
+        position.amount = position
+            .amount
+            .checked_add(amount)
+            .ok_or(ErrorCode::ArithmeticError)?;
+        pool.total_staked = pool
+            .total_staked
+            .checked_add(amount)
+            .ok_or(ErrorCode::ArithmeticError)?;
+        reweight(pool, position, old_weight)?;
+        position.last_reward_timestamp = now;
+        stake_acc.track_position(old_amount, old_weight, position.amount, position.weight()?)?;
+
+        msg!(
+            "Position topped up => index={}, amount={}, new_total={}",
+            index,
+            amount,
+            position.amount
+        );
+        Ok(())
+    }
+
+    /// Fold position `source` into `target`. The merged position keeps the
+    /// later lock end and the smaller boost.
+    pub fn merge_positions(ctx: Context<MergePositions>, source: u64, target: u64) -> Result<()> {
+        require!(source != target, ErrorCode::InvalidPosition);
+
+        let stake_acc = &mut ctx.accounts.stake_account;
+        let pool = &mut ctx.accounts.stake_pool;
+        let src: &mut StakePosition = &mut ctx.accounts.source_position;
+        let dst: &mut StakePosition = &mut ctx.accounts.target_position;
+        let now = Clock::get()?.unix_timestamp;
+        pool.update(now)?;
+
+        let (src_amount, src_weight) = (src.amount, src.weight()?);
+        let (dst_amount, dst_weight) = (dst.amount, dst.weight()?);
+        compound_rewards(pool, src)?;
+        compound_rewards(pool, dst)?;
+
+        dst.amount = dst
+            .amount
+            .checked_add(src.amount)
+            .ok_or(ErrorCode::ArithmeticError)?;
+        dst.lock_end = std::cmp::max(dst.lock_end, src.lock_end);
+        if src.boost_bps < dst.boost_bps {
+            dst.boost_bps = src.boost_bps;
+            dst.lock_tier = src.lock_tier;
+        }
+        dst.start_timestamp = std::cmp::min(dst.start_timestamp, src.start_timestamp);
+        reweight(pool, dst, dst_weight + src_weight)?;
+        dst.last_reward_timestamp = now;
+
+        stake_acc.track_position(src_amount, src_weight, 0, 0)?;
+        stake_acc.track_position(dst_amount, dst_weight, dst.amount, dst.weight()?)?;
+        stake_acc.open_positions -= 1;
+
+        msg!(
+            "Positions merged => {} into {}, new_total={}",
+            source,
+            target,
+            dst.amount
+        );
+        Ok(())
+    }
+
+    /// Move `amount` out of position `index` into a new position with the
+    /// same lock. Both halves must meet the minimum stake.
+    pub fn split_position(ctx: Context<SplitPosition>, index: u64, amount: u64) -> Result<()> {
+        let stake_acc = &mut ctx.accounts.stake_account;
+        let pool = &mut ctx.accounts.stake_pool;
+        let src: &mut StakePosition = &mut ctx.accounts.position;
+        let new: &mut StakePosition = &mut ctx.accounts.new_position;
+        let now = Clock::get()?.unix_timestamp;
+        pool.update(now)?;
+
+        let (src_amount, src_weight) = (src.amount, src.weight()?);
+        compound_rewards(pool, src)?;
+        require!(amount >= MIN_STAKE_LAMPORTS, ErrorCode::StakeTooSmall);
+        require!(
+            src.amount.saturating_sub(amount) >= MIN_STAKE_LAMPORTS,
+            ErrorCode::StakeTooSmall
+        );
+
+        src.amount -= amount;
+        new.authority = src.authority;
+        new.index = stake_acc.position_count;
+        new.amount = amount;
+        new.start_timestamp = src.start_timestamp;
+        new.last_reward_timestamp = now;
+        new.lock_tier = src.lock_tier;
+        new.lock_end = src.lock_end;
+        new.boost_bps = src.boost_bps;
+        new.reward_debt = 0;
+        reweight(pool, src, src_weight)?;
+        reweight(pool, new, 0)?;
+        src.last_reward_timestamp = now;
+
+        stake_acc.position_count += 1;
+        stake_acc.open_positions += 1;
+        stake_acc.track_position(src_amount, src_weight, src.amount, src.weight()?)?;
+        stake_acc.track_position(0, 0, new.amount, new.weight()?)?;
+
+        msg!(
+            "Position split => {} into {} ({} moved)",
+            index,
+            new.index,
+            amount
+        );
+        Ok(())
+    }
+
+    /// Close position `index`. Principal plus rewards (less any early-exit
+    /// penalty) join the StakeAccount withdrawal queue for `claim_stake`.
+    pub fn close_position(ctx: Context<ClosePosition>, index: u64) -> Result<()> {
+        let stake_acc = &mut ctx.accounts.stake_account;
+        let pool = &mut ctx.accounts.stake_pool;
+        let position = &ctx.accounts.position;
+        let now = Clock::get()?.unix_timestamp;
+
+        require!(
+            stake_acc.pending_withdrawal_amount == 0,
+            ErrorCode::UnstakeAlreadyRequested
+        );
+        require!(now >= stake_acc.vote_locked_until, ErrorCode::VoteLocked);
+
+        pool.update(now)?;
+        let weight = position.weight()?;
+        let reward = pool.pending_reward(weight, position.reward_debt)?;
+        let penalty = early_exit_penalty(pool, position.amount, position.lock_end, now)?;
+        let total_withdraw = position
+            .amount
+            .checked_add(reward)
+            .and_then(|v| v.checked_sub(penalty))
+            .ok_or(ErrorCode::ArithmeticError)?;
+
+        pool.total_staked = pool
+            .total_staked
+            .checked_sub(position.amount)
+            .ok_or(ErrorCode::ArithmeticError)?;
+        pool.total_weight = pool
+            .total_weight
+            .checked_sub(weight)
+            .ok_or(ErrorCode::ArithmeticError)?;
+        pool.reward_budget = pool
+            .reward_budget
+            .checked_add(penalty)
+            .ok_or(ErrorCode::ArithmeticError)?;
+
+        stake_acc.track_position(position.amount, weight, 0, 0)?;
+        stake_acc.open_positions -= 1;
+        stake_acc.pending_withdrawal_amount = total_withdraw;
+        stake_acc.withdraw_request_timestamp = now;
+
+        msg!(
+            "Position closed => index={}, amount_pending={}, penalty={}, can_claim_at={}",
+            index,
+            total_withdraw,
+            penalty,
+            now + UNSTAKE_DELAY_SECONDS
+        );
+        Ok(())
+    }
+
+    /// Request to unstake all tokens. After 2 days, user can call `claim_stake`.
+    ///
+    /// Before `lock_end` this fails unless the pool has an early-exit penalty,
+    /// in which case the penalty is returned to the reward budget.
+    ///
+    /// `st_amount` instead burns that much of the owner's stCAL; the
+    /// underlying amount joins the same withdrawal queue.
+    pub fn request_unstake(ctx: Context<RequestUnstake>, st_amount: Option<u64>) -> Result<()> {
+        let stake_acc = &mut ctx.accounts.stake_account;
+        let pool = &mut ctx.accounts.stake_pool;
+        let user_key = stake_acc.authority;
+        let now = Clock::get()?.unix_timestamp;
+
+        if let Some(st_amount) = st_amount {
+            let (st_mint, user_st_ata, token_program) = match (
+                &ctx.accounts.st_mint,
+                &ctx.accounts.user_st_ata,
+                &ctx.accounts.token_program,
+            ) {
+                (Some(mint), Some(ata), Some(program)) => (mint, ata, program),
+                _ => return err!(ErrorCode::MissingLiquidAccounts),
+            };
+            require!(ctx.accounts.user.key() == user_key, ErrorCode::NotAuthorized);
+            require!(st_amount > 0, ErrorCode::NothingToUnstake);
+            require!(
+                stake_acc.pending_withdrawal_amount == 0,
+                ErrorCode::UnstakeAlreadyRequested
+            );
+
+            pool.compound_liquid(now)?;
+            let underlying = pool.underlying_for_st(st_amount, st_mint.supply)?;
+            pool.adjust_liquid(0, underlying)?;
+
+            let cpi_ctx = CpiContext::new(
+                token_program.to_account_info(),
+                Burn {
+                    mint: st_mint.to_account_info(),
+                    from: user_st_ata.to_account_info(),
+                    authority: ctx.accounts.user.to_account_info(),
+                },
+            );
+            token_2022::burn(cpi_ctx, st_amount)?;
+
+            stake_acc.pending_withdrawal_amount = underlying;
+            stake_acc.withdraw_request_timestamp = now;
+
+            msg!(
+                "Liquid unstake requested => user={}, st_burned={}, amount_pending={}, can_claim_at={}",
+                user_key,
+                st_amount,
+                underlying,
+                now + UNSTAKE_DELAY_SECONDS
+            );
+            return Ok(());
+        }
+
+        let (total_withdraw, penalty) = withdraw_stake(pool, stake_acc, now)?;
+        stake_acc.pending_withdrawal_amount = total_withdraw;
+        stake_acc.withdraw_request_timestamp = now;
+
+        msg!(
+            "Unstake requested => user={}, amount_pending={}, penalty={}, can_claim_at={}",
+            user_key,
+            total_withdraw,
+            penalty,
+            now + UNSTAKE_DELAY_SECONDS
+        );
+        Ok(())
+    }
+
+    /// Create the instant-unstake buffer vault (pool PDA as authority). Admin only.
+    pub fn initialize_buffer(ctx: Context<InitializeBuffer>, exit_fee_bps: u16) -> Result<()> {
+        let cfg = &mut ctx.accounts.dapp_config;
+        require_role(cfg, &ctx.accounts.owner.key(), Role::Admin, &ctx.accounts.role_grant)?;
+
+        let pool = &mut ctx.accounts.stake_pool;
+        require!(pool.buffer_vault == Pubkey::default(), ErrorCode::AlreadyInitialized);
+        pool.buffer_vault = ctx.accounts.buffer_vault.key();
+        ParamChange::SetExitFee(exit_fee_bps).apply(cfg, pool)?;
+
+        msg!("Instant unstake buffer created => vault={}", pool.buffer_vault);
+        Ok(())
+    }
+
+    /// Fee charged on `instant_unstake`, kept by the buffer.
+    pub fn set_exit_fee(ctx: Context<UpdateStakePool>, exit_fee_bps: u16) -> Result<()> {
+        let cfg = &mut ctx.accounts.dapp_config;
+        require_role(cfg, &ctx.accounts.owner.key(), Role::ParamManager, &ctx.accounts.role_grant)?;
+        require!(!cfg.timelock_enforced, ErrorCode::TimelockRequired);
+
+        ParamChange::SetExitFee(exit_fee_bps).apply(cfg, &mut ctx.accounts.stake_pool)
+    }
+
+    /// Add liquidity to the buffer. Anyone may fund.
+    pub fn fund_buffer(ctx: Context<FundBuffer>, amount: u64) -> Result<()> {
+        let cpi_ctx = CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            TransferChecked {
+                from: ctx.accounts.funder_ata.to_account_info(),
+                mint: ctx.accounts.token_mint.to_account_info(),
+                to: ctx.accounts.buffer_vault.to_account_info(),
+                authority: ctx.accounts.funder.to_account_info(),
+            },
+        );
+        token_2022::transfer_checked(cpi_ctx, amount, ctx.accounts.token_mint.decimals)?;
+
+        msg!("Buffer funded => amount={}", amount);
+        Ok(())
+    }
+
+    /// Unstake everything and get paid now from the buffer, minus the exit
+    /// fee. The withdrawn tokens stay in the pool vault and are swept back
+    /// into the buffer by the next `claim_stake`.
+    pub fn instant_unstake(ctx: Context<InstantUnstake>) -> Result<()> {
+        let stake_acc = &mut ctx.accounts.stake_account;
+        let pool = &mut ctx.accounts.stake_pool;
+        let user_key = ctx.accounts.user.key();
+        let now = Clock::get()?.unix_timestamp;
+
+        let (total_withdraw, penalty) = withdraw_stake(pool, stake_acc, now)?;
+        let (payout, fee) = pool.draw_buffer(total_withdraw, ctx.accounts.buffer_vault.amount)?;
+
+        let pool_seeds = &[STAKE_POOL_SEED, &[pool.bump]];
+        let signer_seeds = &[&pool_seeds[..]];
+        let cpi_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            TransferChecked {
+                from: ctx.accounts.buffer_vault.to_account_info(),
+                mint: ctx.accounts.token_mint.to_account_info(),
+                to: ctx.accounts.user_ata.to_account_info(),
+                authority: pool.to_account_info(),
+            },
+            signer_seeds,
+        );
+        token_2022::transfer_checked(cpi_ctx, payout, ctx.accounts.token_mint.decimals)?;
+
+        msg!(
+            "Instant unstake => user={}, paid={}, fee={}, penalty={}",
+            user_key,
+            payout,
+            fee,
+            penalty
+        );
+        Ok(())
+    }
+
+    /// Create the stCAL receipt mint (Token-2022, pool PDA as authority).
+    pub fn initialize_liquid_staking(ctx: Context<InitializeLiquidStaking>) -> Result<()> {
+        let cfg = &ctx.accounts.dapp_config;
+        require_role(cfg, &ctx.accounts.owner.key(), Role::Admin, &ctx.accounts.role_grant)?;
+
+        let pool = &mut ctx.accounts.stake_pool;
+        require!(pool.st_mint == Pubkey::default(), ErrorCode::AlreadyInitialized);
+        pool.st_mint = ctx.accounts.st_mint.key();
+
+        msg!("Liquid staking enabled => st_mint={}", pool.st_mint);
+        Ok(())
+    }
+
+    /// Undo a pending unstake: the pending amount is restaked (unlocked) and
+    /// starts earning again from now.
+    pub fn cancel_unstake(ctx: Context<CancelUnstake>) -> Result<()> {
+        let stake_acc: &mut StakeAccount = &mut ctx.accounts.stake_account;
+        let pool = &mut ctx.accounts.stake_pool;
+        let user_key = ctx.accounts.user.key();
+
+        let amount = stake_acc.pending_withdrawal_amount;
+        require!(amount > 0, ErrorCode::NothingToClaim);
+
+        stake_acc.pending_withdrawal_amount = 0;
+        stake_acc.withdraw_request_timestamp = 0;
+        credit_stake(pool, stake_acc, amount, 0, Clock::get()?.unix_timestamp)?;
+
+        msg!(
+            "Unstake cancelled => user={}, restaked={}, new_total={}",
+            user_key,
+            amount,
+            stake_acc.stake_amount
+        );
+        Ok(())
+    }
+
+    /// After 2 days from unstake request, claim tokens back to user's ATA.
+    pub fn claim_stake(ctx: Context<ClaimStake>) -> Result<()> {
+        let stake_acc = &mut ctx.accounts.stake_account;
+        let user_key = ctx.accounts.user.key();
+        let now = Clock::get()?.unix_timestamp;
+
+        require!(
+            stake_acc.pending_withdrawal_amount > 0,
+            ErrorCode::NothingToClaim
+        );
+        require!(
+            now >= stake_acc.withdraw_request_timestamp + UNSTAKE_DELAY_SECONDS,
+            ErrorCode::UnstakeDelayNotMet
+        );
+
+        let amount = stake_acc.pending_withdrawal_amount;
+
+        // Transfer from pool vault to user ATA (vault authority is the pool PDA)
+        let pool = &mut ctx.accounts.stake_pool;
+        let pool_seeds = &[STAKE_POOL_SEED, &[pool.bump]];
+        let signer_seeds = &[&pool_seeds[..]];
+        let cpi_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            TransferChecked {
+                from: ctx.accounts.pool_vault.to_account_info(),
+                mint: ctx.accounts.token_mint.to_account_info(),
+                to: ctx.accounts.user_ata.to_account_info(),
+                authority: pool.to_account_info(),
+            },
+            signer_seeds,
+        );
+        token_2022::transfer_checked(cpi_ctx, amount, ctx.accounts.token_mint.decimals)?;
+
+        // Replenish the instant-unstake buffer with what it has paid out
+        let owed = pool.buffer_owed;
+        if owed > 0 {
+            let buffer_vault = ctx
+                .accounts
+                .buffer_vault
+                .as_ref()
+                .ok_or(ErrorCode::BufferVaultRequired)?;
+            let cpi_ctx = CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.pool_vault.to_account_info(),
+                    mint: ctx.accounts.token_mint.to_account_info(),
+                    to: buffer_vault.to_account_info(),
+                    authority: pool.to_account_info(),
+                },
+                signer_seeds,
+            );
+            token_2022::transfer_checked(cpi_ctx, owed, ctx.accounts.token_mint.decimals)?;
+            pool.buffer_owed = 0;
+            msg!("Buffer replenished => amount={}", owed);
+        }
+
+        // Reset pending fields
+        stake_acc.pending_withdrawal_amount = 0;
+        stake_acc.withdraw_request_timestamp = 0;
+        stake_acc.last_reward_timestamp = now;
+
+        msg!(
+            "Claimed stake => user={}, amount_returned={}",
+            user_key,
+            amount
+        );
+        Ok(())
+    }
+
+    /// Move a stake made before the shared pool into it. The legacy per-user
+    /// vault is emptied into the pool vault and credited as an unlocked stake
+    /// (a pending withdrawal stays pending), and the account is grown to the
+    /// current layout. Rent for the extra bytes is paid by the user.
+    pub fn migrate_stake_account(ctx: Context<MigrateStakeAccount>) -> Result<()> {
+        let info = ctx.accounts.stake_account.to_account_info();
+        let user_key = ctx.accounts.user.key();
+        let now = Clock::get()?.unix_timestamp;
+
+        require!(
+            info.data_len() == LegacyStakeAccount::LEN,
+            ErrorCode::AlreadyMigrated
+        );
+        let legacy = {
+            let data = info.try_borrow_data()?;
+            require!(
+                data[..8] == StakeAccount::DISCRIMINATOR,
+                ErrorCode::InvalidAccountLayout
+            );
+            LegacyStakeAccount::deserialize(&mut &data[8..])?
+        };
+        require!(legacy.authority == user_key, ErrorCode::NotAuthorized);
+
+        // Empty the legacy vault (if it was ever created) into the pool vault
+        let vault_balance = match &ctx.accounts.legacy_vault {
+            Some(vault) if vault.amount > 0 => {
+                let vault_seeds = &[
+                    LEGACY_STAKE_VAULT_SEED,
+                    user_key.as_ref(),
+                    &[ctx.bumps.legacy_vault_authority],
+                ];
+                let signer_seeds = &[&vault_seeds[..]];
+                let cpi_ctx = CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    TransferChecked {
+                        from: vault.to_account_info(),
+                        mint: ctx.accounts.token_mint.to_account_info(),
+                        to: ctx.accounts.pool_vault.to_account_info(),
+                        authority: ctx.accounts.legacy_vault_authority.to_account_info(),
+                    },
+                    signer_seeds,
+                );
+                token_2022::transfer_checked(cpi_ctx, vault.amount, ctx.accounts.token_mint.decimals)?;
+                vault.amount
+            }
+            _ => 0,
+        };
+
+        grow_account(
+            &info,
+            &ctx.accounts.user,
+            &ctx.accounts.system_program,
+            StakeAccount::LEN,
+        )?;
+        let (mut stake_acc, to_stake) = legacy.into_stake_account(vault_balance, now);
+        credit_stake(&mut ctx.accounts.stake_pool, &mut stake_acc, to_stake, 0, now)?;
+        stake_acc.try_serialize(&mut &mut info.try_borrow_mut_data()?[..])?;
+
+        msg!(
+            "Stake migrated => user={}, staked={}, pending={}",
+            user_key,
+            stake_acc.stake_amount,
+            stake_acc.pending_withdrawal_amount
+        );
+        Ok(())
+    }
+
+    /// Grow a UserPda created before sweeps and streaks to the current layout.
+    /// Claim history carries over; the streak starts from the next claim.
+    /// Rent for the extra bytes is paid by the user.
+    pub fn migrate_user_pda(ctx: Context<MigrateUserPda>) -> Result<()> {
+        let info = ctx.accounts.user_pda.to_account_info();
+        let user_key = ctx.accounts.user.key();
+
+        require!(info.data_len() == LegacyUserPda::LEN, ErrorCode::AlreadyMigrated);
+        let legacy = {
+            let data = info.try_borrow_data()?;
+            require!(
+                data[..8] == UserPda::DISCRIMINATOR,
+                ErrorCode::InvalidAccountLayout
+            );
+            LegacyUserPda::deserialize(&mut &data[8..])?
+        };
+        require!(legacy.authority == user_key, ErrorCode::NotAuthorized);
+
+        grow_account(&info, &ctx.accounts.user, &ctx.accounts.system_program, UserPda::LEN)?;
+        legacy
+            .into_user_pda()
+            .try_serialize(&mut &mut info.try_borrow_mut_data()?[..])?;
+
+        msg!("UserPda migrated => user={}", user_key);
+        Ok(())
+    }
+
+    /// Slash `bps` of a user's staked and pending-withdrawal balance and of
+    /// every open position, each passed writable in `remaining_accounts`. The
+    /// slashed tokens go to the treasury ATA, or are burned if `burn` is set.
+    /// Callable by the owner, a validator, or the governance-appointed slasher.
+    /// stCAL is a transferable receipt with no owner-bound stake, so it is out
+    /// of reach until redeemed into a withdrawal queue, which is slashable.
+    pub fn slash<'info>(
+        ctx: Context<'_, '_, 'info, 'info, Slash<'info>>,
+        bps: u16,
+        reason: u8,
+        burn: bool,
+    ) -> Result<()> {
+        let cfg = &ctx.accounts.dapp_config;
+        let signer_key = ctx.accounts.slasher.key();
+        if signer_key != cfg.slasher {
+            require_role(cfg, &signer_key, Role::Validator, &ctx.accounts.role_grant)?;
+        }
+        require!(bps > 0 && bps as u64 <= BPS_DENOMINATOR, ErrorCode::InvalidSlash);
+
+        let stake_acc: &mut StakeAccount = &mut ctx.accounts.stake_account;
+        let pool = &mut ctx.accounts.stake_pool;
+        let now = Clock::get()?.unix_timestamp;
+
+        require!(
+            u32::try_from(ctx.remaining_accounts.len()) == Ok(stake_acc.open_positions),
+            ErrorCode::IncompletePositionSet
+        );
+
+        // Settle rewards first so they are slashed along with the principal
+        pool.update(now)?;
+        let from_stake = slash_stake(pool, stake_acc, bps)?;
+        let from_pending = ((stake_acc.pending_withdrawal_amount as u128) * bps as u128
+            / BPS_DENOMINATOR as u128) as u64;
+        stake_acc.pending_withdrawal_amount -= from_pending;
+        stake_acc.last_reward_timestamp = now;
+        let mut amount = from_stake
+            .checked_add(from_pending)
+            .ok_or(ErrorCode::ArithmeticError)?;
+
+        let mut seen: Vec<Pubkey> = Vec::with_capacity(ctx.remaining_accounts.len());
+        for info in ctx.remaining_accounts.iter() {
+            require!(
+                info.is_writable && !seen.contains(info.key),
+                ErrorCode::IncompletePositionSet
+            );
+            seen.push(*info.key);
+
+            let mut position = Account::<StakePosition>::try_from(info)?;
+            require!(
+                position.authority == stake_acc.authority,
+                ErrorCode::IncompletePositionSet
+            );
+            let (old_amount, old_weight) = (position.amount, position.weight()?);
+            let cut = slash_stake(pool, &mut *position, bps)?;
+            position.last_reward_timestamp = now;
+            stake_acc.track_position(old_amount, old_weight, position.amount, position.weight()?)?;
+            position.exit(ctx.program_id)?;
+            amount = amount.checked_add(cut).ok_or(ErrorCode::ArithmeticError)?;
+        }
+        require!(amount > 0, ErrorCode::NothingToUnstake);
+
+        // Move slashed tokens out of the pool vault
+        let pool_seeds = &[STAKE_POOL_SEED, &[pool.bump]];
+        let signer_seeds = &[&pool_seeds[..]];
+        if burn {
+            let cpi_ctx = CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Burn {
+                    mint: ctx.accounts.token_mint.to_account_info(),
+                    from: ctx.accounts.pool_vault.to_account_info(),
+                    authority: pool.to_account_info(),
+                },
+                signer_seeds,
+            );
+            token_2022::burn(cpi_ctx, amount)?;
+            ctx.accounts.dapp_config.record_burn(amount)?;
+        } else {
+            let treasury_ata = ctx
+                .accounts
+                .treasury_ata
+                .as_ref()
+                .ok_or(ErrorCode::MissingTreasuryAccount)?;
+            let cpi_ctx = CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.pool_vault.to_account_info(),
+                    mint: ctx.accounts.token_mint.to_account_info(),
+                    to: treasury_ata.to_account_info(),
+                    authority: pool.to_account_info(),
+                },
+                signer_seeds,
+            );
+            token_2022::transfer_checked(cpi_ctx, amount, ctx.accounts.token_mint.decimals)?;
+        }
+
+        let history = &mut ctx.accounts.slash_history;
+        history.authority = stake_acc.authority;
+        let slot = (history.count as usize) % SLASH_HISTORY_LEN;
+        history.entries[slot] = SlashEntry {
+            timestamp: now,
+            bps,
+            amount,
+            reason,
+        };
+        history.count += 1;
+        history.total_slashed = history
+            .total_slashed
+            .checked_add(amount)
+            .ok_or(ErrorCode::ArithmeticError)?;
+
+        emit!(SlashEvent {
+            user: stake_acc.authority,
+            slasher: signer_key,
+            bps,
+            amount,
+            reason,
+            burned: burn,
+            timestamp: now,
+        });
+        Ok(())
+    }
+
+    /// Create the governance config. Admin only.
+    pub fn initialize_governance(
+        ctx: Context<InitializeGovernance>,
+        voting_period_secs: i64,
+        quorum: u64,
+        threshold_bps: u16,
+        min_proposal_power: u64,
+    ) -> Result<()> {
+        let cfg = &ctx.accounts.dapp_config;
+        require_role(cfg, &ctx.accounts.owner.key(), Role::Admin, &ctx.accounts.role_grant)?;
+        require!(
+            voting_period_secs > 0 && threshold_bps as u64 <= BPS_DENOMINATOR,
+            ErrorCode::InvalidGovernanceParams
+        );
+
+        let gov = &mut ctx.accounts.governance;
+        gov.bump = ctx.bumps.governance;
+        gov.proposal_count = 0;
+        gov.voting_period_secs = voting_period_secs;
+        gov.quorum = quorum;
+        gov.threshold_bps = threshold_bps;
+        gov.min_proposal_power = min_proposal_power;
+
+        msg!(
+            "Governance created => period={}s, quorum={}, threshold={}bps",
+            voting_period_secs,
+            quorum,
+            threshold_bps
+        );
+        Ok(())
+    }
+
+    /// Open a proposal to apply `action`. The proposer needs at least
+    /// `min_proposal_power` voting power.
+    pub fn create_proposal(ctx: Context<CreateProposal>, action: ParamChange) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let power = voting_power(&ctx.accounts.stake_account, now)?;
+        let gov = &mut ctx.accounts.governance;
+        require!(power >= gov.min_proposal_power, ErrorCode::InsufficientVotingPower);
+
+        let proposal = &mut ctx.accounts.proposal;
+        proposal.id = gov.proposal_count;
+        proposal.proposer = ctx.accounts.proposer.key();
+        proposal.action = action;
+        proposal.created_at = now;
+        proposal.voting_ends_at = now + gov.voting_period_secs;
+        proposal.votes_for = 0;
+        proposal.votes_against = 0;
+        proposal.status = ProposalStatus::Active;
+        gov.proposal_count += 1;
+
+        msg!(
+            "Proposal {} created => proposer={}, voting_ends_at={}",
+            proposal.id,
+            proposal.proposer,
+            proposal.voting_ends_at
+        );
+        Ok(())
+    }
+
+    /// Vote with the caller's current stake weight. One vote per wallet. The
+    /// voter's stake and positions cannot be withdrawn until voting ends, so
+    /// the same tokens cannot vote again from another wallet.
+    pub fn cast_vote(ctx: Context<CastVote>, proposal_id: u64, support: bool) -> Result<()> {
+        let proposal = &mut ctx.accounts.proposal;
+        let now = Clock::get()?.unix_timestamp;
+        require!(
+            proposal.status == ProposalStatus::Active && now < proposal.voting_ends_at,
+            ErrorCode::VotingClosed
+        );
+
+        let power = voting_power(&ctx.accounts.stake_account, now)?;
+        require!(power > 0, ErrorCode::InsufficientVotingPower);
+        if support {
+            proposal.votes_for = proposal
+                .votes_for
+                .checked_add(power)
+                .ok_or(ErrorCode::ArithmeticError)?;
+        } else {
+            proposal.votes_against = proposal
+                .votes_against
+                .checked_add(power)
+                .ok_or(ErrorCode::ArithmeticError)?;
+        }
+
+        let stake_acc = &mut ctx.accounts.stake_account;
+        stake_acc.vote_locked_until =
+            std::cmp::max(stake_acc.vote_locked_until, proposal.voting_ends_at);
+
+        let record = &mut ctx.accounts.vote_record;
+        record.voter = ctx.accounts.voter.key();
+        record.proposal_id = proposal_id;
+        record.support = support;
+        record.power = power;
+
+        msg!(
+            "Vote => proposal={}, voter={}, support={}, power={}",
+            proposal_id,
+            record.voter,
+            support,
+            power
+        );
+        Ok(())
+    }
+
+    /// Queue admin operation `id` to run no earlier than `eta`, which must be
+    /// at least MIN_TIMELOCK_DELAY away. Admin only.
+    pub fn queue_admin_op(ctx: Context<QueueAdminOp>, id: u64, op: AdminOp, eta: i64) -> Result<()> {
+        let cfg = &ctx.accounts.dapp_config;
+        let admin = ctx.accounts.admin.key();
+        require_role(cfg, &admin, Role::Admin, &ctx.accounts.role_grant)?;
+
+        require!(id & PROPOSAL_OP_ID_BIT == 0, ErrorCode::ReservedOpId);
+
+        let now = Clock::get()?.unix_timestamp;
+        require!(eta >= now + MIN_TIMELOCK_DELAY, ErrorCode::TimelockDelayTooShort);
+
+        let queued = &mut ctx.accounts.queued_op;
+        queued.id = id;
+        queued.op = op;
+        queued.eta = eta;
+        queued.queued_by = admin;
+        queued.queued_at = now;
+
+        emit!(AdminOpQueued { id, op, eta, queued_by: admin });
+        msg!("Admin op {} queued => eta={}", id, eta);
+        Ok(())
+    }
+
+    /// Run a queued operation once its ETA has passed. Anyone may execute;
+    /// the account's rent goes back to whoever queued it. Role changes need
+    /// the grant PDA and the system program; the executor pays for a new grant
+    /// and receives the rent of a revoked one.
+    pub fn execute_admin_op(ctx: Context<ExecuteAdminOp>, id: u64) -> Result<()> {
+        let queued = &ctx.accounts.queued_op;
+        let now = Clock::get()?.unix_timestamp;
+        queued.check_ready(now)?;
+
+        match queued.op {
+            AdminOp::GrantRole { role, grantee } => {
+                let grant = ctx.accounts.grant.as_ref().ok_or(ErrorCode::MissingOpAccounts)?;
+                let system_program = ctx
+                    .accounts
+                    .system_program
+                    .as_ref()
+                    .ok_or(ErrorCode::MissingOpAccounts)?;
+                open_role_grant(
+                    grant,
+                    &ctx.accounts.executor,
+                    system_program,
+                    RoleGrant { role, grantee, granted_by: queued.queued_by, granted_at: now },
+                )?;
+                msg!("Role granted => role={:?}, grantee={}", role, grantee);
+            }
+            AdminOp::RevokeRole { role, grantee } => {
+                let grant = ctx.accounts.grant.as_ref().ok_or(ErrorCode::MissingOpAccounts)?;
+                let executor = ctx.accounts.executor.to_account_info();
+                close_role_grant(grant, &executor, role, grantee)?;
+                msg!("Role revoked => role={:?}, grantee={}", role, grantee);
+            }
+            op => op.apply(
+                &mut ctx.accounts.dapp_config,
+                ctx.accounts.stake_pool.as_deref_mut(),
+            )?,
+        }
+
+        emit!(AdminOpExecuted { id, op: queued.op, executor: ctx.accounts.executor.key() });
+        msg!("Admin op {} executed", id);
+        Ok(())
+    }
+
+    /// Drop a queued operation before it runs. Guardian only; like every
+    /// role, the owner holds it implicitly and can always cancel.
+    pub fn cancel_admin_op(ctx: Context<CancelAdminOp>, id: u64) -> Result<()> {
+        let cfg = &ctx.accounts.dapp_config;
+        let guardian = ctx.accounts.guardian.key();
+        require_role(cfg, &guardian, Role::Guardian, &ctx.accounts.role_grant)?;
+
+        emit!(AdminOpCancelled { id, op: ctx.accounts.queued_op.op, guardian });
+        msg!("Admin op {} cancelled", id);
+        Ok(())
+    }
+
+    /// Route all exempt, treasury, parameter and role changes through the
+    /// timelock again after a queued `DisableTimelock`. Admin only.
+    pub fn enforce_timelock(ctx: Context<UpdateFaucet>) -> Result<()> {
+        let cfg = &mut ctx.accounts.dapp_config;
+        require_role(cfg, &ctx.accounts.owner.key(), Role::Admin, &ctx.accounts.role_grant)?;
+
+        cfg.timelock_enforced = true;
+        msg!("Timelock enforced for admin changes");
+        Ok(())
+    }
+
+    /// Close voting. Passing proposals (quorum met and `votes_for` at or
+    /// above the threshold share) are queued like an admin op, as id
+    /// `PROPOSAL_OP_ID_BIT | proposal_id`, and run through `execute_admin_op`
+    /// after MIN_TIMELOCK_DELAY. The finalizer pays for the queued op and gets
+    /// the rent back on execution.
+    pub fn finalize_proposal(ctx: Context<FinalizeProposal>, proposal_id: u64) -> Result<()> {
+        let gov = &ctx.accounts.governance;
+        let proposal = &mut ctx.accounts.proposal;
+        let now = Clock::get()?.unix_timestamp;
+        require!(proposal.status == ProposalStatus::Active, ErrorCode::VotingClosed);
+        require!(now >= proposal.voting_ends_at, ErrorCode::VotingStillOpen);
+
+        let cast = proposal.votes_for as u128 + proposal.votes_against as u128;
+        let passed = cast >= gov.quorum as u128
+            && cast > 0
+            && proposal.votes_for as u128 * BPS_DENOMINATOR as u128
+                >= cast * gov.threshold_bps as u128;
+
+        if passed {
+            let queued = ctx
+                .accounts
+                .queued_op
+                .as_mut()
+                .ok_or(ErrorCode::MissingOpAccounts)?;
+            let id = PROPOSAL_OP_ID_BIT | proposal_id;
+            let op = AdminOp::Param(proposal.action);
+            let eta = now.saturating_add(MIN_TIMELOCK_DELAY);
+            let finalizer = ctx.accounts.finalizer.key();
+            queued.id = id;
+            queued.op = op;
+            queued.eta = eta;
+            queued.queued_by = finalizer;
+            queued.queued_at = now;
+            emit!(AdminOpQueued { id, op, eta, queued_by: finalizer });
+            proposal.status = ProposalStatus::Queued;
+        } else {
+            proposal.status = ProposalStatus::Defeated;
+        }
+
+        msg!(
+            "Proposal {} finalized => for={}, against={}, passed={}",
+            proposal_id,
+            proposal.votes_for,
+            proposal.votes_against,
+            passed
+        );
+        Ok(())
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+//  HELPERS
+// ------------------------------------------------------------------------------------------------
+
+/// Fold accrued rewards into the staked amount and the pool total.
+/// `pool.update` must already have run; the caller then calls `reweight`.
+fn compound_rewards<S: Staked>(pool: &mut StakePool, stake: &mut S) -> Result<u64> {
+    let reward = pool.pending_reward(stake.weight()?, stake.reward_debt())?;
+    *stake.amount_mut() = stake
+        .amount()
+        .checked_add(reward)
+        .ok_or(ErrorCode::ArithmeticError)?;
+    pool.total_staked = pool
+        .total_staked
+        .checked_add(reward)
+        .ok_or(ErrorCode::ArithmeticError)?;
+    Ok(reward)
+}
+
+/// Compound a stake's rewards, then cut `bps` of its principal out of it and
+/// the pool total. `pool.update` must already have run. Returns the amount
+/// cut, which the caller moves out of the pool vault.
+fn slash_stake<S: Staked>(pool: &mut StakePool, stake: &mut S, bps: u16) -> Result<u64> {
+    let old_weight = stake.weight()?;
+    compound_rewards(pool, stake)?;
+
+    let cut = ((stake.amount() as u128) * bps as u128 / BPS_DENOMINATOR as u128) as u64;
+    *stake.amount_mut() -= cut;
+    pool.total_staked = pool
+        .total_staked
+        .checked_sub(cut)
+        .ok_or(ErrorCode::ArithmeticError)?;
+    reweight(pool, stake, old_weight)?;
+    Ok(cut)
+}
+
+/// Compound, add `amount` and apply `lock_tier` to the main stake. The lock
+/// applies if it is at least as strong as the current one; otherwise the
+/// deposit joins the existing lock.
+fn credit_stake(
+    pool: &mut StakePool,
+    stake_acc: &mut StakeAccount,
+    amount: u64,
+    lock_tier: u8,
+    now: i64,
+) -> Result<()> {
+    pool.update(now)?;
+    let old_weight = stake_acc.weight()?;
+    compound_rewards(pool, stake_acc)?;
+
+    stake_acc.stake_amount = stake_acc
+        .stake_amount
+        .checked_add(amount)
+        .ok_or(ErrorCode::ArithmeticError)?;
+    pool.total_staked = pool
+        .total_staked
+        .checked_add(amount)
+        .ok_or(ErrorCode::ArithmeticError)?;
+
+    let tier = pool.lock_tiers[lock_tier as usize];
+    if stake_acc.lock_end <= now || tier.boost_bps >= stake_acc.boost_bps {
+        stake_acc.lock_tier = lock_tier;
+        stake_acc.boost_bps = tier.boost_bps;
+        stake_acc.lock_end = std::cmp::max(stake_acc.lock_end, now + tier.duration_secs);
+    }
+    reweight(pool, stake_acc, old_weight)?;
+    stake_acc.last_reward_timestamp = now;
+    Ok(())
+}
+
+/// Set up a freshly created position at the owner's next index holding
+/// `amount` (already in the pool vault) at `lock_tier`, and count it in the
+/// pool and the owner's position totals.
+fn fill_position(
+    pool: &mut StakePool,
+    stake_acc: &mut StakeAccount,
+    position: &mut StakePosition,
+    amount: u64,
+    lock_tier: u8,
+    now: i64,
+) -> Result<()> {
+    pool.update(now)?;
+
+    let tier = pool.lock_tiers[lock_tier as usize];
+    position.authority = stake_acc.authority;
+    position.index = stake_acc.position_count;
+    position.amount = amount;
+    position.start_timestamp = now;
+    position.last_reward_timestamp = now;
+    position.lock_tier = lock_tier;
+    position.lock_end = now + tier.duration_secs;
+    position.boost_bps = tier.boost_bps;
+    position.reward_debt = 0;
+
+    pool.total_staked = pool
+        .total_staked
+        .checked_add(amount)
+        .ok_or(ErrorCode::ArithmeticError)?;
+    reweight(pool, position, 0)?;
+    stake_acc.position_count += 1;
+    stake_acc.open_positions += 1;
+    stake_acc.track_position(0, 0, position.amount, position.weight()?)
+}
+
+/// Replace `old_weight` with the stake's current weight in the pool total
+/// and settle its reward debt at the current rate.
+fn reweight<S: Staked>(pool: &mut StakePool, stake: &mut S, old_weight: u64) -> Result<()> {
+    let new_weight = stake.weight()?;
+    pool.total_weight = pool
+        .total_weight
+        .checked_sub(old_weight)
+        .and_then(|w| w.checked_add(new_weight))
+        .ok_or(ErrorCode::ArithmeticError)?;
+    *stake.reward_debt_mut() = pool.reward_debt_for(new_weight)?;
+    Ok(())
+}
+
+/// Stake weighted by a lock boost.
+fn boosted(amount: u64, boost_bps: u16) -> Result<u64> {
+    let weight = (amount as u128)
+        .checked_mul(boost_bps as u128)
+        .ok_or(ErrorCode::ArithmeticError)?
+        / BPS_DENOMINATOR as u128;
+    u64::try_from(weight).map_err(|_| error!(ErrorCode::ArithmeticError))
+}
+
+/// Reset a stake whose lock has ended to tier 0 so it stops earning the
+/// boost. Rewards earned up to now are compounded first, at the old weight.
+/// Returns false (and changes nothing) while the lock is active or unboosted.
+fn expire_lock<S: Staked>(pool: &mut StakePool, stake: &mut S, now: i64) -> Result<bool> {
+    if now < stake.lock_end() || stake.boost_bps() == BPS_DENOMINATOR as u16 {
+        return Ok(false);
+    }
+    pool.update(now)?;
+    let old_weight = stake.weight()?;
+    compound_rewards(pool, stake)?;
+    stake.clear_lock();
+    reweight(pool, stake, old_weight)?;
+    Ok(true)
+}
+
+/// Governance weight: the main stake, boosted only while its lock is active,
+/// plus all open positions (as last reweighted; `poke_position` drops an
+/// expired one), so longer locks vote with proportionally more weight.
+fn voting_power(stake_acc: &StakeAccount, now: i64) -> Result<u64> {
+    stake_acc
+        .effective_weight(now)?
+        .checked_add(stake_acc.position_weight)
+        .ok_or(error!(ErrorCode::ArithmeticError))
+}
+
+/// Faucet accounting shared by `claim` and `claim_and_stake`: cooldown,
+/// accrual cap, streak and stake multipliers, and the supply cap. The stake
+/// boost is keyed on `stake_power`, the caller's `voting_power`. Returns the
+/// amount the caller must mint (0 means nothing to mint).
+fn accrue_faucet(
+    cfg: &mut DappConfig,
+    user_pda: &mut UserPda,
+    stake_power: u64,
+    user_key: Pubkey,
+    now: i64,
+    caller: &str,
+) -> Result<u64> {
+    // Update the total_claims counter
+    cfg.total_claims = cfg
+        .total_claims
+        .checked_add(1)
+        .ok_or(ErrorCode::IssuanceRateTooHigh)?;
+
+    let raw_elapsed = now.saturating_sub(user_pda.last_claimed_timestamp);
+    require!(raw_elapsed >= 60, ErrorCode::CooldownNotMet);
+
+    // Cap elapsed at 3 days (259,200 seconds)
+    let elapsed = std::cmp::min(raw_elapsed, MAX_ACCUMULATION_SECONDS);
+
+    // Determine per-second rate: exempt = 42×, else normal
+    let tokens_per_second_micro = if user_key == cfg.exempt_address {
+        cfg.exempt_rate_per_sec
+    } else {
+        cfg.user_rate_per_sec
+    };
+
+    let base_amount = tokens_per_second_micro
+        .checked_mul(elapsed as u64)
+        .ok_or(ErrorCode::IssuanceRateTooHigh)?;
+
+    // Daily streak multiplier, then the boost for the caller's current stake
+    let streak = user_pda.record_claim_day(now);
+    let streak_bps = cfg.streak_multiplier_bps(streak);
+    let stake_bps = cfg.stake_boost_bps(stake_power);
+    let minted_amount = u64::try_from(
+        base_amount as u128 * streak_bps as u128 / BPS_DENOMINATOR as u128
+            * stake_bps as u128
+            / BPS_DENOMINATOR as u128,
+    )
+    .map_err(|_| error!(ErrorCode::IssuanceRateTooHigh))?;
+    if minted_amount == 0 {
+        msg!("No tokens to mint; skipping claim.");
+        return Ok(0);
+    }
+
+    // Enforce global max supply cap
+    cfg.record_mint(minted_amount)?;
+
+    // Track per-user total claimed
+    let new_user_total = user_pda
+        .claimed_so_far
+        .checked_add(minted_amount)
+        .ok_or(ErrorCode::IssuanceRateTooHigh)?;
+    user_pda.claimed_so_far = new_user_total;
+
+    user_pda.last_claimed_timestamp = now;
+    user_pda.inactive_flagged_at = 0; // any claim cancels a pending sweep
+    msg!(
+        "{} => user={}, minted={} μtokens, elapsed={}s, streak={}d ({}bps), stake_boost={}bps, last_claimed={}",
+        caller,
+        user_key,
+        minted_amount,
+        elapsed,
+        streak,
+        streak_bps,
+        stake_bps,
+        now
+    );
+    Ok(minted_amount)
+}
+
+/// The owner holds every role; anyone else needs a RoleGrant for `role`.
+/// RoleGrants are only created by `grant_role`, so matching data is enough.
+fn require_role(
+    cfg: &DappConfig,
+    signer: &Pubkey,
+    role: Role,
+    grant: &Option<Account<RoleGrant>>,
+) -> Result<()> {
+    if *signer == cfg.owner {
+        return Ok(());
+    }
+    require!(
+        grant
+            .as_ref()
+            .is_some_and(|g| g.grantee == *signer && g.role == role),
+        ErrorCode::MissingRole
+    );
+    Ok(())
+}
+
+/// Create the RoleGrant PDA for a timelocked `GrantRole`. Like Anchor's
+/// `init`, this also works if someone has already sent lamports to the address.
+fn open_role_grant<'info>(
+    info: &AccountInfo<'info>,
+    payer: &Signer<'info>,
+    system_program: &Program<'info, System>,
+    grant: RoleGrant,
+) -> Result<()> {
+    let role_seed = [grant.role as u8];
+    let (expected, bump) =
+        Pubkey::find_program_address(&[ROLE_SEED, &role_seed, grant.grantee.as_ref()], &crate::ID);
+    require_keys_eq!(info.key(), expected, ErrorCode::InvalidRoleGrant);
+    let bump_seed = [bump];
+    let signer: &[&[&[u8]]] = &[&[ROLE_SEED, &role_seed, grant.grantee.as_ref(), &bump_seed]];
+
+    let rent = Rent::get()?.minimum_balance(RoleGrant::LEN);
+    if info.lamports() == 0 {
+        system_program::create_account(
+            CpiContext::new_with_signer(
+                system_program.to_account_info(),
+                system_program::CreateAccount {
+                    from: payer.to_account_info(),
+                    to: info.clone(),
+                },
+                signer,
+            ),
+            rent,
+            RoleGrant::LEN as u64,
+            &crate::ID,
+        )?;
+    } else {
+        require_keys_eq!(*info.owner, System::id(), ErrorCode::InvalidRoleGrant);
+        let shortfall = rent.saturating_sub(info.lamports());
+        if shortfall > 0 {
+            system_program::transfer(
+                CpiContext::new(
+                    system_program.to_account_info(),
+                    system_program::Transfer {
+                        from: payer.to_account_info(),
+                        to: info.clone(),
+                    },
+                ),
+                shortfall,
+            )?;
+        }
+        system_program::allocate(
+            CpiContext::new_with_signer(
+                system_program.to_account_info(),
+                system_program::Allocate { account_to_allocate: info.clone() },
+                signer,
+            ),
+            RoleGrant::LEN as u64,
+        )?;
+        system_program::assign(
+            CpiContext::new_with_signer(
+                system_program.to_account_info(),
+                system_program::Assign { account_to_assign: info.clone() },
+                signer,
+            ),
+            &crate::ID,
+        )?;
+    }
+
+    grant.try_serialize(&mut &mut info.try_borrow_mut_data()?[..])?;
+    Ok(())
+}
+
+/// Close the RoleGrant a timelocked `RevokeRole` names, sending its rent to
+/// `destination`.
+fn close_role_grant<'info>(
+    info: &AccountInfo<'info>,
+    destination: &AccountInfo<'info>,
+    role: Role,
+    grantee: Pubkey,
+) -> Result<()> {
+    require_keys_eq!(*info.owner, crate::ID, ErrorCode::InvalidRoleGrant);
+    let grant = RoleGrant::try_deserialize(&mut &info.try_borrow_data()?[..])?;
+    require!(
+        grant.role == role && grant.grantee == grantee,
+        ErrorCode::InvalidRoleGrant
+    );
+
+    let lamports = info.lamports();
+    **destination.try_borrow_mut_lamports()? = destination
+        .lamports()
+        .checked_add(lamports)
+        .ok_or(ErrorCode::ArithmeticError)?;
+    **info.try_borrow_mut_lamports()? = 0;
+    info.assign(&System::id());
+    info.realloc(0, false)?;
+    Ok(())
+}
+
+/// Check a Merkle proof built with sorted-pair keccak hashing.
+fn verify_merkle_proof(proof: &[[u8; 32]], root: [u8; 32], leaf: [u8; 32]) -> bool {
+    let computed = proof.iter().fold(leaf, |node, sibling| {
+        if node <= *sibling {
+            keccak::hashv(&[&node, sibling]).0
+        } else {
+            keccak::hashv(&[sibling, &node]).0
+        }
+    });
+    computed == root
+}
+
+/// Gateway pass check shared by registration paths. The exempt address skips it.
+fn verify_gateway(
+    cfg: &DappConfig,
+    gateway_token: &UncheckedAccount,
+    user_key: &Pubkey,
+    caller: &str,
+) -> Result<()> {
+    if *user_key == cfg.exempt_address {
+        msg!("User is exempt => skipping gateway check in {}", caller);
+        return Ok(());
+    }
+
+    // Use on-chain gatekeeper_network instead of hardcoded literal
+    Gateway::verify_gateway_token_account_info(
+        &gateway_token.to_account_info(),
+        user_key,
+        &cfg.gatekeeper_network,
+        None,
+    )
+    .map_err(|_e| {
+        msg!("Gateway token verification failed");
+        error!(ErrorCode::GatewayCheckFailed)
+    })?;
+    Ok(())
+}
+
+/// Register a user implicitly on their first `claim`, `claim_and_stake` or
+/// `stake`. Unlike `register_user`, accrual starts now: the registering call
+/// earns nothing, and a swept user cannot collect a fresh
+/// MAX_ACCUMULATION_SECONDS just by claiming again.
+fn register_on_first_use(
+    user_pda: &mut UserPda,
+    stake: &mut StakeAccount,
+    user_key: Pubkey,
+    now: i64,
+) {
+    init_user_accounts(user_pda, stake, user_key, now);
+    user_pda.last_claimed_timestamp = now;
+}
+
+/// Fill in a freshly created UserPda and StakeAccount.
+fn init_user_accounts(
+    user_pda: &mut UserPda,
+    stake: &mut StakeAccount,
+    user_key: Pubkey,
+    now: i64,
+) {
+    user_pda.authority = user_key;
+    user_pda.last_claimed_timestamp = 0;
+    user_pda.claimed_so_far = 0;
+    user_pda.inactive_flagged_at = 0;
+    user_pda.current_streak = 0;
+    user_pda.longest_streak = 0;
+    user_pda.last_streak_day = 0;
+
+    stake.authority = user_key;
+    stake.stake_amount = 0;
+    stake.last_reward_timestamp = now;
+    stake.pending_withdrawal_amount = 0;
+    stake.withdraw_request_timestamp = 0;
+    stake.reward_debt = 0;
+    stake.lock_tier = 0;
+    stake.lock_end = 0;
+    stake.boost_bps = BPS_DENOMINATOR as u16;
+    stake.position_count = 0;
+    stake.open_positions = 0;
+    stake.position_total = 0;
+    stake.position_weight = 0;
+    stake.withdraw_authority = Pubkey::default();
+    stake.vote_locked_until = 0;
+}
+
+/// Deregistration requires nothing left in the stake account or positions.
+fn require_empty_stake(stake_acc: &StakeAccount) -> Result<()> {
+    require!(
+        stake_acc.stake_amount == 0
+            && stake_acc.pending_withdrawal_amount == 0
+            && stake_acc.open_positions == 0,
+        ErrorCode::StakeNotEmpty
+    );
+    Ok(())
+}
+
+/// Take the whole stake (plus rewards, minus any early-exit penalty) out of
+/// the pool and reset the account. Returns `(total_withdraw, penalty)`; the
+/// penalty goes back to the reward budget.
+fn withdraw_stake(pool: &mut StakePool, stake_acc: &mut StakeAccount, now: i64) -> Result<(u64, u64)> {
+    require!(stake_acc.stake_amount > 0, ErrorCode::NothingToUnstake);
+    require!(now >= stake_acc.vote_locked_until, ErrorCode::VoteLocked);
+    require!(
+        stake_acc.pending_withdrawal_amount == 0,
+        ErrorCode::UnstakeAlreadyRequested
+    );
+
+    // Compute rewards up to now
+    pool.update(now)?;
+    let weight = stake_acc.weight()?;
+    let reward = pool.pending_reward(weight, stake_acc.reward_debt)?;
+    let penalty = early_exit_penalty(pool, stake_acc.stake_amount, stake_acc.lock_end, now)?;
+
+    // Total to withdraw = stake + reward - penalty
+    let total_withdraw = stake_acc
+        .stake_amount
+        .checked_add(reward)
+        .and_then(|v| v.checked_sub(penalty))
+        .ok_or(ErrorCode::ArithmeticError)?;
+
+    pool.total_staked = pool
+        .total_staked
+        .checked_sub(stake_acc.stake_amount)
+        .ok_or(ErrorCode::ArithmeticError)?;
+    pool.total_weight = pool
+        .total_weight
+        .checked_sub(weight)
+        .ok_or(ErrorCode::ArithmeticError)?;
+    pool.reward_budget = pool
+        .reward_budget
+        .checked_add(penalty)
+        .ok_or(ErrorCode::ArithmeticError)?;
+    stake_acc.stake_amount = 0;
+    stake_acc.reward_debt = 0;
+    stake_acc.lock_tier = 0;
+    stake_acc.lock_end = 0;
+    stake_acc.boost_bps = BPS_DENOMINATOR as u16;
+    stake_acc.last_reward_timestamp = now;
+    Ok((total_withdraw, penalty))
+}
+
+/// Fund rent for `new_len` bytes from `payer` and realloc `info` to it. The
+/// new tail is zeroed, so appended fields start out as zero.
+fn grow_account<'info>(
+    info: &AccountInfo<'info>,
+    payer: &Signer<'info>,
+    system_program: &Program<'info, System>,
+    new_len: usize,
+) -> Result<()> {
+    let shortfall = Rent::get()?
+        .minimum_balance(new_len)
+        .saturating_sub(info.lamports());
+    if shortfall > 0 {
+        let cpi_ctx = CpiContext::new(
+            system_program.to_account_info(),
+            system_program::Transfer {
+                from: payer.to_account_info(),
+                to: info.clone(),
+            },
+        );
+        system_program::transfer(cpi_ctx, shortfall)?;
+    }
+    info.realloc(new_len, true)?;
+    Ok(())
+}
+
+/// Penalty for withdrawing `amount` before `lock_end`; errors when the pool
+/// disallows early exit.
+fn early_exit_penalty(pool: &StakePool, amount: u64, lock_end: i64, now: i64) -> Result<u64> {
+    if now >= lock_end {
+        return Ok(0);
+    }
+    require!(pool.early_exit_penalty_bps > 0, ErrorCode::StakeLocked);
+    Ok(((amount as u128) * pool.early_exit_penalty_bps as u128 / BPS_DENOMINATOR as u128) as u64)
+}
+
+// ------------------------------------------------------------------------------------------------
+//  STATE ACCOUNTS
+// ------------------------------------------------------------------------------------------------
+
+#[account]
+pub struct DappConfig {
+    pub gatekeeper_network: Pubkey,  // Gatekeeper network ID.
+    pub token_mint: Pubkey,          // Token mint used for distribution.
+    pub mint_authority_bump: u8,     // Bump for mint authority PDA.
+    pub exempt_address: Pubkey,      // Exempt address.
+    pub owner: Pubkey,               // Owner of the dapp.
+    pub initialized: bool,           // Flag to block re-initializing.
+
+    // Supply control fields
+    pub total_minted: u64,           // Total microtokens minted so far.
+    pub max_supply: u64,             // Maximum microtoken supply.
+    pub reserved_supply: u64,        // Set aside for airdrops and vesting, not yet minted.
+    pub total_burned: u64,           // Burned via burn, buyback_burn and slash.
+    pub burn_reopens_supply: bool,   // If set, burns free room under max_supply; see set_burn_reopens.
+    pub paused: bool,                // Halts all minting; set by a pauser.
+
+    // Claim counter
+    pub total_claims: u64,           // Counts how many times `claim` was invoked
+
+    // Faucet rates
+    pub user_rate_per_sec: u64,      // Normal accrual, microtokens per second.
+    pub exempt_rate_per_sec: u64,    // Exempt accrual, microtokens per second.
+
+    // Protocol wallets
+    pub treasury: Pubkey,            // Receives slashed tokens and other protocol revenue.
+    pub slasher: Pubkey,             // Besides the owner, may call `slash`; set by governance.
+
+    // Faucet loyalty
+    pub streak_tiers: [StreakTier; MAX_STREAK_TIERS], // Sorted by min_days
+    pub stake_boost_tiers: [StakeBoostTier; MAX_STAKE_BOOST_TIERS], // Sorted by min_stake
+
+    // Admin timelock
+    pub timelock_enforced: bool,     // Direct admin setters disabled; use the timelock.
+}
+
+impl DappConfig {
+    // 8 discriminator + 32 + 32 + 1 + 32 + 32 + 1 + 8 + 8 + 8 + 8 + 1 + 1 + 8 + 8 + 8
+    //   + 32 + 32 + 6 * 4 + 10 * 4 + 1 = 325 bytes
+    pub const LEN: usize = 8 + 32 + 32 + 1 + 32 + 32 + 1
+        + 8 + 8 + 8 + 8 + 1 + 1
+        + 8 + 8 + 8
+        + 32 + 32
+        + StreakTier::LEN * MAX_STREAK_TIERS
+        + StakeBoostTier::LEN * MAX_STAKE_BOOST_TIERS
+        + 1;
+
+    /// Byte offset of `owner`, which the deployed layout shares.
+    pub const OWNER_OFFSET: usize = 8 + 32 + 32 + 1 + 32;
+
+    /// Supply counted against `max_supply`: everything minted, less burns if
+    /// `burn_reopens_supply` is set.
+    pub fn capped_supply(&self) -> u64 {
+        if self.burn_reopens_supply {
+            self.total_minted.saturating_sub(self.total_burned)
+        } else {
+            self.total_minted
+        }
+    }
+
+    /// Replace streak tier `index`, keeping the table sorted by `min_days`
+    /// with tier 0 starting at day 0.
+    pub fn set_streak_tier(&mut self, index: u8, tier: StreakTier) -> Result<()> {
+        require!((index as usize) < MAX_STREAK_TIERS, ErrorCode::InvalidStreakTier);
+
+        let mut tiers = self.streak_tiers;
+        tiers[index as usize] = tier;
+        require!(
+            tiers[0].min_days == 0
+                && tiers.windows(2).all(|w| w[0].min_days <= w[1].min_days)
+                && tiers.iter().all(|t| t.multiplier_bps as u64 >= BPS_DENOMINATOR),
+            ErrorCode::InvalidStreakTier
+        );
+        self.streak_tiers = tiers;
+
+        msg!(
+            "Streak tier {} => min_days={}, multiplier={}bps",
+            index,
+            tier.min_days,
+            tier.multiplier_bps
+        );
+        Ok(())
+    }
+
+    /// Replace stake boost tier `index`, keeping the table sorted by
+    /// `min_stake` with tier 0 starting at zero stake.
+    pub fn set_stake_boost_tier(&mut self, index: u8, tier: StakeBoostTier) -> Result<()> {
+        require!((index as usize) < MAX_STAKE_BOOST_TIERS, ErrorCode::InvalidStakeBoostTier);
+
+        let mut tiers = self.stake_boost_tiers;
+        tiers[index as usize] = tier;
+        require!(
+            tiers[0].min_stake == 0
+                && tiers.windows(2).all(|w| w[0].min_stake <= w[1].min_stake)
+                && tiers.iter().all(|t| t.multiplier_bps as u64 >= BPS_DENOMINATOR),
+            ErrorCode::InvalidStakeBoostTier
+        );
+        self.stake_boost_tiers = tiers;
+
+        msg!(
+            "Stake boost tier {} => min_stake={}, multiplier={}bps",
+            index,
+            tier.min_stake,
+            tier.multiplier_bps
+        );
+        Ok(())
+    }
+
+    /// Toggle `burn_reopens_supply`. Turning it off counts burns against the
+    /// cap again, so it is refused if that would put the supply over it.
+    pub fn set_burn_reopens(&mut self, enabled: bool) -> Result<()> {
+        if !enabled {
+            require!(
+                self.total_minted
+                    .checked_add(self.reserved_supply)
+                    .is_some_and(|t| t <= self.max_supply),
+                ErrorCode::SupplyExceeded
+            );
+        }
+        self.burn_reopens_supply = enabled;
+        Ok(())
+    }
+
+    /// Count a fresh mint against `max_supply`, leaving reserved supply untouched.
+    pub fn record_mint(&mut self, amount: u64) -> Result<()> {
+        require!(!self.paused, ErrorCode::Paused);
+        let new_total = self
+            .total_minted
+            .checked_add(amount)
+            .ok_or(ErrorCode::IssuanceRateTooHigh)?;
+        require!(
+            self.capped_supply()
+                .checked_add(amount)
+                .and_then(|t| t.checked_add(self.reserved_supply))
+                .is_some_and(|t| t <= self.max_supply),
+            ErrorCode::SupplyExceeded
+        );
+        self.total_minted = new_total;
+        Ok(())
+    }
+
+    /// Set `amount` aside under `max_supply` for a later `mint_reserved`.
+    pub fn reserve_supply(&mut self, amount: u64) -> Result<()> {
+        let reserved = self
+            .reserved_supply
+            .checked_add(amount)
+            .ok_or(ErrorCode::ArithmeticError)?;
+        require!(
+            self.capped_supply()
+                .checked_add(reserved)
+                .is_some_and(|t| t <= self.max_supply),
+            ErrorCode::SupplyExceeded
+        );
+        self.reserved_supply = reserved;
+        Ok(())
+    }
+
+    /// Hand back a reservation that will never be minted.
+    pub fn release_reservation(&mut self, amount: u64) -> Result<()> {
+        self.reserved_supply = self
+            .reserved_supply
+            .checked_sub(amount)
+            .ok_or(ErrorCode::ArithmeticError)?;
+        Ok(())
+    }
+
+    /// Count a mint made out of an earlier reservation.
+    pub fn mint_reserved(&mut self, amount: u64) -> Result<()> {
+        self.release_reservation(amount)?;
+        self.record_mint(amount)
+    }
+
+    pub fn record_burn(&mut self, amount: u64) -> Result<()> {
+        self.total_burned = self
+            .total_burned
+            .checked_add(amount)
+            .ok_or(ErrorCode::ArithmeticError)?;
+        Ok(())
+    }
+
+    /// Multiplier of the highest tier reached by `streak`.
+    pub fn streak_multiplier_bps(&self, streak: u32) -> u16 {
+        self.streak_tiers
+            .iter()
+            .rev()
+            .find(|t| streak >= t.min_days)
+            .map_or(BPS_DENOMINATOR as u16, |t| t.multiplier_bps)
+    }
+
+    /// Faucet multiplier of the highest tier reached by `stake_power`, the
+    /// same measure as `voting_power`: the main stake (boosted while locked)
+    /// plus open positions. Unstaking drops it, and the boost with it.
+    pub fn stake_boost_bps(&self, stake_power: u64) -> u16 {
+        self.stake_boost_tiers
+            .iter()
+            .rev()
+            .find(|t| stake_power >= t.min_stake)
+            .map_or(BPS_DENOMINATOR as u16, |t| t.multiplier_bps)
+    }
+}
+
+/// `DappConfig` as first deployed; only read by `migrate_dapp_config`.
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct LegacyDappConfig {
+    pub gatekeeper_network: Pubkey,
+    pub token_mint: Pubkey,
+    pub mint_authority_bump: u8,
+    pub exempt_address: Pubkey,
+    pub owner: Pubkey,
+    pub initialized: bool,
+    pub total_minted: u64,
+    pub max_supply: u64,
+    pub total_claims: u64,
+}
+
+impl LegacyDappConfig {
+    // 8 discriminator + 32 + 32 + 1 + 32 + 32 + 1 + 8 + 8 + 8 = 162 bytes
+    pub const LEN: usize = 8 + 32 + 32 + 1 + 32 + 32 + 1 + 8 + 8 + 8;
+
+    /// Current config with the deployed values kept and every newer field
+    /// set as `initialize_dapp` would.
+    pub fn into_config(self) -> DappConfig {
+        DappConfig {
+            gatekeeper_network: self.gatekeeper_network,
+            token_mint: self.token_mint,
+            mint_authority_bump: self.mint_authority_bump,
+            exempt_address: self.exempt_address,
+            owner: self.owner,
+            initialized: self.initialized,
+            total_minted: self.total_minted,
+            max_supply: self.max_supply,
+            reserved_supply: 0,
+            total_burned: 0,
+            burn_reopens_supply: false,
+            paused: false,
+            total_claims: self.total_claims,
+            user_rate_per_sec: USER_RATE_PER_SEC,
+            exempt_rate_per_sec: EXEMPT_RATE_PER_SEC,
+            treasury: self.owner,
+            slasher: self.owner,
+            streak_tiers: StreakTier::defaults(),
+            stake_boost_tiers: StakeBoostTier::defaults(),
+            timelock_enforced: true,
+        }
+    }
+}
+
+/// Claim-streak length and the faucet multiplier it earns.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct StreakTier {
+    pub min_days: u32,
+    pub multiplier_bps: u16,
+}
+
+impl StreakTier {
+    pub const LEN: usize = 4 + 2;
+
+    pub fn defaults() -> [StreakTier; MAX_STREAK_TIERS] {
+        [
+            StreakTier { min_days: 0, multiplier_bps: 10_000 },
+            StreakTier { min_days: 7, multiplier_bps: 11_000 },
+            StreakTier { min_days: 30, multiplier_bps: 12_500 },
+            StreakTier { min_days: 100, multiplier_bps: 15_000 },
+        ]
+    }
+}
+
+/// Stake (as counted by `voting_power`) and the faucet multiplier it earns.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct StakeBoostTier {
+    pub min_stake: u64,
+    pub multiplier_bps: u16,
+}
+
+impl StakeBoostTier {
+    pub const LEN: usize = 8 + 2;
+
+    pub fn defaults() -> [StakeBoostTier; MAX_STAKE_BOOST_TIERS] {
+        [
+            StakeBoostTier { min_stake: 0, multiplier_bps: 10_000 },
+            StakeBoostTier { min_stake: MIN_STAKE_LAMPORTS, multiplier_bps: 11_000 },
+            StakeBoostTier { min_stake: 10 * MIN_STAKE_LAMPORTS, multiplier_bps: 12_500 },
+            StakeBoostTier { min_stake: 100 * MIN_STAKE_LAMPORTS, multiplier_bps: 15_000 },
+        ]
+    }
+}
+
+#[account]
+pub struct MintAuthority {
+    pub bump: u8,
+}
+
+impl MintAuthority {
+    pub const LEN: usize = 8 + 1;
+}
+
+/// Per-user account tracking faucet claims.
+#[account]
+pub struct UserPda {
+    pub authority: Pubkey,
+    pub last_claimed_timestamp: i64,
+    pub claimed_so_far: u64,
+    pub inactive_flagged_at: i64,    // 0 unless the owner started a sweep notice
+    pub current_streak: u32,         // Consecutive UTC days with a claim
+    pub longest_streak: u32,
+    pub last_streak_day: i64,        // UTC day index (unix_timestamp / 86400) of the last claim
+}
+
+impl UserPda {
+    // 8 discriminator + 32 + 8 + 8 + 8 + 4 + 4 + 8 = 80 bytes
+    pub const LEN: usize = 8 + 32 + 8 + 8 + 8 + 4 + 4 + 8;
+
+    /// Count a claim at `now` towards the daily streak and return it. Repeat
+    /// claims on the same day keep the streak; a missed day resets it to 1.
+    pub fn record_claim_day(&mut self, now: i64) -> u32 {
+        let today = now.div_euclid(SECONDS_PER_DAY);
+        if today == self.last_streak_day {
+            return self.current_streak;
+        }
+        self.current_streak = if today == self.last_streak_day + 1 {
+            self.current_streak.saturating_add(1)
+        } else {
+            1
+        };
+        self.longest_streak = self.longest_streak.max(self.current_streak);
+        self.last_streak_day = today;
+        self.current_streak
+    }
+}
+
+/// Per-user staking account.
+#[account]
+pub struct StakeAccount {
+    pub authority: Pubkey,             // User who owns this stake
+    pub stake_amount: u64,             // Currently staked balance
+    pub last_reward_timestamp: i64,    // Last time rewards were calculated
+    pub pending_withdrawal_amount: u64,// Amount locked for withdrawal
+    pub withdraw_request_timestamp: i64,// When unstake was requested
+    pub reward_debt: u128,             // weight × pool rate at last settlement
+    pub lock_tier: u8,                 // Lock tier chosen at last stake
+    pub lock_end: i64,                 // No unstake (or penalty) before this time
+    pub boost_bps: u16,                // Reward weight multiplier from the lock tier
+    pub position_count: u64,           // Next StakePosition index
+    pub open_positions: u32,           // StakePositions not yet closed or merged
+    pub position_total: u64,           // Sum of open position amounts
+    pub position_weight: u64,          // Sum of open position weights
+    pub withdraw_authority: Pubkey,    // Optional delegate allowed to request unstake
+    pub vote_locked_until: i64,        // No withdrawals until the last voted proposal closes
+}
+
+impl StakeAccount {
+    // 8 discriminator + 32 + 8 + 8 + 8 + 8 + 16 + 1 + 8 + 2 + 8 + 4 + 8 + 8 + 32 + 8 = 167 bytes
+    pub const LEN: usize = 8 + 32 + 8 + 8 + 8 + 8 + 16 + 1 + 8 + 2 + 8 + 4 + 8 + 8 + 32 + 8;
+
+    /// Swap a position's old amount/weight for its new one in the totals.
+    pub fn track_position(
+        &mut self,
+        old_amount: u64,
+        old_weight: u64,
+        new_amount: u64,
+        new_weight: u64,
+    ) -> Result<()> {
+        self.position_total = self
+            .position_total
+            .checked_sub(old_amount)
+            .and_then(|v| v.checked_add(new_amount))
+            .ok_or(ErrorCode::ArithmeticError)?;
+        self.position_weight = self
+            .position_weight
+            .checked_sub(old_weight)
+            .and_then(|v| v.checked_add(new_weight))
+            .ok_or(ErrorCode::ArithmeticError)?;
+        Ok(())
+    }
+}
+
+/// `StakeAccount` as it was with per-user vaults and the fixed APY; only
+/// read by `migrate_stake_account`.
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct LegacyStakeAccount {
+    pub authority: Pubkey,
+    pub stake_amount: u64,
+    pub last_reward_timestamp: i64,
+    pub pending_withdrawal_amount: u64,
+    pub withdraw_request_timestamp: i64,
+}
+
+impl LegacyStakeAccount {
+    // 8 discriminator + 32 + 8 + 8 + 8 + 8 = 72 bytes
+    pub const LEN: usize = 8 + 32 + 8 + 8 + 8 + 8;
+
+    /// Current-layout account holding the pending withdrawal, plus the amount
+    /// still to be credited as stake. The old APY compounded into
+    /// `stake_amount` without ever being funded, so only what the vault
+    /// actually held carries over; the pending withdrawal is backed first.
+    pub fn into_stake_account(&self, vault_balance: u64, now: i64) -> (StakeAccount, u64) {
+        let pending = self.pending_withdrawal_amount.min(vault_balance);
+        let stake_acc = StakeAccount {
+            authority: self.authority,
+            stake_amount: 0,
+            last_reward_timestamp: now,
+            pending_withdrawal_amount: pending,
+            withdraw_request_timestamp: if pending > 0 { self.withdraw_request_timestamp } else { 0 },
+            reward_debt: 0,
+            lock_tier: 0,
+            lock_end: 0,
+            boost_bps: BPS_DENOMINATOR as u16,
+            position_count: 0,
+            open_positions: 0,
+            position_total: 0,
+            position_weight: 0,
+            withdraw_authority: Pubkey::default(),
+            vote_locked_until: 0,
+        };
+        (stake_acc, vault_balance - pending)
+    }
+}
+
+/// `UserPda` as it was before inactivity sweeps and claim streaks; only read
+/// by `migrate_user_pda`.
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct LegacyUserPda {
+    pub authority: Pubkey,
+    pub last_claimed_timestamp: i64,
+    pub claimed_so_far: u64,
+}
+
+impl LegacyUserPda {
+    // 8 discriminator + 32 + 8 + 8 = 56 bytes
+    pub const LEN: usize = 8 + 32 + 8 + 8;
+
+    pub fn into_user_pda(&self) -> UserPda {
+        UserPda {
+            authority: self.authority,
+            last_claimed_timestamp: self.last_claimed_timestamp,
+            claimed_so_far: self.claimed_so_far,
+            inactive_flagged_at: 0,
+            current_streak: 0,
+            longest_streak: 0,
+            last_streak_day: 0,
+        }
+    }
+}
+
+/// An independent stake owned by a user, at [STAKE_POSITION_SEED, user, index].
+#[account]
+pub struct StakePosition {
+    pub authority: Pubkey,             // User who owns this position
+    pub index: u64,                    // Per-user position index
+    pub amount: u64,                   // Staked balance
+    pub start_timestamp: i64,          // When the position was opened
+    pub last_reward_timestamp: i64,    // Last time rewards were settled
+    pub lock_tier: u8,                 // Lock tier chosen at open
+    pub lock_end: i64,                 // No close (or penalty) before this time
+    pub boost_bps: u16,                // Reward weight multiplier
+    pub reward_debt: u128,             // weight × pool rate at last settlement
+}
+
+impl StakePosition {
+    // 8 discriminator + 32 + 8 + 8 + 8 + 8 + 1 + 8 + 2 + 16 = 99 bytes
+    pub const LEN: usize = 8 + 32 + 8 + 8 + 8 + 8 + 1 + 8 + 2 + 16;
+}
+
+/// Reward bookkeeping shared by `StakeAccount` and `StakePosition`.
+pub trait Staked {
+    fn amount(&self) -> u64;
+    fn amount_mut(&mut self) -> &mut u64;
+    fn boost_bps(&self) -> u16;
+    fn reward_debt(&self) -> u128;
+    fn reward_debt_mut(&mut self) -> &mut u128;
+    fn lock_end(&self) -> i64;
+    /// Drop back to the unlocked tier 0.
+    fn clear_lock(&mut self);
+
+    /// Reward weight as counted in `pool.total_weight`: amount scaled by the
+    /// lock boost. After `lock_end` it stays boosted until `expire_lock` runs
+    /// (via `poke`, or the owner's next deposit).
+    fn weight(&self) -> Result<u64> {
+        boosted(self.amount(), self.boost_bps())
+    }
+
+    /// Weight as of `now`: the boost only counts while the lock is active.
+    fn effective_weight(&self, now: i64) -> Result<u64> {
+        if now < self.lock_end() {
+            self.weight()
+        } else {
+            Ok(self.amount())
+        }
+    }
+}
+
+impl Staked for StakeAccount {
+    fn amount(&self) -> u64 { self.stake_amount }
+    fn amount_mut(&mut self) -> &mut u64 { &mut self.stake_amount }
+    fn boost_bps(&self) -> u16 { self.boost_bps }
+    fn reward_debt(&self) -> u128 { self.reward_debt }
+    fn reward_debt_mut(&mut self) -> &mut u128 { &mut self.reward_debt }
+    fn lock_end(&self) -> i64 { self.lock_end }
+    fn clear_lock(&mut self) {
+        self.lock_tier = 0;
+        self.boost_bps = BPS_DENOMINATOR as u16;
+    }
+}
+
+impl Staked for StakePosition {
+    fn amount(&self) -> u64 { self.amount }
+    fn amount_mut(&mut self) -> &mut u64 { &mut self.amount }
+    fn boost_bps(&self) -> u16 { self.boost_bps }
+    fn reward_debt(&self) -> u128 { self.reward_debt }
+    fn reward_debt_mut(&mut self) -> &mut u128 { &mut self.reward_debt }
+    fn lock_end(&self) -> i64 { self.lock_end }
+    fn clear_lock(&mut self) {
+        self.lock_tier = 0;
+        self.boost_bps = BPS_DENOMINATOR as u16;
+    }
+}
+
+/// Privileged roles. The owner implicitly holds all of them.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Role {
+    Admin,        // Wallets, exempt address, subsystem setup, role grants
+    Pauser,       // Pause/resume issuance
+    ParamManager, // Rates, tiers, fees
+    Moderator,    // Inactive-user flagging and sweeps
+    Treasurer,    // Vesting and airdrop allocations
+    Validator,    // Slashing
+    Guardian,     // Cancelling queued admin operations (the owner included)
+}
+
+/// One role held by one key. PDA: [ROLE_SEED, role, grantee].
+#[account]
+pub struct RoleGrant {
+    pub role: Role,
+    pub grantee: Pubkey,
+    pub granted_by: Pubkey,
+    pub granted_at: i64,
+}
+
+impl RoleGrant {
+    // 8 discriminator + 1 + 32 + 32 + 8 = 81 bytes
+    pub const LEN: usize = 8 + 1 + 32 + 32 + 8;
+}
+
+/// Merkle airdrop. Claims are minted on demand; a bitmap records claimed leaves.
+#[account]
+pub struct Distributor {
+    pub id: u64,
+    pub root: [u8; 32],              // keccak root over (index, claimant, amount) leaves
+    pub total_amount: u64,           // Reserved under max_supply at creation
+    pub claimed_amount: u64,
+    pub expires_at: i64,             // Claims close; clawback opens
+    pub num_leaves: u32,
+    pub claimed_bitmap: Vec<u8>,     // One bit per leaf
+}
+
+impl Distributor {
+    /// Account size for `num_leaves` leaves.
+    // 8 discriminator + 8 + 32 + 8 + 8 + 8 + 4 + (4 + bitmap)
+    pub fn space(num_leaves: u32) -> usize {
+        8 + 8 + 32 + 8 + 8 + 8 + 4 + 4 + Self::bitmap_len(num_leaves)
+    }
+
+    pub fn bitmap_len(num_leaves: u32) -> usize {
+        (num_leaves as usize).div_ceil(8)
+    }
+
+    pub fn is_claimed(&self, index: u32) -> bool {
+        self.claimed_bitmap[index as usize / 8] & (1 << (index % 8)) != 0
+    }
+
+    pub fn set_claimed(&mut self, index: u32) {
+        self.claimed_bitmap[index as usize / 8] |= 1 << (index % 8);
+    }
+}
+
+/// Linear vesting with a cliff for a team or partner allocation.
+#[account]
+pub struct VestingSchedule {
+    pub beneficiary: Pubkey,
+    pub total: u64,                  // Tokens to vest; cut to the vested amount on revoke
+    pub released: u64,               // Already minted to the beneficiary
+    pub start: i64,
+    pub cliff_secs: i64,             // Nothing vests before start + cliff_secs
+    pub duration_secs: i64,          // Fully vested at start + duration_secs
+    pub revocable: bool,
+    pub revoked: bool,
+}
+
+impl VestingSchedule {
+    // 8 discriminator + 32 + 8 + 8 + 8 + 8 + 8 + 1 + 1 = 82 bytes
+    pub const LEN: usize = 8 + 32 + 8 + 8 + 8 + 8 + 8 + 1 + 1;
+
+    /// Amount vested by `now`, including what was already released.
+    pub fn vested_amount(&self, now: i64) -> Result<u64> {
+        if self.revoked {
+            return Ok(self.total);
+        }
+        let elapsed = now.saturating_sub(self.start);
+        if elapsed < self.cliff_secs {
+            return Ok(0);
+        }
+        if elapsed >= self.duration_secs {
+            return Ok(self.total);
+        }
+        u64::try_from(self.total as u128 * elapsed as u128 / self.duration_secs as u128)
+            .map_err(|_| error!(ErrorCode::ArithmeticError))
+    }
+}
+
+/// Lock duration and the reward boost it earns.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct LockTier {
+    pub duration_secs: i64,
+    pub boost_bps: u16,
+}
+
+impl LockTier {
+    pub const LEN: usize = 8 + 2;
+}
+
+/// Global staking pool. All stakes share one vault; rewards are emitted at
+/// `reward_rate` per second, split pro rata, until `reward_budget` runs out.
+#[account]
+pub struct StakePool {
+    pub bump: u8,                      // Bump for the pool PDA (vault authority)
+    pub vault: Pubkey,                 // Pool vault ATA
+    pub total_staked: u64,             // Sum of all stake_amount balances
+    pub reward_rate: u64,              // Reward microtokens emitted per second
+    pub reward_per_token_stored: u128, // Accumulated reward per staked token × REWARD_PRECISION
+    pub last_update_timestamp: i64,    // Last time reward_per_token_stored advanced
+    pub reward_budget: u64,            // Funded rewards not yet emitted
+    pub total_weight: u64,             // Sum of boosted stake weights
+    pub lock_tiers: [LockTier; MAX_LOCK_TIERS],
+    pub early_exit_penalty_bps: u16,   // 0 → unstaking before lock_end is disallowed
+
+    // Liquid staking (stCAL)
+    pub st_mint: Pubkey,               // stCAL receipt mint; default until enabled
+    pub liquid_principal: u64,         // Tokens backing stCAL, rewards compounded in
+    pub liquid_reward_debt: u128,      // liquid_principal × pool rate at last settlement
+
+    // Instant unstake
+    pub buffer_vault: Pubkey,          // Liquidity buffer; default until enabled
+    pub exit_fee_bps: u16,             // Fee on instant unstakes, retained by the buffer
+    pub buffer_owed: u64,              // Paid out by the buffer, still held in the pool vault
+}
+
+impl StakePool {
+    // 8 discriminator + 1 + 32 + 8 + 8 + 16 + 8 + 8 + 8 + 10 * 5 + 2 + 32 + 8 + 16
+    //   + 32 + 2 + 8 = 247 bytes
+    pub const LEN: usize = 8 + 1 + 32 + 8 + 8 + 16 + 8 + 8 + 8 + LockTier::LEN * MAX_LOCK_TIERS
+        + 2 + 32 + 8 + 16 + 32 + 2 + 8;
+
+    /// Advance `reward_per_token_stored` to `now`. Nothing is emitted while
+    /// the pool is empty, so the budget is not lost to idle periods.
+    pub fn update(&mut self, now: i64) -> Result<()> {
+        let elapsed = now.saturating_sub(self.last_update_timestamp).max(0) as u64;
+        self.last_update_timestamp = now;
+        if self.total_weight == 0 || elapsed == 0 {
+            return Ok(());
+        }
+
+        let emitted = self
+            .reward_rate
+            .saturating_mul(elapsed)
+            .min(self.reward_budget);
+        self.reward_budget -= emitted;
+        let per_token = (emitted as u128)
+            .checked_mul(REWARD_PRECISION)
+            .ok_or(ErrorCode::ArithmeticError)?
+            / self.total_weight as u128;
+        self.reward_per_token_stored = self
+            .reward_per_token_stored
+            .checked_add(per_token)
+            .ok_or(ErrorCode::ArithmeticError)?;
+        Ok(())
+    }
+
+    /// Compound the liquid sub-pool's rewards into `liquid_principal`, which
+    /// is what makes the stCAL exchange rate grow.
+    pub fn compound_liquid(&mut self, now: i64) -> Result<()> {
+        self.update(now)?;
+        let reward = self.pending_reward(self.liquid_principal, self.liquid_reward_debt)?;
+        self.adjust_liquid(reward, 0)
+    }
+
+    /// Split an instant withdrawal of `amount` into the payout and the exit
+    /// fee, which stays in the buffer. The whole amount is owed back to the
+    /// buffer from the pool vault. Fails if the buffer cannot cover the payout.
+    pub fn draw_buffer(&mut self, amount: u64, buffer_balance: u64) -> Result<(u64, u64)> {
+        let fee = ((amount as u128) * self.exit_fee_bps as u128 / BPS_DENOMINATOR as u128) as u64;
+        let payout = amount - fee;
+        require!(buffer_balance >= payout, ErrorCode::InsufficientBufferLiquidity);
+
+        self.buffer_owed = self
+            .buffer_owed
+            .checked_add(amount)
+            .ok_or(ErrorCode::ArithmeticError)?;
+        Ok((payout, fee))
+    }
+
+    /// stCAL minted for depositing `amount` while `st_supply` is outstanding;
+    /// 1:1 for the first deposit.
+    pub fn st_for_deposit(&self, amount: u64, st_supply: u64) -> Result<u64> {
+        if st_supply == 0 || self.liquid_principal == 0 {
+            return Ok(amount);
+        }
+        u64::try_from(amount as u128 * st_supply as u128 / self.liquid_principal as u128)
+            .map_err(|_| error!(ErrorCode::ArithmeticError))
+    }
+
+    /// Underlying owed for burning `st_amount` out of `st_supply` stCAL.
+    pub fn underlying_for_st(&self, st_amount: u64, st_supply: u64) -> Result<u64> {
+        require!(st_supply > 0, ErrorCode::NothingToUnstake);
+        u64::try_from(st_amount as u128 * self.liquid_principal as u128 / st_supply as u128)
+            .map_err(|_| error!(ErrorCode::ArithmeticError))
+    }
+
+    /// Add/remove liquid principal (unboosted) and re-settle its debt.
+    pub fn adjust_liquid(&mut self, add: u64, sub: u64) -> Result<()> {
+        self.liquid_principal = self
+            .liquid_principal
+            .checked_add(add)
+            .and_then(|v| v.checked_sub(sub))
+            .ok_or(ErrorCode::ArithmeticError)?;
+        self.total_staked = self
+            .total_staked
+            .checked_add(add)
+            .and_then(|v| v.checked_sub(sub))
+            .ok_or(ErrorCode::ArithmeticError)?;
+        self.total_weight = self
+            .total_weight
+            .checked_add(add)
+            .and_then(|v| v.checked_sub(sub))
+            .ok_or(ErrorCode::ArithmeticError)?;
+        self.liquid_reward_debt = self.reward_debt_for(self.liquid_principal)?;
+        Ok(())
+    }
+
+    /// Reward debt for a weight settled at the current rate.
+    pub fn reward_debt_for(&self, weight: u64) -> Result<u128> {
+        Ok((weight as u128)
+            .checked_mul(self.reward_per_token_stored)
+            .ok_or(ErrorCode::ArithmeticError)?
+            / REWARD_PRECISION)
+    }
+
+    /// Rewards accrued by `weight` since it was settled at `reward_debt`.
+    pub fn pending_reward(&self, weight: u64, reward_debt: u128) -> Result<u64> {
+        let accrued = self.reward_debt_for(weight)?.saturating_sub(reward_debt);
+        u64::try_from(accrued).map_err(|_| error!(ErrorCode::ArithmeticError))
+    }
+}
+
+/// Whitelisted protocol parameter changes, applied by governance or the owner.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub enum ParamChange {
+    SetRewardRate(u64),
+    SetUserRate(u64),
+    SetExemptRate(u64),
+    SetLockTier { index: u8, tier: LockTier },
+    SetEarlyExitPenalty(u16),
+    SetSlasher(Pubkey),
+    SetExitFee(u16),
+}
+
+impl ParamChange {
+    /// Serialized size reserved in `Proposal`, with room for new variants.
+    pub const MAX_LEN: usize = 64;
+
+    pub fn apply(&self, cfg: &mut DappConfig, pool: &mut StakePool) -> Result<()> {
+        match *self {
+            ParamChange::SetRewardRate(rate) => {
+                pool.update(Clock::get()?.unix_timestamp)?;
+                pool.reward_rate = rate;
+                msg!("Reward rate updated => {}/s", rate);
+            }
+            ParamChange::SetUserRate(rate) => {
+                cfg.user_rate_per_sec = rate;
+                msg!("User faucet rate updated => {}/s", rate);
+            }
+            ParamChange::SetExemptRate(rate) => {
+                cfg.exempt_rate_per_sec = rate;
+                msg!("Exempt faucet rate updated => {}/s", rate);
+            }
+            ParamChange::SetLockTier { index, tier } => {
+                require!(
+                    index > 0 && (index as usize) < MAX_LOCK_TIERS,
+                    ErrorCode::InvalidLockTier
+                );
+                require!(
+                    tier.duration_secs > 0 && tier.boost_bps as u64 >= BPS_DENOMINATOR,
+                    ErrorCode::InvalidLockTier
+                );
+                pool.lock_tiers[index as usize] = tier;
+                msg!(
+                    "Lock tier {} => duration={}s, boost={}bps",
+                    index,
+                    tier.duration_secs,
+                    tier.boost_bps
+                );
+            }
+            ParamChange::SetEarlyExitPenalty(bps) => {
+                require!(bps as u64 <= BPS_DENOMINATOR, ErrorCode::InvalidEarlyExitPenalty);
+                pool.early_exit_penalty_bps = bps;
+                msg!("Early exit penalty => {}bps", bps);
+            }
+            ParamChange::SetSlasher(slasher) => {
+                cfg.slasher = slasher;
+                msg!("Slasher updated => {}", slasher);
+            }
+            ParamChange::SetExitFee(bps) => {
+                require!(bps as u64 <= BPS_DENOMINATOR, ErrorCode::InvalidExitFee);
+                pool.exit_fee_bps = bps;
+                msg!("Instant unstake exit fee => {}bps", bps);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct SlashEntry {
+    pub timestamp: i64,
+    pub bps: u16,
+    pub amount: u64,
+    pub reason: u8,
+}
+
+impl SlashEntry {
+    pub const LEN: usize = 8 + 2 + 8 + 1;
+}
+
+/// Per-user slash log kept for appeals; the last SLASH_HISTORY_LEN entries.
+#[account]
+pub struct SlashHistory {
+    pub authority: Pubkey,             // Slashed user
+    pub count: u64,                    // Total slashes ever recorded
+    pub total_slashed: u64,            // Sum of all slashed amounts
+    pub entries: [SlashEntry; SLASH_HISTORY_LEN],
+}
+
+impl SlashHistory {
+    // 8 discriminator + 32 + 8 + 8 + 19 * 8 = 208 bytes
+    pub const LEN: usize = 8 + 32 + 8 + 8 + SlashEntry::LEN * SLASH_HISTORY_LEN;
+}
+
+/// Governance parameters and the proposal counter.
+#[account]
+pub struct GovernanceConfig {
+    pub bump: u8,
+    pub proposal_count: u64,           // Next proposal id
+    pub voting_period_secs: i64,       // Voting window per proposal
+    pub quorum: u64,                   // Minimum total voting power cast
+    pub threshold_bps: u16,            // Share of cast power that must vote for
+    pub min_proposal_power: u64,       // Voting power needed to propose
+}
+
+impl GovernanceConfig {
+    // 8 discriminator + 1 + 8 + 8 + 8 + 2 + 8 = 43 bytes
+    pub const LEN: usize = 8 + 1 + 8 + 8 + 8 + 2 + 8;
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum ProposalStatus {
+    Active,
+    Queued,                            // Passed; waiting in the timelock
+    Defeated,
+}
+
+#[account]
+pub struct Proposal {
+    pub id: u64,
+    pub proposer: Pubkey,
+    pub action: ParamChange,
+    pub created_at: i64,
+    pub voting_ends_at: i64,
+    pub votes_for: u64,
+    pub votes_against: u64,
+    pub status: ProposalStatus,
+}
+
+impl Proposal {
+    // 8 discriminator + 8 + 32 + 64 + 8 + 8 + 8 + 8 + 1 = 145 bytes
+    pub const LEN: usize = 8 + 8 + 32 + ParamChange::MAX_LEN + 8 + 8 + 8 + 8 + 1;
+}
+
+/// Admin actions that can be queued behind the timelock.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub enum AdminOp {
+    Param(ParamChange),
+    SetExempt(Pubkey),
+    SetTreasury(Pubkey),
+    SetGatekeeperNetwork(Pubkey),
+    SetStreakTier { index: u8, tier: StreakTier },
+    SetStakeBoostTier { index: u8, tier: StakeBoostTier },
+    SetBurnReopens(bool),
+    GrantRole { role: Role, grantee: Pubkey },
+    RevokeRole { role: Role, grantee: Pubkey },
+    DisableTimelock,
+}
+
+impl AdminOp {
+    // 1 variant tag + largest payload
+    pub const MAX_LEN: usize = 1 + ParamChange::MAX_LEN;
+
+    /// Apply a config change. Only `Param` needs the stake pool; role
+    /// changes touch grant accounts and are run by `execute_admin_op` itself.
+    pub fn apply(&self, cfg: &mut DappConfig, pool: Option<&mut StakePool>) -> Result<()> {
+        match *self {
+            AdminOp::Param(change) => {
+                change.apply(cfg, pool.ok_or(ErrorCode::MissingOpAccounts)?)?
+            }
+            AdminOp::SetExempt(exempt) => {
+                cfg.exempt_address = exempt;
+                msg!("Exempt address updated => new_exempt={}", exempt);
+            }
+            AdminOp::SetTreasury(treasury) => {
+                cfg.treasury = treasury;
+                msg!("Treasury updated => new_treasury={}", treasury);
+            }
+            AdminOp::SetGatekeeperNetwork(network) => {
+                cfg.gatekeeper_network = network;
+                msg!("Gatekeeper network updated => {}", network);
+            }
+            AdminOp::SetStreakTier { index, tier } => cfg.set_streak_tier(index, tier)?,
+            AdminOp::SetStakeBoostTier { index, tier } => cfg.set_stake_boost_tier(index, tier)?,
+            AdminOp::SetBurnReopens(enabled) => {
+                cfg.set_burn_reopens(enabled)?;
+                msg!("Burn reopens supply => {}", enabled);
+            }
+            AdminOp::GrantRole { .. } | AdminOp::RevokeRole { .. } => {
+                return err!(ErrorCode::MissingOpAccounts)
+            }
+            AdminOp::DisableTimelock => {
+                cfg.timelock_enforced = false;
+                msg!("Timelock disabled for admin changes");
+            }
+        }
+        Ok(())
+    }
+}
+
+/// An admin operation waiting out the timelock.
+#[account]
+pub struct QueuedOp {
+    pub id: u64,
+    pub op: AdminOp,
+    pub eta: i64,                    // Earliest execution time
+    pub queued_by: Pubkey,           // Paid for the account; gets the rent back
+    pub queued_at: i64,
+}
+
+impl QueuedOp {
+    // 8 discriminator + 8 + 65 + 8 + 32 + 8 = 129 bytes
+    pub const LEN: usize = 8 + 8 + AdminOp::MAX_LEN + 8 + 32 + 8;
+
+    /// Executable from `eta` until TIMELOCK_GRACE_PERIOD after it.
+    pub fn check_ready(&self, now: i64) -> Result<()> {
+        require!(now >= self.eta, ErrorCode::TimelockNotReady);
+        require!(
+            now <= self.eta.saturating_add(TIMELOCK_GRACE_PERIOD),
+            ErrorCode::TimelockExpired
+        );
+        Ok(())
+    }
+}
+
+/// One per (proposal, voter); its existence blocks double voting.
+#[account]
+pub struct VoteRecord {
+    pub voter: Pubkey,
+    pub proposal_id: u64,
+    pub support: bool,
+    pub power: u64,
+}
+
+impl VoteRecord {
+    // 8 discriminator + 32 + 8 + 1 + 8 = 57 bytes
+    pub const LEN: usize = 8 + 32 + 8 + 1 + 8;
+}
+
+// ------------------------------------------------------------------------------------------------
+//  ACCOUNTS FOR INSTRUCTIONS
+// ------------------------------------------------------------------------------------------------
+
+#[derive(Accounts)]
+pub struct InitializeDapp<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = DappConfig::LEN,
+        seeds = [b"dapp_config"],
+        bump
+    )]
+    pub dapp_config: Account<'info, DappConfig>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(constraint = program.programdata_address()? == Some(program_data.key()))]
+    pub program: Program<'info, crate::program::CalCoin>,
+
+    /// Upgrade authority must be the payer
+    #[account(
+        constraint = program_data.upgrade_authority_address == Some(payer.key())
+            @ ErrorCode::NotAuthorized
+    )]
+    pub program_data: Account<'info, ProgramData>,
+
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateDappConfig<'info> {
+    /// CHECK: still in the deployed layout; decoded in the handler
+    #[account(
+        mut,
+        owner = crate::ID,
+        seeds = [b"dapp_config"],
+        bump
+    )]
+    pub dapp_config: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeMint<'info> {
+    #[account(
+        mut,
+        seeds = [b"dapp_config"],
+        bump,
+    )]
+    pub dapp_config: Account<'info, DappConfig>,
+
+    /// PDA that will own & mint tokens
+    #[account(
+        init,
+        payer = payer,
+        space = MintAuthority::LEN,
+        seeds = [MINT_AUTHORITY_SEED],
+        bump
+    )]
+    pub mint_authority: Account<'info, MintAuthority>,
+
+    /// SPL-Token-2022 mint
+    #[account(
+        init,
+        payer = payer,
+        mint::decimals = 9,
+        mint::authority = mint_authority,
+        mint::freeze_authority = mint_authority,
+        owner = token_program.key(),
+    )]
+    pub mint_for_dapp: InterfaceAccount<'info, Mint>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(address = TOKEN_2022_PROGRAM_ID)]
+    pub token_program: Program<'info, Token2022>,
+
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+
+    /// Caller's RoleGrant; not needed when the caller is the owner
+    pub role_grant: Option<Account<'info, RoleGrant>>,
+}
+
+#[derive(Accounts)]
+pub struct RegisterUser<'info> {
+    #[account(
+        seeds = [b"dapp_config"],
+        bump
+    )]
+    pub dapp_config: Account<'info, DappConfig>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    pub gateway_token: UncheckedAccount<'info>,
+
+    #[account(
+        init,
+        payer = user,
+        space = UserPda::LEN,
+        seeds = [b"user_pda", user.key().as_ref()],
+        bump
+    )]
+    pub user_pda: Account<'info, UserPda>,
+
+    /// Initialize staking account for this user
+    #[account(
+        init,
+        payer = user,
+        space = StakeAccount::LEN,
+        seeds = [b"stake_account", user.key().as_ref()],
+        bump
+    )]
+    pub stake_account: Account<'info, StakeAccount>,
+
+    #[account(address = TOKEN_2022_PROGRAM_ID)]
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct Claim<'info> {
+    #[account(
+        mut,
+        seeds = [b"dapp_config"],
+        bump
+    )]
+    pub dapp_config: Account<'info, DappConfig>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    pub gateway_token: UncheckedAccount<'info>,
+
+    /// Created on the user's first claim
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = UserPda::LEN,
+        seeds = [b"user_pda", user.key().as_ref()],
+        bump
+    )]
+    pub user_pda: Account<'info, UserPda>,
+
+    /// Created alongside `user_pda` on the user's first claim; its
+    /// `stake_amount` sets the faucet stake boost
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = StakeAccount::LEN,
+        seeds = [b"stake_account", user.key().as_ref()],
+        bump
+    )]
+    pub stake_account: Account<'info, StakeAccount>,
+
+    #[account(
+        mut,
+        constraint = token_mint.key() == dapp_config.token_mint
+    )]
+    pub token_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        seeds = [MINT_AUTHORITY_SEED],
+        bump = dapp_config.mint_authority_bump,
+    )]
+    pub mint_authority: Account<'info, MintAuthority>,
+
+    #[account(
+        init_if_needed,
+        payer = user,
+        associated_token::mint = token_mint,
+        associated_token::authority = user
+    )]
+    pub user_ata: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(address = TOKEN_2022_PROGRAM_ID)]
+    pub token_program: Program<'info, Token2022>,
+
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimAndStake<'info> {
+    #[account(
+        mut,
+        seeds = [b"dapp_config"],
+        bump
+    )]
+    pub dapp_config: Account<'info, DappConfig>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    pub gateway_token: UncheckedAccount<'info>,
+
+    /// Created on the user's first claim
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = UserPda::LEN,
+        seeds = [b"user_pda", user.key().as_ref()],
+        bump
+    )]
+    pub user_pda: Account<'info, UserPda>,
+
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = StakeAccount::LEN,
+        seeds = [b"stake_account", user.key().as_ref()],
+        bump
+    )]
+    pub stake_account: Account<'info, StakeAccount>,
+
+    #[account(
+        mut,
+        seeds = [STAKE_POOL_SEED],
+        bump = stake_pool.bump
+    )]
+    pub stake_pool: Account<'info, StakePool>,
+
+    /// Pool vault receiving the minted stake
+    #[account(mut, address = stake_pool.vault)]
+    pub pool_vault: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = token_mint.key() == dapp_config.token_mint
+    )]
+    pub token_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        seeds = [MINT_AUTHORITY_SEED],
+        bump = dapp_config.mint_authority_bump,
+    )]
+    pub mint_authority: Account<'info, MintAuthority>,
+
+    #[account(address = TOKEN_2022_PROGRAM_ID)]
+    pub token_program: Program<'info, Token2022>,
+
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+#[instruction(id: u64, root: [u8; 32], total_amount: u64, num_leaves: u32)]
+pub struct CreateDistributor<'info> {
+    #[account(
+        mut,
+        seeds = [b"dapp_config"],
+        bump
+    )]
+    pub dapp_config: Account<'info, DappConfig>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        init,
+        payer = owner,
+        space = Distributor::space(num_leaves),
+        seeds = [DISTRIBUTOR_SEED, id.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub distributor: Account<'info, Distributor>,
+
+    pub system_program: Program<'info, System>,
+
+    /// Caller's RoleGrant; not needed when the caller is the owner
+    pub role_grant: Option<Account<'info, RoleGrant>>,
+}
+
+#[derive(Accounts)]
+#[instruction(id: u64)]
+pub struct ClaimAirdrop<'info> {
+    #[account(
+        mut,
+        seeds = [b"dapp_config"],
+        bump
+    )]
+    pub dapp_config: Account<'info, DappConfig>,
+
+    #[account(mut)]
+    pub claimant: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [DISTRIBUTOR_SEED, id.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub distributor: Account<'info, Distributor>,
+
+    #[account(
+        mut,
+        constraint = token_mint.key() == dapp_config.token_mint
+    )]
+    pub token_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        seeds = [MINT_AUTHORITY_SEED],
+        bump = dapp_config.mint_authority_bump,
+    )]
+    pub mint_authority: Account<'info, MintAuthority>,
+
+    #[account(
+        init_if_needed,
+        payer = claimant,
+        associated_token::mint = token_mint,
+        associated_token::authority = claimant
+    )]
+    pub claimant_ata: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(address = TOKEN_2022_PROGRAM_ID)]
+    pub token_program: Program<'info, Token2022>,
+
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(id: u64)]
+pub struct Clawback<'info> {
+    #[account(
+        mut,
+        seeds = [b"dapp_config"],
+        bump
+    )]
+    pub dapp_config: Account<'info, DappConfig>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        close = owner,
+        seeds = [DISTRIBUTOR_SEED, id.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub distributor: Account<'info, Distributor>,
+
+    /// Caller's RoleGrant; not needed when the caller is the owner
+    pub role_grant: Option<Account<'info, RoleGrant>>,
+}
+
+#[derive(Accounts)]
+#[instruction(beneficiary: Pubkey)]
+pub struct CreateVesting<'info> {
+    #[account(
+        mut,
+        seeds = [b"dapp_config"],
+        bump
+    )]
+    pub dapp_config: Account<'info, DappConfig>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        init,
+        payer = owner,
+        space = VestingSchedule::LEN,
+        seeds = [VESTING_SEED, beneficiary.as_ref()],
+        bump
+    )]
+    pub vesting: Account<'info, VestingSchedule>,
+
+    pub system_program: Program<'info, System>,
+
+    /// Caller's RoleGrant; not needed when the caller is the owner
+    pub role_grant: Option<Account<'info, RoleGrant>>,
+}
+
+#[derive(Accounts)]
+pub struct ReleaseVested<'info> {
+    #[account(
+        mut,
+        seeds = [b"dapp_config"],
+        bump
+    )]
+    pub dapp_config: Account<'info, DappConfig>,
+
+    #[account(mut)]
+    pub beneficiary: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [VESTING_SEED, beneficiary.key().as_ref()],
+        bump
+    )]
+    pub vesting: Account<'info, VestingSchedule>,
+
+    #[account(
+        mut,
+        constraint = token_mint.key() == dapp_config.token_mint
+    )]
+    pub token_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        seeds = [MINT_AUTHORITY_SEED],
+        bump = dapp_config.mint_authority_bump,
+    )]
+    pub mint_authority: Account<'info, MintAuthority>,
+
+    #[account(
+        init_if_needed,
+        payer = beneficiary,
+        associated_token::mint = token_mint,
+        associated_token::authority = beneficiary
+    )]
+    pub beneficiary_ata: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(address = TOKEN_2022_PROGRAM_ID)]
+    pub token_program: Program<'info, Token2022>,
+
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RevokeVesting<'info> {
+    #[account(
+        mut,
+        seeds = [b"dapp_config"],
+        bump
+    )]
+    pub dapp_config: Account<'info, DappConfig>,
+
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [VESTING_SEED, vesting.beneficiary.as_ref()],
+        bump
+    )]
+    pub vesting: Account<'info, VestingSchedule>,
+
+    /// Caller's RoleGrant; not needed when the caller is the owner
+    pub role_grant: Option<Account<'info, RoleGrant>>,
+}
+
+#[derive(Accounts)]
+pub struct DeregisterUser<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(
+        mut,
+        close = user,
+        seeds = [b"user_pda", user.key().as_ref()],
+        bump
+    )]
+    pub user_pda: Account<'info, UserPda>,
+
+    #[account(
+        mut,
+        close = user,
+        seeds = [b"stake_account", user.key().as_ref()],
+        bump
+    )]
+    pub stake_account: Account<'info, StakeAccount>,
+}
+
+#[derive(Accounts)]
+pub struct FlagInactiveUser<'info> {
+    #[account(
+        seeds = [b"dapp_config"],
+        bump
+    )]
+    pub dapp_config: Account<'info, DappConfig>,
+
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"user_pda", user_pda.authority.as_ref()],
+        bump
+    )]
+    pub user_pda: Account<'info, UserPda>,
+
+    #[account(
+        seeds = [b"stake_account", user_pda.authority.as_ref()],
+        bump
+    )]
+    pub stake_account: Account<'info, StakeAccount>,
+
+    /// Caller's RoleGrant; not needed when the caller is the owner
+    pub role_grant: Option<Account<'info, RoleGrant>>,
+}
+
+#[derive(Accounts)]
+pub struct SweepInactiveUser<'info> {
+    #[account(
+        seeds = [b"dapp_config"],
+        bump
+    )]
+    pub dapp_config: Account<'info, DappConfig>,
+
+    pub owner: Signer<'info>,
+
+    /// CHECK: the swept user's wallet; receives the reclaimed rent
+    #[account(mut, address = user_pda.authority)]
+    pub user: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        close = user,
+        seeds = [b"user_pda", user.key().as_ref()],
+        bump
+    )]
+    pub user_pda: Account<'info, UserPda>,
+
+    #[account(
+        mut,
+        close = user,
+        seeds = [b"stake_account", user.key().as_ref()],
+        bump
+    )]
+    pub stake_account: Account<'info, StakeAccount>,
+
+    /// Caller's RoleGrant; not needed when the caller is the owner
+    pub role_grant: Option<Account<'info, RoleGrant>>,
+}
+
+#[derive(Accounts)]
+pub struct SetExempt<'info> {
+    #[account(
+        mut,
+        seeds = [b"dapp_config"],
+        bump
+    )]
+    pub dapp_config: Account<'info, DappConfig>,
+
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    /// Caller's RoleGrant; not needed when the caller is the owner
+    pub role_grant: Option<Account<'info, RoleGrant>>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeStakePool<'info> {
+    #[account(
+        seeds = [b"dapp_config"],
+        bump
+    )]
+    pub dapp_config: Account<'info, DappConfig>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        init,
+        payer = owner,
+        space = StakePool::LEN,
+        seeds = [STAKE_POOL_SEED],
+        bump
+    )]
+    pub stake_pool: Account<'info, StakePool>,
+
+    /// Pool vault holding all staked tokens and the reward budget
+    #[account(
+        init,
+        payer = owner,
+        associated_token::mint = token_mint,
+        associated_token::authority = stake_pool,
+    )]
+    pub pool_vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// The mint for cal_coin
+    #[account(constraint = token_mint.key() == dapp_config.token_mint)]
+    pub token_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(address = TOKEN_2022_PROGRAM_ID)]
+    pub token_program: Program<'info, Token2022>,
+
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+
+    /// Caller's RoleGrant; not needed when the caller is the owner
+    pub role_grant: Option<Account<'info, RoleGrant>>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateStakePool<'info> {
+    #[account(
+        mut,
+        seeds = [b"dapp_config"],
+        bump
+    )]
+    pub dapp_config: Account<'info, DappConfig>,
+
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [STAKE_POOL_SEED],
+        bump = stake_pool.bump
+    )]
+    pub stake_pool: Account<'info, StakePool>,
+
+    /// Caller's RoleGrant; not needed when the caller is the owner
+    pub role_grant: Option<Account<'info, RoleGrant>>,
+}
+
+#[derive(Accounts)]
+pub struct FundRewards<'info> {
+    #[account(
+        seeds = [b"dapp_config"],
+        bump
+    )]
+    pub dapp_config: Account<'info, DappConfig>,
+
+    #[account(mut)]
+    pub funder: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [STAKE_POOL_SEED],
+        bump = stake_pool.bump
+    )]
+    pub stake_pool: Account<'info, StakePool>,
+
+    #[account(mut, address = stake_pool.vault)]
+    pub pool_vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// Funder's token account (source of rewards)
+    #[account(
+        mut,
+        associated_token::mint = token_mint,
+        associated_token::authority = funder
+    )]
+    pub funder_ata: InterfaceAccount<'info, TokenAccount>,
+
+    /// The mint for cal_coin
+    #[account(constraint = token_mint.key() == dapp_config.token_mint)]
+    pub token_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(address = TOKEN_2022_PROGRAM_ID)]
+    pub token_program: Program<'info, Token2022>,
+}
+
+#[derive(Accounts)]
+pub struct Stake<'info> {
+    #[account(
+        seeds = [b"dapp_config"],
+        bump
+    )]
+    pub dapp_config: Account<'info, DappConfig>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    /// CHECK: gateway pass, only verified when this call registers the user
+    pub gateway_token: UncheckedAccount<'info>,
+
+    /// Created on the user's first stake if they never registered
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = UserPda::LEN,
+        seeds = [b"user_pda", user.key().as_ref()],
+        bump
+    )]
+    pub user_pda: Account<'info, UserPda>,
+
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = StakeAccount::LEN,
+        seeds = [b"stake_account", user.key().as_ref()],
+        bump
+    )]
+    pub stake_account: Account<'info, StakeAccount>,
+
+    #[account(
+        mut,
+        seeds = [STAKE_POOL_SEED],
+        bump = stake_pool.bump
+    )]
+    pub stake_pool: Account<'info, StakePool>,
+
+    /// Pool vault holding staked tokens
+    #[account(mut, address = stake_pool.vault)]
+    pub pool_vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// User's token account (source of stake)
+    #[account(
+        mut,
+        associated_token::mint = token_mint,
+        associated_token::authority = user
+    )]
+    pub user_ata: InterfaceAccount<'info, TokenAccount>,
+
+    /// The mint for cal_coin
+    #[account(constraint = token_mint.key() == dapp_config.token_mint)]
+    pub token_mint: InterfaceAccount<'info, Mint>,
+
+    /// stCAL mint; required when staking liquid
+    #[account(mut, address = stake_pool.st_mint)]
+    pub st_mint: Option<InterfaceAccount<'info, Mint>>,
+
+    /// User's stCAL account (receives the receipt); required when staking liquid
+    #[account(
+        mut,
+        associated_token::mint = st_mint,
+        associated_token::authority = user
+    )]
+    pub user_st_ata: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(address = TOKEN_2022_PROGRAM_ID)]
+    pub token_program: Program<'info, Token2022>,
+
+    pub system_program: Program<'info, System>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct StakeFor<'info> {
+    #[account(
+        seeds = [b"dapp_config"],
+        bump
+    )]
+    pub dapp_config: Account<'info, DappConfig>,
+
+    #[account(mut)]
+    pub funder: Signer<'info>,
+
+    /// Beneficiary's staking account (must already be registered)
+    #[account(
+        mut,
+        seeds = [b"stake_account", stake_account.authority.as_ref()],
+        bump
+    )]
+    pub stake_account: Account<'info, StakeAccount>,
+
+    /// New position holding the deposit, at the beneficiary's next index
+    #[account(
+        init,
+        payer = funder,
+        space = StakePosition::LEN,
+        seeds = [
+            STAKE_POSITION_SEED,
+            stake_account.authority.as_ref(),
+            &stake_account.position_count.to_le_bytes()
+        ],
+        bump
+    )]
+    pub position: Account<'info, StakePosition>,
+
+    #[account(
+        mut,
+        seeds = [STAKE_POOL_SEED],
+        bump = stake_pool.bump
+    )]
+    pub stake_pool: Account<'info, StakePool>,
+
+    /// Pool vault holding staked tokens
+    #[account(mut, address = stake_pool.vault)]
+    pub pool_vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// Funder's token account (source of stake)
+    #[account(
+        mut,
+        associated_token::mint = token_mint,
+        associated_token::authority = funder
+    )]
+    pub funder_ata: InterfaceAccount<'info, TokenAccount>,
+
+    /// The mint for cal_coin
+    #[account(constraint = token_mint.key() == dapp_config.token_mint)]
+    pub token_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(address = TOKEN_2022_PROGRAM_ID)]
+    pub token_program: Program<'info, Token2022>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SetWithdrawAuthority<'info> {
+    pub user: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"stake_account", user.key().as_ref()],
+        bump
+    )]
+    pub stake_account: Account<'info, StakeAccount>,
+}
+
+#[derive(Accounts)]
+pub struct HarvestRewards<'info> {
+    #[account(
+        seeds = [b"dapp_config"],
+        bump
+    )]
+    pub dapp_config: Account<'info, DappConfig>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"stake_account", user.key().as_ref()],
+        bump
+    )]
+    pub stake_account: Account<'info, StakeAccount>,
+
+    #[account(
+        mut,
+        seeds = [STAKE_POOL_SEED],
+        bump = stake_pool.bump
+    )]
+    pub stake_pool: Account<'info, StakePool>,
+
+    /// Pool vault (reward source)
+    #[account(mut, address = stake_pool.vault)]
+    pub pool_vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// User's token account (destination for rewards)
+    #[account(
+        init_if_needed,
+        payer = user,
+        associated_token::mint = token_mint,
+        associated_token::authority = user
+    )]
+    pub user_ata: InterfaceAccount<'info, TokenAccount>,
+
+    /// The mint for cal_coin
+    #[account(constraint = token_mint.key() == dapp_config.token_mint)]
+    pub token_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(address = TOKEN_2022_PROGRAM_ID)]
+    pub token_program: Program<'info, Token2022>,
+
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct Compound<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"stake_account", user.key().as_ref()],
+        bump
+    )]
+    pub stake_account: Account<'info, StakeAccount>,
+
+    #[account(
+        mut,
+        seeds = [STAKE_POOL_SEED],
+        bump = stake_pool.bump
+    )]
+    pub stake_pool: Account<'info, StakePool>,
+}
+
+#[derive(Accounts)]
+pub struct Poke<'info> {
+    #[account(
+        mut,
+        seeds = [b"stake_account", stake_account.authority.as_ref()],
+        bump
+    )]
+    pub stake_account: Account<'info, StakeAccount>,
+
+    #[account(
+        mut,
+        seeds = [STAKE_POOL_SEED],
+        bump = stake_pool.bump
+    )]
+    pub stake_pool: Account<'info, StakePool>,
+}
+
+#[derive(Accounts)]
+#[instruction(index: u64)]
+pub struct PokePosition<'info> {
+    #[account(
+        mut,
+        seeds = [b"stake_account", stake_account.authority.as_ref()],
+        bump
+    )]
+    pub stake_account: Account<'info, StakeAccount>,
+
+    #[account(
+        mut,
+        seeds = [
+            STAKE_POSITION_SEED,
+            stake_account.authority.as_ref(),
+            &index.to_le_bytes()
+        ],
+        bump
+    )]
+    pub position: Account<'info, StakePosition>,
+
+    #[account(
+        mut,
+        seeds = [STAKE_POOL_SEED],
+        bump = stake_pool.bump
+    )]
+    pub stake_pool: Account<'info, StakePool>,
+}
+
+#[derive(Accounts)]
+pub struct OpenPosition<'info> {
+    #[account(
+        seeds = [b"dapp_config"],
+        bump
+    )]
+    pub dapp_config: Account<'info, DappConfig>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"stake_account", user.key().as_ref()],
+        bump
+    )]
+    pub stake_account: Account<'info, StakeAccount>,
+
+    #[account(
+        init,
+        payer = user,
+        space = StakePosition::LEN,
+        seeds = [
+            STAKE_POSITION_SEED,
+            user.key().as_ref(),
+            &stake_account.position_count.to_le_bytes()
+        ],
+        bump
+    )]
+    pub position: Account<'info, StakePosition>,
+
+    #[account(
+        mut,
+        seeds = [STAKE_POOL_SEED],
+        bump = stake_pool.bump
+    )]
+    pub stake_pool: Account<'info, StakePool>,
+
+    /// Pool vault holding staked tokens
+    #[account(mut, address = stake_pool.vault)]
+    pub pool_vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// User's token account (source of stake)
+    #[account(
+        mut,
+        associated_token::mint = token_mint,
+        associated_token::authority = user
+    )]
+    pub user_ata: InterfaceAccount<'info, TokenAccount>,
+
+    /// The mint for cal_coin
+    #[account(constraint = token_mint.key() == dapp_config.token_mint)]
+    pub token_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(address = TOKEN_2022_PROGRAM_ID)]
+    pub token_program: Program<'info, Token2022>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(index: u64)]
+pub struct TopUpPosition<'info> {
+    #[account(
+        seeds = [b"dapp_config"],
+        bump
+    )]
+    pub dapp_config: Account<'info, DappConfig>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"stake_account", user.key().as_ref()],
+        bump
+    )]
+    pub stake_account: Account<'info, StakeAccount>,
+
+    #[account(
+        mut,
+        seeds = [STAKE_POSITION_SEED, user.key().as_ref(), &index.to_le_bytes()],
+        bump
+    )]
+    pub position: Account<'info, StakePosition>,
+
+    #[account(
+        mut,
+        seeds = [STAKE_POOL_SEED],
+        bump = stake_pool.bump
+    )]
+    pub stake_pool: Account<'info, StakePool>,
+
+    /// Pool vault holding staked tokens
+    #[account(mut, address = stake_pool.vault)]
+    pub pool_vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// User's token account (source of stake)
+    #[account(
+        mut,
+        associated_token::mint = token_mint,
+        associated_token::authority = user
+    )]
+    pub user_ata: InterfaceAccount<'info, TokenAccount>,
+
+    /// The mint for cal_coin
+    #[account(constraint = token_mint.key() == dapp_config.token_mint)]
+    pub token_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(address = TOKEN_2022_PROGRAM_ID)]
+    pub token_program: Program<'info, Token2022>,
+}
+
+#[derive(Accounts)]
+#[instruction(source: u64, target: u64)]
+pub struct MergePositions<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"stake_account", user.key().as_ref()],
+        bump
+    )]
+    pub stake_account: Account<'info, StakeAccount>,
+
+    /// Closed after the merge; rent returns to the user
+    #[account(
+        mut,
+        close = user,
+        seeds = [STAKE_POSITION_SEED, user.key().as_ref(), &source.to_le_bytes()],
+        bump
+    )]
+    pub source_position: Account<'info, StakePosition>,
+
+    #[account(
+        mut,
+        seeds = [STAKE_POSITION_SEED, user.key().as_ref(), &target.to_le_bytes()],
+        bump
+    )]
+    pub target_position: Account<'info, StakePosition>,
+
+    #[account(
+        mut,
+        seeds = [STAKE_POOL_SEED],
+        bump = stake_pool.bump
+    )]
+    pub stake_pool: Account<'info, StakePool>,
+}
+
+#[derive(Accounts)]
+#[instruction(index: u64)]
+pub struct SplitPosition<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"stake_account", user.key().as_ref()],
+        bump
+    )]
+    pub stake_account: Account<'info, StakeAccount>,
+
+    #[account(
+        mut,
+        seeds = [STAKE_POSITION_SEED, user.key().as_ref(), &index.to_le_bytes()],
+        bump
+    )]
+    pub position: Account<'info, StakePosition>,
+
+    #[account(
+        init,
+        payer = user,
+        space = StakePosition::LEN,
+        seeds = [
+            STAKE_POSITION_SEED,
+            user.key().as_ref(),
+            &stake_account.position_count.to_le_bytes()
+        ],
+        bump
+    )]
+    pub new_position: Account<'info, StakePosition>,
+
+    #[account(
+        mut,
+        seeds = [STAKE_POOL_SEED],
+        bump = stake_pool.bump
+    )]
+    pub stake_pool: Account<'info, StakePool>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(index: u64)]
+pub struct ClosePosition<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"stake_account", user.key().as_ref()],
+        bump
+    )]
+    pub stake_account: Account<'info, StakeAccount>,
+
+    #[account(
+        mut,
+        close = user,
+        seeds = [STAKE_POSITION_SEED, user.key().as_ref(), &index.to_le_bytes()],
+        bump
+    )]
+    pub position: Account<'info, StakePosition>,
+
+    #[account(
+        mut,
+        seeds = [STAKE_POOL_SEED],
+        bump = stake_pool.bump
+    )]
+    pub stake_pool: Account<'info, StakePool>,
+}
+
+#[derive(Accounts)]
+pub struct RequestUnstake<'info> {
+    #[account(
+        seeds = [b"dapp_config"],
+        bump
+    )]
+    pub dapp_config: Account<'info, DappConfig>,
+
+    /// Stake owner or its withdraw authority
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"stake_account", stake_account.authority.as_ref()],
+        bump,
+        constraint = user.key() == stake_account.authority
+            || user.key() == stake_account.withdraw_authority
+            @ ErrorCode::NotAuthorized
+    )]
+    pub stake_account: Account<'info, StakeAccount>,
+
+    #[account(
+        mut,
+        seeds = [STAKE_POOL_SEED],
+        bump = stake_pool.bump
+    )]
+    pub stake_pool: Account<'info, StakePool>,
+
+    /// stCAL mint; required when redeeming stCAL
+    #[account(mut, address = stake_pool.st_mint)]
+    pub st_mint: Option<InterfaceAccount<'info, Mint>>,
+
+    /// Owner's stCAL account to burn from; required when redeeming stCAL
+    #[account(
+        mut,
+        associated_token::mint = st_mint,
+        associated_token::authority = user
+    )]
+    pub user_st_ata: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    /// Required when redeeming stCAL
+    #[account(address = TOKEN_2022_PROGRAM_ID)]
+    pub token_program: Option<Program<'info, Token2022>>,
+
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeBuffer<'info> {
+    #[account(
+        mut,
+        seeds = [b"dapp_config"],
+        bump
+    )]
+    pub dapp_config: Account<'info, DappConfig>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [STAKE_POOL_SEED],
+        bump = stake_pool.bump
+    )]
+    pub stake_pool: Account<'info, StakePool>,
+
+    /// Liquidity buffer for instant unstakes, owned by the pool PDA
+    #[account(
+        init,
+        payer = owner,
+        seeds = [BUFFER_VAULT_SEED],
+        bump,
+        token::mint = token_mint,
+        token::authority = stake_pool,
+    )]
+    pub buffer_vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// The mint for cal_coin
+    #[account(constraint = token_mint.key() == dapp_config.token_mint)]
+    pub token_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(address = TOKEN_2022_PROGRAM_ID)]
+    pub token_program: Program<'info, Token2022>,
+
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+
+    /// Caller's RoleGrant; not needed when the caller is the owner
+    pub role_grant: Option<Account<'info, RoleGrant>>,
+}
+
+#[derive(Accounts)]
+pub struct FundBuffer<'info> {
+    #[account(
+        seeds = [b"dapp_config"],
+        bump
+    )]
+    pub dapp_config: Account<'info, DappConfig>,
+
+    #[account(mut)]
+    pub funder: Signer<'info>,
+
+    #[account(
+        seeds = [STAKE_POOL_SEED],
+        bump = stake_pool.bump
+    )]
+    pub stake_pool: Account<'info, StakePool>,
+
+    #[account(mut, address = stake_pool.buffer_vault)]
+    pub buffer_vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// Funder's token account (source of liquidity)
+    #[account(
+        mut,
+        associated_token::mint = token_mint,
+        associated_token::authority = funder
+    )]
+    pub funder_ata: InterfaceAccount<'info, TokenAccount>,
+
+    /// The mint for cal_coin
+    #[account(constraint = token_mint.key() == dapp_config.token_mint)]
+    pub token_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(address = TOKEN_2022_PROGRAM_ID)]
+    pub token_program: Program<'info, Token2022>,
+}
+
+#[derive(Accounts)]
+pub struct InstantUnstake<'info> {
+    #[account(
+        seeds = [b"dapp_config"],
+        bump
+    )]
+    pub dapp_config: Account<'info, DappConfig>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"stake_account", user.key().as_ref()],
+        bump
+    )]
+    pub stake_account: Account<'info, StakeAccount>,
+
+    #[account(
+        mut,
+        seeds = [STAKE_POOL_SEED],
+        bump = stake_pool.bump
+    )]
+    pub stake_pool: Account<'info, StakePool>,
+
+    /// Liquidity buffer paying the withdrawal
+    #[account(mut, address = stake_pool.buffer_vault)]
+    pub buffer_vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// User's token account (destination for withdrawal)
+    #[account(
+        mut,
+        associated_token::mint = token_mint,
+        associated_token::authority = user
+    )]
+    pub user_ata: InterfaceAccount<'info, TokenAccount>,
+
+    /// The mint for cal_coin
+    #[account(constraint = token_mint.key() == dapp_config.token_mint)]
+    pub token_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(address = TOKEN_2022_PROGRAM_ID)]
+    pub token_program: Program<'info, Token2022>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeLiquidStaking<'info> {
+    #[account(
+        seeds = [b"dapp_config"],
+        bump
+    )]
+    pub dapp_config: Account<'info, DappConfig>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [STAKE_POOL_SEED],
+        bump = stake_pool.bump
+    )]
+    pub stake_pool: Account<'info, StakePool>,
+
+    /// stCAL receipt mint, minted and burned under the pool PDA
+    #[account(
+        init,
+        payer = owner,
+        seeds = [ST_MINT_SEED],
+        bump,
+        mint::decimals = 9,
+        mint::authority = stake_pool,
+        mint::freeze_authority = stake_pool,
+        owner = token_program.key(),
+    )]
+    pub st_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(address = TOKEN_2022_PROGRAM_ID)]
+    pub token_program: Program<'info, Token2022>,
+
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+
+    /// Caller's RoleGrant; not needed when the caller is the owner
+    pub role_grant: Option<Account<'info, RoleGrant>>,
+}
+
+#[derive(Accounts)]
+pub struct CancelUnstake<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"stake_account", user.key().as_ref()],
+        bump
+    )]
+    pub stake_account: Account<'info, StakeAccount>,
 
-#[derive(Accounts)]
-pub struct InitializeMint<'info> {
     #[account(
         mut,
+        seeds = [STAKE_POOL_SEED],
+        bump = stake_pool.bump
+    )]
+    pub stake_pool: Account<'info, StakePool>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimStake<'info> {
+    #[account(
         seeds = [b"dapp_config"],
-        bump,
+        bump
     )]
     pub dapp_config: Account<'info, DappConfig>,
 
-    /// PDA that will own & mint tokens
+    #[account(mut)]
+    pub user: Signer<'info>,
+
     #[account(
-        init,
-        payer = payer,
-        space = MintAuthority::LEN,
-        seeds = [b"mint_authority"],
+        mut,
+        seeds = [b"stake_account", user.key().as_ref()],
         bump
     )]
-    pub mint_authority: Account<'info, MintAuthority>,
+    pub stake_account: Account<'info, StakeAccount>,
 
-    /// SPL-Token-2022 mint
     #[account(
-        init,
-        payer = payer,
-        mint::decimals = 9,
-        mint::authority = mint_authority,
-        mint::freeze_authority = mint_authority,
-        owner = token_program.key(),
+        mut,
+        seeds = [STAKE_POOL_SEED],
+        bump = stake_pool.bump
     )]
-    pub mint_for_dapp: InterfaceAccount<'info, Mint>,
+    pub stake_pool: Account<'info, StakePool>,
 
-    #[account(mut)]
-    pub payer: Signer<'info>,
+    /// Pool vault token account
+    #[account(mut, address = stake_pool.vault)]
+    pub pool_vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// Instant-unstake buffer; required while `buffer_owed` is non-zero,
+    /// which is swept into it
+    #[account(mut, address = stake_pool.buffer_vault)]
+    pub buffer_vault: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    /// User's token account (destination for withdrawal)
+    #[account(
+        mut,
+        associated_token::mint = token_mint,
+        associated_token::authority = user
+    )]
+    pub user_ata: InterfaceAccount<'info, TokenAccount>,
+
+    /// The mint for cal_coin
+    #[account(constraint = token_mint.key() == dapp_config.token_mint)]
+    pub token_mint: InterfaceAccount<'info, Mint>,
 
     #[account(address = TOKEN_2022_PROGRAM_ID)]
     pub token_program: Program<'info, Token2022>,
 
+    pub associated_token_program: Program<'info, AssociatedToken>,
     pub system_program: Program<'info, System>,
     pub rent: Sysvar<'info, Rent>,
 }
 
 #[derive(Accounts)]
-pub struct RegisterUser<'info> {
+pub struct MigrateStakeAccount<'info> {
     #[account(
         seeds = [b"dapp_config"],
         bump
@@ -532,34 +5008,98 @@ pub struct RegisterUser<'info> {
     #[account(mut)]
     pub user: Signer<'info>,
 
-    pub gateway_token: UncheckedAccount<'info>,
+    /// CHECK: still in the 72-byte legacy layout; decoded in the handler
+    #[account(
+        mut,
+        owner = crate::ID,
+        seeds = [b"stake_account", user.key().as_ref()],
+        bump
+    )]
+    pub stake_account: UncheckedAccount<'info>,
 
     #[account(
-        init,
-        payer = user,
-        space = UserPda::LEN,
+        mut,
+        seeds = [STAKE_POOL_SEED],
+        bump = stake_pool.bump
+    )]
+    pub stake_pool: Account<'info, StakePool>,
+
+    /// Pool vault receiving the legacy balance
+    #[account(mut, address = stake_pool.vault)]
+    pub pool_vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// CHECK: PDA that owned the user's legacy vault; signs the transfer out
+    #[account(
+        seeds = [LEGACY_STAKE_VAULT_SEED, user.key().as_ref()],
+        bump
+    )]
+    pub legacy_vault_authority: UncheckedAccount<'info>,
+
+    /// Legacy per-user vault; omitted if the user never staked
+    #[account(
+        mut,
+        associated_token::mint = token_mint,
+        associated_token::authority = legacy_vault_authority,
+    )]
+    pub legacy_vault: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    /// The mint for cal_coin
+    #[account(constraint = token_mint.key() == dapp_config.token_mint)]
+    pub token_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(address = TOKEN_2022_PROGRAM_ID)]
+    pub token_program: Program<'info, Token2022>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateUserPda<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    /// CHECK: still in the 56-byte legacy layout; decoded in the handler
+    #[account(
+        mut,
+        owner = crate::ID,
         seeds = [b"user_pda", user.key().as_ref()],
         bump
     )]
-    pub user_pda: Account<'info, UserPda>,
+    pub user_pda: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(role: Role, grantee: Pubkey)]
+pub struct GrantRole<'info> {
+    #[account(
+        seeds = [b"dapp_config"],
+        bump
+    )]
+    pub dapp_config: Account<'info, DappConfig>,
+
+    #[account(mut)]
+    pub admin: Signer<'info>,
 
-    /// Initialize staking account for this user
     #[account(
         init,
-        payer = user,
-        space = StakeAccount::LEN,
-        seeds = [b"stake_account", user.key().as_ref()],
+        payer = admin,
+        space = RoleGrant::LEN,
+        seeds = [ROLE_SEED, &[role as u8], grantee.as_ref()],
         bump
     )]
-    pub stake_account: Account<'info, StakeAccount>,
+    pub new_grant: Account<'info, RoleGrant>,
+
+    /// Caller's admin RoleGrant; not needed when the caller is the owner
+    pub role_grant: Option<Account<'info, RoleGrant>>,
 
-    #[account(address = TOKEN_2022_PROGRAM_ID)]
     pub system_program: Program<'info, System>,
-    pub rent: Sysvar<'info, Rent>,
 }
 
 #[derive(Accounts)]
-pub struct Claim<'info> {
+#[instruction(role: Role, grantee: Pubkey)]
+pub struct RevokeRole<'info> {
     #[account(
         seeds = [b"dapp_config"],
         bump
@@ -567,16 +5107,38 @@ pub struct Claim<'info> {
     pub dapp_config: Account<'info, DappConfig>,
 
     #[account(mut)]
-    pub user: Signer<'info>,
+    pub admin: Signer<'info>,
 
-    pub gateway_token: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        close = admin,
+        seeds = [ROLE_SEED, &[role as u8], grantee.as_ref()],
+        bump
+    )]
+    pub revoked_grant: Account<'info, RoleGrant>,
+
+    /// Caller's admin RoleGrant; not needed when the caller is the owner
+    pub role_grant: Option<Account<'info, RoleGrant>>,
+}
 
+#[derive(Accounts)]
+pub struct BurnTokens<'info> {
     #[account(
         mut,
-        seeds = [b"user_pda", user.key().as_ref()],
+        seeds = [b"dapp_config"],
         bump
     )]
-    pub user_pda: Account<'info, UserPda>,
+    pub dapp_config: Account<'info, DappConfig>,
+
+    /// Holder of the tokens being burned (the treasury for buyback_burn)
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        associated_token::mint = token_mint,
+        associated_token::authority = authority
+    )]
+    pub authority_ata: InterfaceAccount<'info, TokenAccount>,
 
     #[account(
         mut,
@@ -584,97 +5146,240 @@ pub struct Claim<'info> {
     )]
     pub token_mint: InterfaceAccount<'info, Mint>,
 
+    #[account(address = TOKEN_2022_PROGRAM_ID)]
+    pub token_program: Program<'info, Token2022>,
+}
+
+#[derive(Accounts)]
+pub struct NetSupply<'info> {
     #[account(
-        seeds = [b"mint_authority"],
-        bump = dapp_config.mint_authority_bump,
+        seeds = [b"dapp_config"],
+        bump
     )]
-    pub mint_authority: Account<'info, MintAuthority>,
+    pub dapp_config: Account<'info, DappConfig>,
+
+    /// The mint for cal_coin
+    #[account(constraint = token_mint.key() == dapp_config.token_mint)]
+    pub token_mint: InterfaceAccount<'info, Mint>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateFaucet<'info> {
+    #[account(
+        mut,
+        seeds = [b"dapp_config"],
+        bump
+    )]
+    pub dapp_config: Account<'info, DappConfig>,
+
+    pub owner: Signer<'info>,
+
+    /// Caller's RoleGrant; not needed when the caller is the owner
+    pub role_grant: Option<Account<'info, RoleGrant>>,
+}
+
+#[derive(Accounts)]
+pub struct SetTreasury<'info> {
+    #[account(
+        mut,
+        seeds = [b"dapp_config"],
+        bump
+    )]
+    pub dapp_config: Account<'info, DappConfig>,
+
+    pub owner: Signer<'info>,
+
+    /// Caller's RoleGrant; not needed when the caller is the owner
+    pub role_grant: Option<Account<'info, RoleGrant>>,
+}
+
+#[derive(Accounts)]
+pub struct Slash<'info> {
+    #[account(
+        mut,
+        seeds = [b"dapp_config"],
+        bump
+    )]
+    pub dapp_config: Account<'info, DappConfig>,
+
+    #[account(mut)]
+    pub slasher: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"stake_account", stake_account.authority.as_ref()],
+        bump
+    )]
+    pub stake_account: Account<'info, StakeAccount>,
 
     #[account(
         init_if_needed,
-        payer = user,
+        payer = slasher,
+        space = SlashHistory::LEN,
+        seeds = [SLASH_HISTORY_SEED, stake_account.authority.as_ref()],
+        bump
+    )]
+    pub slash_history: Account<'info, SlashHistory>,
+
+    #[account(
+        mut,
+        seeds = [STAKE_POOL_SEED],
+        bump = stake_pool.bump
+    )]
+    pub stake_pool: Account<'info, StakePool>,
+
+    #[account(mut, address = stake_pool.vault)]
+    pub pool_vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// Treasury token account; required unless burning
+    #[account(
+        mut,
         associated_token::mint = token_mint,
-        associated_token::authority = user
+        associated_token::authority = dapp_config.treasury
     )]
-    pub user_ata: InterfaceAccount<'info, TokenAccount>,
+    pub treasury_ata: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    /// The mint for cal_coin (mutable for burns)
+    #[account(
+        mut,
+        constraint = token_mint.key() == dapp_config.token_mint
+    )]
+    pub token_mint: InterfaceAccount<'info, Mint>,
 
     #[account(address = TOKEN_2022_PROGRAM_ID)]
     pub token_program: Program<'info, Token2022>,
 
-    pub associated_token_program: Program<'info, AssociatedToken>,
     pub system_program: Program<'info, System>,
-    pub rent: Sysvar<'info, Rent>,
+
+    /// Caller's RoleGrant; not needed when the caller is the owner
+    pub role_grant: Option<Account<'info, RoleGrant>>,
 }
 
 #[derive(Accounts)]
-pub struct SetExempt<'info> {
+pub struct InitializeGovernance<'info> {
     #[account(
-        mut,
         seeds = [b"dapp_config"],
         bump
     )]
     pub dapp_config: Account<'info, DappConfig>,
 
     #[account(mut)]
-    pub current_exempt: Signer<'info>,
+    pub owner: Signer<'info>,
+
+    #[account(
+        init,
+        payer = owner,
+        space = GovernanceConfig::LEN,
+        seeds = [GOVERNANCE_SEED],
+        bump
+    )]
+    pub governance: Account<'info, GovernanceConfig>,
+
+    pub system_program: Program<'info, System>,
+
+    /// Caller's RoleGrant; not needed when the caller is the owner
+    pub role_grant: Option<Account<'info, RoleGrant>>,
 }
 
 #[derive(Accounts)]
-pub struct Stake<'info> {
+pub struct CreateProposal<'info> {
     #[account(
-        seeds = [b"dapp_config"],
+        mut,
+        seeds = [GOVERNANCE_SEED],
+        bump = governance.bump
+    )]
+    pub governance: Account<'info, GovernanceConfig>,
+
+    #[account(mut)]
+    pub proposer: Signer<'info>,
+
+    /// Source of the proposer's voting power
+    #[account(
+        seeds = [b"stake_account", proposer.key().as_ref()],
         bump
     )]
-    pub dapp_config: Account<'info, DappConfig>,
+    pub stake_account: Account<'info, StakeAccount>,
+
+    #[account(
+        init,
+        payer = proposer,
+        space = Proposal::LEN,
+        seeds = [PROPOSAL_SEED, &governance.proposal_count.to_le_bytes()],
+        bump
+    )]
+    pub proposal: Account<'info, Proposal>,
 
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(proposal_id: u64)]
+pub struct CastVote<'info> {
     #[account(mut)]
-    pub user: Signer<'info>,
+    pub voter: Signer<'info>,
+
+    /// Source of the voter's voting power; vote-locked until voting ends
+    #[account(
+        mut,
+        seeds = [b"stake_account", voter.key().as_ref()],
+        bump
+    )]
+    pub stake_account: Account<'info, StakeAccount>,
+
+    #[account(
+        mut,
+        seeds = [PROPOSAL_SEED, &proposal_id.to_le_bytes()],
+        bump
+    )]
+    pub proposal: Account<'info, Proposal>,
 
     #[account(
-        mut,
-        seeds = [b"stake_account", user.key().as_ref()],
+        init,
+        payer = voter,
+        space = VoteRecord::LEN,
+        seeds = [VOTE_SEED, &proposal_id.to_le_bytes(), voter.key().as_ref()],
         bump
     )]
-    pub stake_account: Account<'info, StakeAccount>,
+    pub vote_record: Account<'info, VoteRecord>,
 
-    /// Vault PDA associated token account for holding staked tokens
-    #[account(
-            init_if_needed,
-            payer = user,
-            associated_token::mint = token_mint,
-            associated_token::authority = vault_authority,
-        )]
-    pub stake_vault: InterfaceAccount<'info, TokenAccount>,
+    pub system_program: Program<'info, System>,
+}
 
-    /// PDA that signs for transferring from vault
+#[derive(Accounts)]
+#[instruction(proposal_id: u64)]
+pub struct FinalizeProposal<'info> {
     #[account(
-        seeds = [STAKE_VAULT_SEED, user.key().as_ref()],
-        bump
+        seeds = [GOVERNANCE_SEED],
+        bump = governance.bump
     )]
-    pub vault_authority: AccountInfo<'info>,
+    pub governance: Account<'info, GovernanceConfig>,
 
-    /// User's token account (source of stake)
     #[account(
         mut,
-        associated_token::mint = token_mint,
-        associated_token::authority = user
+        seeds = [PROPOSAL_SEED, &proposal_id.to_le_bytes()],
+        bump
     )]
-    pub user_ata: InterfaceAccount<'info, TokenAccount>,
+    pub proposal: Account<'info, Proposal>,
 
-    /// The mint for cal_coin
-    #[account(constraint = token_mint.key() == dapp_config.token_mint)]
-    pub token_mint: InterfaceAccount<'info, Mint>,
+    #[account(mut)]
+    pub finalizer: Signer<'info>,
 
-    #[account(address = TOKEN_2022_PROGRAM_ID)]
-    pub token_program: Program<'info, Token2022>,
+    /// Needed only if the proposal passed
+    #[account(
+        init,
+        payer = finalizer,
+        space = QueuedOp::LEN,
+        seeds = [QUEUED_OP_SEED, &(PROPOSAL_OP_ID_BIT | proposal_id).to_le_bytes()],
+        bump
+    )]
+    pub queued_op: Option<Account<'info, QueuedOp>>,
 
     pub system_program: Program<'info, System>,
-    pub associated_token_program: Program<'info, AssociatedToken>,
-    pub rent: Sysvar<'info, Rent>,
 }
 
 #[derive(Accounts)]
-pub struct RequestUnstake<'info> {
+#[instruction(id: u64)]
+pub struct QueueAdminOp<'info> {
     #[account(
         seeds = [b"dapp_config"],
         bump
@@ -682,80 +5387,140 @@ pub struct RequestUnstake<'info> {
     pub dapp_config: Account<'info, DappConfig>,
 
     #[account(mut)]
-    pub user: Signer<'info>,
-
-    #[account(
-        mut,
-        seeds = [b"stake_account", user.key().as_ref()],
-        bump
-    )]
-    pub stake_account: Account<'info, StakeAccount>,
+    pub admin: Signer<'info>,
 
-    /// Vault PDA authority (for potential later transfers)
     #[account(
-        seeds = [STAKE_VAULT_SEED, user.key().as_ref()],
+        init,
+        payer = admin,
+        space = QueuedOp::LEN,
+        seeds = [QUEUED_OP_SEED, &id.to_le_bytes()],
         bump
     )]
-    pub vault_authority: AccountInfo<'info>,
+    pub queued_op: Account<'info, QueuedOp>,
 
-    #[account(address = TOKEN_2022_PROGRAM_ID)]
-    pub token_program: Program<'info, Token2022>,
+    /// Caller's RoleGrant; not needed when the caller is the owner
+    pub role_grant: Option<Account<'info, RoleGrant>>,
 
     pub system_program: Program<'info, System>,
-    pub rent: Sysvar<'info, Rent>,
 }
 
 #[derive(Accounts)]
-pub struct ClaimStake<'info> {
+#[instruction(id: u64)]
+pub struct ExecuteAdminOp<'info> {
     #[account(
+        mut,
         seeds = [b"dapp_config"],
         bump
     )]
     pub dapp_config: Account<'info, DappConfig>,
 
     #[account(mut)]
-    pub user: Signer<'info>,
+    pub executor: Signer<'info>,
 
     #[account(
         mut,
-        seeds = [b"stake_account", user.key().as_ref()],
+        close = queued_by,
+        seeds = [QUEUED_OP_SEED, &id.to_le_bytes()],
         bump
     )]
-    pub stake_account: Account<'info, StakeAccount>,
+    pub queued_op: Account<'info, QueuedOp>,
 
-    /// Vault PDA token account
+    /// CHECK: receives the queued op's rent
+    #[account(mut, address = queued_op.queued_by)]
+    pub queued_by: UncheckedAccount<'info>,
+
+    /// Only needed for `AdminOp::Param`
     #[account(
         mut,
-        associated_token::mint = token_mint,
-        associated_token::authority = vault_authority,
+        seeds = [STAKE_POOL_SEED],
+        bump = stake_pool.bump
     )]
-    pub stake_vault: InterfaceAccount<'info, TokenAccount>,
+    pub stake_pool: Option<Account<'info, StakePool>>,
+
+    /// CHECK: the RoleGrant PDA a GrantRole/RevokeRole op creates or closes;
+    /// checked in `open_role_grant` / `close_role_grant`
+    #[account(mut)]
+    pub grant: Option<UncheckedAccount<'info>>,
 
-    /// Vault PDA authority
+    pub system_program: Option<Program<'info, System>>,
+}
+
+#[derive(Accounts)]
+#[instruction(id: u64)]
+pub struct CancelAdminOp<'info> {
     #[account(
-        seeds = [STAKE_VAULT_SEED, user.key().as_ref()],
+        seeds = [b"dapp_config"],
         bump
     )]
-    pub vault_authority: AccountInfo<'info>,
+    pub dapp_config: Account<'info, DappConfig>,
+
+    pub guardian: Signer<'info>,
 
-    /// User's token account (destination for withdrawal)
     #[account(
         mut,
-        associated_token::mint = token_mint,
-        associated_token::authority = user
+        close = queued_by,
+        seeds = [QUEUED_OP_SEED, &id.to_le_bytes()],
+        bump
     )]
-    pub user_ata: InterfaceAccount<'info, TokenAccount>,
+    pub queued_op: Account<'info, QueuedOp>,
 
-    /// The mint for cal_coin
-    #[account(constraint = token_mint.key() == dapp_config.token_mint)]
-    pub token_mint: InterfaceAccount<'info, Mint>,
+    /// CHECK: receives the queued op's rent
+    #[account(mut, address = queued_op.queued_by)]
+    pub queued_by: UncheckedAccount<'info>,
 
-    #[account(address = TOKEN_2022_PROGRAM_ID)]
-    pub token_program: Program<'info, Token2022>,
+    /// Caller's guardian RoleGrant; not needed when the caller is the owner
+    pub role_grant: Option<Account<'info, RoleGrant>>,
+}
 
-    pub associated_token_program: Program<'info, AssociatedToken>,
-    pub system_program: Program<'info, System>,
-    pub rent: Sysvar<'info, Rent>,
+// ------------------------------------------------------------------------------------------------
+//  EVENTS
+// ------------------------------------------------------------------------------------------------
+
+#[event]
+pub struct SlashEvent {
+    pub user: Pubkey,
+    pub slasher: Pubkey,
+    pub bps: u16,
+    pub amount: u64,
+    pub reason: u8,
+    pub burned: bool,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct RoleChanged {
+    pub role: Role,
+    pub grantee: Pubkey,
+    pub admin: Pubkey,
+    pub granted: bool,
+}
+
+#[event]
+pub struct AdminOpQueued {
+    pub id: u64,
+    pub op: AdminOp,
+    pub eta: i64,
+    pub queued_by: Pubkey,
+}
+
+#[event]
+pub struct AdminOpExecuted {
+    pub id: u64,
+    pub op: AdminOp,
+    pub executor: Pubkey,
+}
+
+#[event]
+pub struct AdminOpCancelled {
+    pub id: u64,
+    pub op: AdminOp,
+    pub guardian: Pubkey,
+}
+
+#[event]
+pub struct InactivityNotice {
+    pub user: Pubkey,
+    pub sweepable_at: i64,
 }
 
 // ------------------------------------------------------------------------------------------------
@@ -790,4 +5555,867 @@ pub enum ErrorCode {
     NothingToClaim,
     #[msg("Unstake delay not yet met.")]
     UnstakeDelayNotMet,
+    #[msg("Invalid lock tier.")]
+    InvalidLockTier,
+    #[msg("Stake is still locked.")]
+    StakeLocked,
+    #[msg("Invalid stake position.")]
+    InvalidPosition,
+    #[msg("Invalid governance parameters.")]
+    InvalidGovernanceParams,
+    #[msg("Not enough voting power.")]
+    InsufficientVotingPower,
+    #[msg("Voting on this proposal is closed.")]
+    VotingClosed,
+    #[msg("Voting period has not ended.")]
+    VotingStillOpen,
+    #[msg("Slash must be between 1 and 10000 bps.")]
+    InvalidSlash,
+    #[msg("Treasury token account is required.")]
+    MissingTreasuryAccount,
+    #[msg("Exit fee must be at most 10000 bps.")]
+    InvalidExitFee,
+    #[msg("Early exit penalty must be at most 10000 bps.")]
+    InvalidEarlyExitPenalty,
+    #[msg("Not enough liquidity in the instant unstake buffer.")]
+    InsufficientBufferLiquidity,
+    #[msg("Stake, pending withdrawal and positions must all be empty.")]
+    StakeNotEmpty,
+    #[msg("User has been active within the inactivity period.")]
+    UserStillActive,
+    #[msg("Deregistration notice period has not ended.")]
+    NoticePeriodNotMet,
+    #[msg("Invalid streak tier.")]
+    InvalidStreakTier,
+    #[msg("Invalid stake boost tier.")]
+    InvalidStakeBoostTier,
+    #[msg("Invalid vesting schedule.")]
+    InvalidVestingSchedule,
+    #[msg("Nothing has vested yet.")]
+    NothingVested,
+    #[msg("Vesting schedule is not revocable or already revoked.")]
+    VestingNotRevocable,
+    #[msg("Invalid distributor parameters.")]
+    InvalidDistributor,
+    #[msg("Invalid Merkle proof.")]
+    InvalidProof,
+    #[msg("Airdrop leaf already claimed.")]
+    AlreadyClaimed,
+    #[msg("Airdrop distributor has expired.")]
+    DistributorExpired,
+    #[msg("Airdrop distributor has not expired yet.")]
+    DistributorNotExpired,
+    #[msg("Burn amount must be greater than zero.")]
+    NothingToBurn,
+    #[msg("Caller does not hold the required role.")]
+    MissingRole,
+    #[msg("Issuance is paused.")]
+    Paused,
+    #[msg("Timelock delay is shorter than the minimum.")]
+    TimelockDelayTooShort,
+    #[msg("Queued operation is not ready yet.")]
+    TimelockNotReady,
+    #[msg("Queued operation has expired.")]
+    TimelockExpired,
+    #[msg("This change must go through the timelock.")]
+    TimelockRequired,
+    #[msg("The queued operation needs accounts that were not passed.")]
+    MissingOpAccounts,
+    #[msg("Account is not the RoleGrant this operation names.")]
+    InvalidRoleGrant,
+    #[msg("Account is already in the current layout.")]
+    AlreadyMigrated,
+    #[msg("Account data does not match the expected layout.")]
+    InvalidAccountLayout,
+    #[msg("Lock is still active or carries no boost.")]
+    LockStillActive,
+    #[msg("Stake is locked until the proposals it voted on close.")]
+    VoteLocked,
+    #[msg("Pass each of the user's open positions exactly once.")]
+    IncompletePositionSet,
+    #[msg("The stCAL mint and token account are required for liquid staking.")]
+    MissingLiquidAccounts,
+    #[msg("The instant-unstake buffer must be passed while it is owed a refill.")]
+    BufferVaultRequired,
+    #[msg("Queued op ids with the top bit set are reserved for proposals.")]
+    ReservedOpId,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pool() -> StakePool {
+        StakePool {
+            bump: 255,
+            vault: Pubkey::new_unique(),
+            total_staked: 0,
+            reward_rate: 1_000,
+            reward_per_token_stored: 0,
+            last_update_timestamp: 0,
+            reward_budget: 1_000_000_000,
+            total_weight: 0,
+            lock_tiers: [
+                LockTier { duration_secs: 0, boost_bps: 10_000 },
+                LockTier { duration_secs: 30 * SECONDS_PER_DAY, boost_bps: 11_000 },
+                LockTier { duration_secs: 90 * SECONDS_PER_DAY, boost_bps: 12_500 },
+                LockTier { duration_secs: 180 * SECONDS_PER_DAY, boost_bps: 15_000 },
+                LockTier { duration_secs: 365 * SECONDS_PER_DAY, boost_bps: 20_000 },
+            ],
+            early_exit_penalty_bps: 0,
+            st_mint: Pubkey::default(),
+            liquid_principal: 0,
+            liquid_reward_debt: 0,
+            buffer_vault: Pubkey::default(),
+            exit_fee_bps: 0,
+            buffer_owed: 0,
+        }
+    }
+
+    fn fresh_stake() -> StakeAccount {
+        StakeAccount {
+            authority: Pubkey::new_unique(),
+            stake_amount: 0,
+            last_reward_timestamp: 0,
+            pending_withdrawal_amount: 0,
+            withdraw_request_timestamp: 0,
+            reward_debt: 0,
+            lock_tier: 0,
+            lock_end: 0,
+            boost_bps: BPS_DENOMINATOR as u16,
+            position_count: 0,
+            open_positions: 0,
+            position_total: 0,
+            position_weight: 0,
+            withdraw_authority: Pubkey::default(),
+            vote_locked_until: 0,
+        }
+    }
+
+    fn staked(pool: &mut StakePool, amount: u64, lock_tier: u8, now: i64) -> StakeAccount {
+        let mut acc = fresh_stake();
+        credit_stake(pool, &mut acc, amount, lock_tier, now).unwrap();
+        acc
+    }
+
+    fn legacy_stake(stake_amount: u64, pending: u64) -> LegacyStakeAccount {
+        LegacyStakeAccount {
+            authority: Pubkey::new_unique(),
+            stake_amount,
+            last_reward_timestamp: 10,
+            pending_withdrawal_amount: pending,
+            withdraw_request_timestamp: if pending > 0 { 20 } else { 0 },
+        }
+    }
+
+    #[test]
+    fn legacy_stake_account_decodes_from_72_bytes() {
+        let old = legacy_stake(5_000, 0);
+        let mut data = StakeAccount::DISCRIMINATOR.to_vec();
+        old.serialize(&mut data).unwrap();
+        assert_eq!(data.len(), LegacyStakeAccount::LEN);
+
+        let decoded = LegacyStakeAccount::deserialize(&mut &data[8..]).unwrap();
+        assert_eq!(decoded.authority, old.authority);
+        assert_eq!(decoded.stake_amount, 5_000);
+    }
+
+    #[test]
+    fn migration_credits_the_vault_balance_to_the_pool() {
+        let mut pool = pool();
+        let (mut acc, to_stake) = legacy_stake(5_000, 0).into_stake_account(5_000, 100);
+        assert_eq!(to_stake, 5_000);
+
+        credit_stake(&mut pool, &mut acc, to_stake, 0, 100).unwrap();
+        assert_eq!(acc.stake_amount, 5_000);
+        assert_eq!(acc.boost_bps, 10_000);
+        assert_eq!(acc.lock_end, 100); // tier 0 has no duration, so unlocked from now
+        assert_eq!(pool.total_staked, 5_000);
+        assert_eq!(pool.total_weight, 5_000);
+        assert_eq!(acc.reward_debt, pool.reward_debt_for(5_000).unwrap());
+
+        let mut out = Vec::new();
+        acc.try_serialize(&mut out).unwrap();
+        assert_eq!(out.len(), StakeAccount::LEN);
+    }
+
+    #[test]
+    fn migration_backs_pending_withdrawal_first_and_drops_unfunded_apy() {
+        // 4_000 pending and 3_000 staked on paper, but the vault only holds 5_000.
+        let (acc, to_stake) = legacy_stake(3_000, 4_000).into_stake_account(5_000, 100);
+        assert_eq!(acc.pending_withdrawal_amount, 4_000);
+        assert_eq!(acc.withdraw_request_timestamp, 20);
+        assert_eq!(to_stake, 1_000);
+
+        let (acc, to_stake) = legacy_stake(0, 4_000).into_stake_account(3_000, 100);
+        assert_eq!(acc.pending_withdrawal_amount, 3_000);
+        assert_eq!(to_stake, 0);
+    }
+
+    #[test]
+    fn rewards_split_pro_rata_by_weight() {
+        let mut pool = pool();
+        let a = staked(&mut pool, 1_000, 0, 0);
+        let b = staked(&mut pool, 3_000, 0, 0);
+
+        pool.update(10).unwrap(); // 10s × 1_000/s
+        assert_eq!(pool.pending_reward(a.weight().unwrap(), a.reward_debt).unwrap(), 2_500);
+        assert_eq!(pool.pending_reward(b.weight().unwrap(), b.reward_debt).unwrap(), 7_500);
+        assert_eq!(pool.reward_budget, 1_000_000_000 - 10_000);
+    }
+
+    #[test]
+    fn emission_stops_at_the_funded_budget() {
+        let mut pool = pool();
+        pool.reward_budget = 4_000;
+        let a = staked(&mut pool, 1_000, 0, 0);
+
+        pool.update(10).unwrap();
+        assert_eq!(pool.reward_budget, 0);
+        assert_eq!(pool.pending_reward(a.weight().unwrap(), a.reward_debt).unwrap(), 4_000);
+    }
+
+    #[test]
+    fn idle_pool_keeps_its_budget() {
+        let mut pool = pool();
+        pool.update(1_000).unwrap();
+        assert_eq!(pool.reward_budget, 1_000_000_000);
+        assert_eq!(pool.reward_per_token_stored, 0);
+    }
+
+    #[test]
+    fn harvest_settles_debt_without_touching_stake() {
+        let mut pool = pool();
+        let mut a = staked(&mut pool, 1_000, 0, 0);
+        pool.update(10).unwrap();
+        let reward = pool.pending_reward(a.weight().unwrap(), a.reward_debt).unwrap();
+        assert_eq!(reward, 10_000);
+
+        // What `harvest_rewards` does after paying out
+        a.reward_debt = pool.reward_debt_for(a.weight().unwrap()).unwrap();
+        assert_eq!(a.stake_amount, 1_000);
+        assert_eq!(pool.pending_reward(a.weight().unwrap(), a.reward_debt).unwrap(), 0);
+    }
+
+    #[test]
+    fn compound_restakes_the_reward() {
+        let mut pool = pool();
+        let mut a = staked(&mut pool, 1_000, 0, 0);
+        pool.update(10).unwrap();
+
+        let old_weight = a.weight().unwrap();
+        let reward = compound_rewards(&mut pool, &mut a).unwrap();
+        reweight(&mut pool, &mut a, old_weight).unwrap();
+        assert_eq!(reward, 10_000);
+        assert_eq!(a.stake_amount, 11_000);
+        assert_eq!(pool.total_staked, 11_000);
+        assert_eq!(pool.total_weight, 11_000);
+        assert_eq!(pool.pending_reward(a.weight().unwrap(), a.reward_debt).unwrap(), 0);
+    }
+
+    #[test]
+    fn boost_only_counts_while_locked() {
+        let mut pool = pool();
+        let acc = staked(&mut pool, 1_000, 4, 0); // 365 days, 2×
+        let lock_end = acc.lock_end;
+        assert_eq!(acc.effective_weight(lock_end - 1).unwrap(), 2_000);
+        assert_eq!(acc.effective_weight(lock_end).unwrap(), 1_000);
+        assert_eq!(voting_power(&acc, lock_end).unwrap(), 1_000);
+    }
+
+    #[test]
+    fn expire_lock_drops_boost_after_compounding() {
+        let mut pool = pool();
+        let mut acc = staked(&mut pool, 1_000, 1, 0); // 30 days, 1.1×
+        assert_eq!(pool.total_weight, 1_100);
+
+        let lock_end = acc.lock_end;
+        assert!(!expire_lock(&mut pool, &mut acc, lock_end - 1).unwrap());
+        assert_eq!(pool.total_weight, 1_100);
+
+        pool.reward_rate = 0; // keep the arithmetic simple
+        assert!(expire_lock(&mut pool, &mut acc, lock_end).unwrap());
+        assert_eq!(acc.boost_bps, 10_000);
+        assert_eq!(acc.lock_tier, 0);
+        assert_eq!(pool.total_weight, acc.stake_amount);
+        assert!(!expire_lock(&mut pool, &mut acc, lock_end + 1).unwrap());
+    }
+
+    #[test]
+    fn position_totals_follow_each_change() {
+        let mut acc = fresh_stake();
+        acc.track_position(0, 0, 2_000, 3_000).unwrap(); // open, 1.5×
+        acc.track_position(0, 0, 1_000, 1_000).unwrap(); // open, 1×
+        assert_eq!((acc.position_total, acc.position_weight), (3_000, 4_000));
+
+        acc.track_position(2_000, 3_000, 2_500, 3_750).unwrap(); // top-up
+        assert_eq!((acc.position_total, acc.position_weight), (3_500, 4_750));
+
+        acc.track_position(1_000, 1_000, 0, 0).unwrap(); // close
+        assert_eq!((acc.position_total, acc.position_weight), (2_500, 3_750));
+    }
+
+    #[test]
+    fn removing_more_than_tracked_is_an_error() {
+        let mut acc = fresh_stake();
+        acc.track_position(0, 0, 1_000, 1_000).unwrap();
+        assert!(acc.track_position(2_000, 2_000, 0, 0).is_err());
+    }
+
+    #[test]
+    fn position_addresses_are_unique_per_index() {
+        let user = Pubkey::new_unique();
+        let at = |i: u64| {
+            Pubkey::find_program_address(
+                &[STAKE_POSITION_SEED, user.as_ref(), &i.to_le_bytes()],
+                &crate::ID,
+            )
+            .0
+        };
+        assert_ne!(at(0), at(1));
+        assert_eq!(at(7), at(7));
+    }
+
+    #[test]
+    fn early_exit_needs_a_penalty_rate() {
+        let mut pool = pool();
+        assert!(early_exit_penalty(&pool, 1_000, 100, 99).is_err());
+        assert_eq!(early_exit_penalty(&pool, 1_000, 100, 100).unwrap(), 0);
+
+        pool.early_exit_penalty_bps = 1_000; // 10%
+        assert_eq!(early_exit_penalty(&pool, 1_000, 100, 99).unwrap(), 100);
+    }
+
+    #[test]
+    fn voted_stake_cannot_be_withdrawn_until_voting_ends() {
+        let mut pool = pool();
+        let mut acc = staked(&mut pool, 1_000, 0, 0);
+        acc.vote_locked_until = 500;
+
+        assert_eq!(
+            withdraw_stake(&mut pool, &mut acc, 499).unwrap_err(),
+            error!(ErrorCode::VoteLocked)
+        );
+        let (total, penalty) = withdraw_stake(&mut pool, &mut acc, 500).unwrap();
+        assert_eq!(penalty, 0);
+        assert!(total >= 1_000);
+    }
+
+    #[test]
+    fn legacy_dapp_config_keeps_deployed_values() {
+        let owner = Pubkey::new_unique();
+        let legacy = LegacyDappConfig {
+            gatekeeper_network: Pubkey::new_unique(),
+            token_mint: Pubkey::new_unique(),
+            mint_authority_bump: 254,
+            exempt_address: Pubkey::new_unique(),
+            owner,
+            initialized: true,
+            total_minted: 123,
+            max_supply: 1_000,
+            total_claims: 9,
+        };
+        let mut data = DappConfig::DISCRIMINATOR.to_vec();
+        legacy.serialize(&mut data).unwrap();
+        assert_eq!(data.len(), LegacyDappConfig::LEN);
+
+        let cfg = LegacyDappConfig::deserialize(&mut &data[8..]).unwrap().into_config();
+        assert_eq!(cfg.owner, owner);
+        assert_eq!(cfg.total_minted, 123);
+        assert_eq!(cfg.max_supply, 1_000);
+        assert_eq!(cfg.total_claims, 9);
+        assert_eq!(cfg.treasury, owner);
+        assert_eq!(cfg.user_rate_per_sec, USER_RATE_PER_SEC);
+
+        let mut out = Vec::new();
+        cfg.try_serialize(&mut out).unwrap();
+        assert_eq!(out.len(), DappConfig::LEN);
+    }
+
+    fn empty_position() -> StakePosition {
+        StakePosition {
+            authority: Pubkey::default(),
+            index: 0,
+            amount: 0,
+            start_timestamp: 0,
+            last_reward_timestamp: 0,
+            lock_tier: 0,
+            lock_end: 0,
+            boost_bps: 0,
+            reward_debt: 0,
+        }
+    }
+
+    #[test]
+    fn stake_for_leaves_the_beneficiarys_stake_and_lock_alone() {
+        let mut pool = pool();
+        let mut victim = staked(&mut pool, 1_000, 1, 0);
+        let before = (victim.stake_amount, victim.lock_end, victim.boost_bps);
+
+        // What `stake_for` does with a gifted deposit
+        let mut gift = empty_position();
+        fill_position(&mut pool, &mut victim, &mut gift, 2_000, 0, 10).unwrap();
+
+        assert_eq!((victim.stake_amount, victim.lock_end, victim.boost_bps), before);
+        assert_eq!(victim.withdraw_authority, Pubkey::default());
+        assert_eq!(gift.authority, victim.authority);
+        assert_eq!(gift.lock_end, 10);
+        assert_eq!((gift.index, victim.position_count, victim.open_positions), (0, 1, 1));
+        assert_eq!((victim.position_total, victim.position_weight), (2_000, 2_000));
+        assert_eq!(pool.total_staked, 3_000);
+    }
+
+    #[test]
+    fn slash_cuts_main_stake_and_positions_alike() {
+        let mut pool = pool();
+        let mut acc = staked(&mut pool, 10_000, 2, 0);
+        let mut position = empty_position();
+        fill_position(&mut pool, &mut acc, &mut position, 4_000, 1, 0).unwrap();
+
+        let now = 100;
+        pool.update(now).unwrap();
+        let main_reward = pool.pending_reward(acc.weight().unwrap(), acc.reward_debt).unwrap();
+        let from_stake = slash_stake(&mut pool, &mut acc, 2_500).unwrap();
+        assert_eq!(from_stake, (10_000 + main_reward) / 4);
+
+        let (old_amount, old_weight) = (position.amount, position.weight().unwrap());
+        let position_reward = pool.pending_reward(old_weight, position.reward_debt).unwrap();
+        let cut = slash_stake(&mut pool, &mut position, 2_500).unwrap();
+        acc.track_position(old_amount, old_weight, position.amount, position.weight().unwrap())
+            .unwrap();
+
+        assert_eq!(cut, (4_000 + position_reward) / 4);
+        assert_eq!(position.amount + cut, 4_000 + position_reward);
+        assert_eq!(acc.position_total, position.amount);
+        assert_eq!(acc.position_weight, position.weight().unwrap());
+        assert_eq!(pool.total_staked, acc.stake_amount + position.amount);
+        assert_eq!(
+            pool.total_weight,
+            acc.weight().unwrap() + position.weight().unwrap()
+        );
+    }
+
+    #[test]
+    fn cancel_unstake_restakes_the_pending_amount_unlocked() {
+        let mut pool = pool();
+        let mut other = staked(&mut pool, 5_000, 0, 0);
+        let mut acc = staked(&mut pool, 5_000, 2, 0);
+
+        let now = 90 * SECONDS_PER_DAY;
+        let (pending, penalty) = withdraw_stake(&mut pool, &mut acc, now).unwrap();
+        assert_eq!(penalty, 0);
+        assert!(pending > 5_000);
+        acc.pending_withdrawal_amount = pending;
+        acc.withdraw_request_timestamp = now;
+
+        // What `cancel_unstake` does a day later
+        let later = now + SECONDS_PER_DAY;
+        acc.pending_withdrawal_amount = 0;
+        acc.withdraw_request_timestamp = 0;
+        credit_stake(&mut pool, &mut acc, pending, 0, later).unwrap();
+
+        assert_eq!(acc.stake_amount, pending);
+        assert_eq!((acc.lock_tier, acc.lock_end), (0, later));
+        assert_eq!(acc.boost_bps, BPS_DENOMINATOR as u16);
+        // Nothing is earned for the time spent in the withdrawal queue
+        assert_eq!(pool.pending_reward(acc.weight().unwrap(), acc.reward_debt).unwrap(), 0);
+
+        compound_rewards(&mut pool, &mut other).unwrap();
+        reweight(&mut pool, &mut other, 5_000).unwrap();
+        assert_eq!(pool.total_staked, acc.stake_amount + other.stake_amount);
+        assert_eq!(pool.total_weight, acc.stake_amount + other.stake_amount);
+    }
+
+    #[test]
+    fn stcal_rate_follows_liquid_principal() {
+        let mut pool = pool();
+        assert_eq!(pool.st_for_deposit(1_000, 0).unwrap(), 1_000);
+        pool.adjust_liquid(1_000, 0).unwrap();
+
+        // Rewards compound into the principal, so each stCAL is worth more
+        pool.update(10).unwrap();
+        let reward = pool.pending_reward(pool.liquid_principal, pool.liquid_reward_debt).unwrap();
+        pool.compound_liquid(10).unwrap();
+        assert_eq!(pool.liquid_principal, 1_000 + reward);
+        assert!(pool.st_for_deposit(1_000, 1_000).unwrap() < 1_000);
+        assert_eq!(pool.underlying_for_st(1_000, 1_000).unwrap(), 1_000 + reward);
+        assert_eq!(pool.underlying_for_st(500, 1_000).unwrap(), (1_000 + reward) / 2);
+    }
+
+    #[test]
+    fn stcal_redemption_without_supply_is_rejected() {
+        let mut pool = pool();
+        pool.adjust_liquid(1_000, 0).unwrap();
+        assert_eq!(
+            pool.underlying_for_st(1, 0).unwrap_err(),
+            error!(ErrorCode::NothingToUnstake)
+        );
+    }
+
+    #[test]
+    fn instant_unstake_keeps_the_fee_and_owes_the_buffer_in_full() {
+        let mut pool = pool();
+        pool.exit_fee_bps = 300;
+
+        assert_eq!(pool.draw_buffer(10_000, 9_700).unwrap(), (9_700, 300));
+        assert_eq!(pool.buffer_owed, 10_000);
+        assert_eq!(
+            pool.draw_buffer(10_000, 9_699).unwrap_err(),
+            error!(ErrorCode::InsufficientBufferLiquidity)
+        );
+        assert_eq!(pool.buffer_owed, 10_000);
+
+        pool.exit_fee_bps = 0;
+        assert_eq!(pool.draw_buffer(500, 500).unwrap(), (500, 0));
+        assert_eq!(pool.buffer_owed, 10_500);
+    }
+
+    #[test]
+    fn lazy_registration_starts_the_faucet_clock() {
+        let mut user_pda = LegacyUserPda {
+            authority: Pubkey::default(),
+            last_claimed_timestamp: 0,
+            claimed_so_far: 0,
+        }
+        .into_user_pda();
+        let mut acc = fresh_stake();
+        register_on_first_use(&mut user_pda, &mut acc, Pubkey::new_unique(), 1_000_000);
+        assert_eq!(user_pda.last_claimed_timestamp, 1_000_000);
+        assert_eq!(acc.last_reward_timestamp, 1_000_000);
+
+        // Explicit registration keeps the initial accrual
+        init_user_accounts(&mut user_pda, &mut acc, Pubkey::new_unique(), 1_000_000);
+        assert_eq!(user_pda.last_claimed_timestamp, 0);
+    }
+
+    #[test]
+    fn legacy_user_pda_keeps_its_claim_history() {
+        let old = LegacyUserPda {
+            authority: Pubkey::new_unique(),
+            last_claimed_timestamp: 1_234,
+            claimed_so_far: 99_000,
+        };
+        let mut data = UserPda::DISCRIMINATOR.to_vec();
+        old.serialize(&mut data).unwrap();
+        assert_eq!(data.len(), LegacyUserPda::LEN);
+
+        let user_pda = LegacyUserPda::deserialize(&mut &data[8..]).unwrap().into_user_pda();
+        assert_eq!(user_pda.authority, old.authority);
+        assert_eq!(
+            (user_pda.last_claimed_timestamp, user_pda.claimed_so_far),
+            (1_234, 99_000)
+        );
+        assert_eq!((user_pda.inactive_flagged_at, user_pda.current_streak), (0, 0));
+
+        let mut grown = Vec::new();
+        user_pda.try_serialize(&mut grown).unwrap();
+        assert_eq!(grown.len(), UserPda::LEN);
+    }
+
+    fn config() -> DappConfig {
+        LegacyDappConfig {
+            gatekeeper_network: Pubkey::new_unique(),
+            token_mint: Pubkey::new_unique(),
+            mint_authority_bump: 255,
+            exempt_address: Pubkey::new_unique(),
+            owner: Pubkey::new_unique(),
+            initialized: true,
+            total_minted: 0,
+            max_supply: 1_000_000_000 * 10u64.pow(9),
+            total_claims: 0,
+        }
+        .into_config()
+    }
+
+    #[test]
+    fn lazily_registered_user_accrues_from_registration() {
+        let mut cfg = config();
+        let user = Pubkey::new_unique();
+        let mut user_pda = LegacyUserPda {
+            authority: Pubkey::default(),
+            last_claimed_timestamp: 0,
+            claimed_so_far: 0,
+        }
+        .into_user_pda();
+        let mut acc = fresh_stake();
+        register_on_first_use(&mut user_pda, &mut acc, user, 1_000_000);
+
+        // The registering call itself has nothing to claim
+        assert_eq!(
+            accrue_faucet(&mut cfg, &mut user_pda, 0, user, 1_000_000, "Claim").unwrap_err(),
+            error!(ErrorCode::CooldownNotMet)
+        );
+        let minted = accrue_faucet(&mut cfg, &mut user_pda, 0, user, 1_000_060, "Claim").unwrap();
+        assert_eq!(minted, 60 * USER_RATE_PER_SEC);
+        assert_eq!(user_pda.claimed_so_far, minted);
+        assert_eq!(cfg.total_minted, minted);
+    }
+
+    #[test]
+    fn faucet_claims_count_against_max_supply() {
+        let mut cfg = config();
+        let user = Pubkey::new_unique();
+        let mut user_pda = LegacyUserPda {
+            authority: user,
+            last_claimed_timestamp: 1_000_000,
+            claimed_so_far: 0,
+        }
+        .into_user_pda();
+        cfg.max_supply = 90 * USER_RATE_PER_SEC;
+
+        let minted = accrue_faucet(&mut cfg, &mut user_pda, 0, user, 1_000_060, "Claim").unwrap();
+        assert_eq!((cfg.total_minted, cfg.total_claims), (minted, 1));
+        assert_eq!(
+            accrue_faucet(&mut cfg, &mut user_pda, 0, user, 1_000_120, "Claim").unwrap_err(),
+            error!(ErrorCode::SupplyExceeded)
+        );
+    }
+
+    #[test]
+    fn streak_grows_daily_and_resets_after_a_missed_day() {
+        let mut user_pda = LegacyUserPda {
+            authority: Pubkey::new_unique(),
+            last_claimed_timestamp: 0,
+            claimed_so_far: 0,
+        }
+        .into_user_pda();
+        let day = |d: i64| d * SECONDS_PER_DAY + 3_600;
+
+        assert_eq!(user_pda.record_claim_day(day(100)), 1);
+        assert_eq!(user_pda.record_claim_day(day(100) + 60), 1);
+        for d in 101..=107 {
+            user_pda.record_claim_day(day(d));
+        }
+        assert_eq!((user_pda.current_streak, user_pda.longest_streak), (8, 8));
+
+        assert_eq!(user_pda.record_claim_day(day(109)), 1);
+        assert_eq!((user_pda.current_streak, user_pda.longest_streak), (1, 8));
+    }
+
+    #[test]
+    fn streak_multiplier_uses_the_highest_tier_reached() {
+        let cfg = config();
+        assert_eq!(cfg.streak_multiplier_bps(1), 10_000);
+        assert_eq!(cfg.streak_multiplier_bps(6), 10_000);
+        assert_eq!(cfg.streak_multiplier_bps(7), 11_000);
+        assert_eq!(cfg.streak_multiplier_bps(99), 12_500);
+        assert_eq!(cfg.streak_multiplier_bps(400), 15_000);
+    }
+
+    #[test]
+    fn faucet_boost_counts_positions_like_voting_power() {
+        let cfg = config();
+        let mut pool = pool();
+        let mut acc = staked(&mut pool, MIN_STAKE_LAMPORTS / 2, 0, 0);
+        assert_eq!(cfg.stake_boost_bps(voting_power(&acc, 10).unwrap()), 10_000);
+
+        let mut position = empty_position();
+        fill_position(&mut pool, &mut acc, &mut position, MIN_STAKE_LAMPORTS / 2, 0, 10).unwrap();
+        assert_eq!(cfg.stake_boost_bps(voting_power(&acc, 10).unwrap()), 11_000);
+
+        // Withdrawing the main stake drops the boost again
+        withdraw_stake(&mut pool, &mut acc, 20).unwrap();
+        assert_eq!(cfg.stake_boost_bps(voting_power(&acc, 20).unwrap()), 10_000);
+    }
+
+    #[test]
+    fn claimed_stake_joins_the_existing_lock() {
+        let mut cfg = config();
+        let mut pool = pool();
+        let user = Pubkey::new_unique();
+        let mut user_pda = LegacyUserPda {
+            authority: user,
+            last_claimed_timestamp: 0,
+            claimed_so_far: 0,
+        }
+        .into_user_pda();
+        let mut acc = staked(&mut pool, MIN_STAKE_LAMPORTS - 1, 2, 0);
+        let lock_end = acc.lock_end;
+
+        // What `claim_and_stake` does after the cooldown
+        let now = 60;
+        let power = voting_power(&acc, now).unwrap();
+        let minted = accrue_faucet(&mut cfg, &mut user_pda, power, user, now, "Claim and stake").unwrap();
+        assert!(acc.stake_amount.saturating_add(minted) >= MIN_STAKE_LAMPORTS);
+        let before = acc.stake_amount;
+        credit_stake(&mut pool, &mut acc, minted, 0, now).unwrap();
+
+        assert!(acc.stake_amount >= before + minted);
+        assert_eq!((acc.lock_tier, acc.lock_end), (2, lock_end));
+        assert_eq!(pool.total_staked, acc.stake_amount);
+    }
+
+    fn vesting(total: u64, start: i64, cliff_secs: i64, duration_secs: i64) -> VestingSchedule {
+        VestingSchedule {
+            beneficiary: Pubkey::new_unique(),
+            total,
+            released: 0,
+            start,
+            cliff_secs,
+            duration_secs,
+            revocable: true,
+            revoked: false,
+        }
+    }
+
+    #[test]
+    fn vesting_total_is_reserved_until_released_or_revoked() {
+        let mut cfg = config();
+        cfg.max_supply = 10_000;
+        let mut schedule = vesting(8_000, 0, 100, 1_000);
+        cfg.reserve_supply(schedule.total).unwrap();
+
+        // The reservation already counts against the cap
+        assert_eq!(cfg.record_mint(2_001).unwrap_err(), error!(ErrorCode::SupplyExceeded));
+        assert_eq!(schedule.vested_amount(99).unwrap(), 0);
+
+        let vested = schedule.vested_amount(500).unwrap();
+        assert_eq!(vested, 4_000);
+        cfg.mint_reserved(vested).unwrap();
+        schedule.released = vested;
+        assert_eq!((cfg.total_minted, cfg.reserved_supply), (4_000, 4_000));
+
+        // Revoking at 750 frees the 2,000 that will never vest
+        let vested = schedule.vested_amount(750).unwrap();
+        cfg.release_reservation(schedule.total - vested).unwrap();
+        schedule.total = vested;
+        schedule.revoked = true;
+        assert_eq!(cfg.reserved_supply, 2_000);
+        assert_eq!(schedule.vested_amount(10_000).unwrap() - schedule.released, 2_000);
+        cfg.mint_reserved(2_000).unwrap();
+        assert_eq!((cfg.total_minted, cfg.reserved_supply), (6_000, 0));
+        cfg.record_mint(4_000).unwrap();
+    }
+
+    #[test]
+    fn migrated_config_starts_with_empty_supply_controls() {
+        let cfg = config();
+        assert_eq!((cfg.reserved_supply, cfg.total_burned), (0, 0));
+        assert!(!cfg.burn_reopens_supply && !cfg.paused);
+
+        let mut data = Vec::new();
+        cfg.try_serialize(&mut data).unwrap();
+        assert_eq!(data.len(), DappConfig::LEN);
+        assert_eq!(
+            &data[DappConfig::OWNER_OFFSET..DappConfig::OWNER_OFFSET + 32],
+            cfg.owner.as_ref()
+        );
+    }
+
+    #[test]
+    fn configs_start_with_the_timelock_enforced() {
+        assert!(config().timelock_enforced);
+        let mut cfg = config();
+        AdminOp::DisableTimelock.apply(&mut cfg, None).unwrap();
+        assert!(!cfg.timelock_enforced);
+    }
+
+    #[test]
+    fn queued_tier_and_supply_ops_run_without_the_stake_pool() {
+        let mut cfg = config();
+        let mut streak = cfg.streak_tiers[1];
+        streak.multiplier_bps += 500;
+        AdminOp::SetStreakTier { index: 1, tier: streak }.apply(&mut cfg, None).unwrap();
+        assert_eq!(cfg.streak_tiers[1].multiplier_bps, streak.multiplier_bps);
+
+        let boost = StakeBoostTier { min_stake: 0, multiplier_bps: 10_000 };
+        AdminOp::SetStakeBoostTier { index: 0, tier: boost }.apply(&mut cfg, None).unwrap();
+        AdminOp::SetBurnReopens(true).apply(&mut cfg, None).unwrap();
+        assert!(cfg.burn_reopens_supply);
+
+        // Same validation as the direct setters
+        let unsorted = StreakTier { min_days: 0, ..streak };
+        assert_eq!(
+            AdminOp::SetStreakTier { index: 2, tier: unsorted }
+                .apply(&mut cfg, None)
+                .unwrap_err(),
+            error!(ErrorCode::InvalidStreakTier)
+        );
+    }
+
+    #[test]
+    fn pool_and_role_ops_need_their_accounts() {
+        let mut cfg = config();
+        assert_eq!(
+            AdminOp::Param(ParamChange::SetUserRate(1)).apply(&mut cfg, None).unwrap_err(),
+            error!(ErrorCode::MissingOpAccounts)
+        );
+        let revoke = AdminOp::RevokeRole { role: Role::Guardian, grantee: Pubkey::new_unique() };
+        assert_eq!(
+            revoke.apply(&mut cfg, None).unwrap_err(),
+            error!(ErrorCode::MissingOpAccounts)
+        );
+    }
+
+    #[test]
+    fn early_exit_penalty_param_is_capped_at_100_percent() {
+        let (mut cfg, mut pool) = (config(), pool());
+        let set = |bps| AdminOp::Param(ParamChange::SetEarlyExitPenalty(bps));
+        assert_eq!(
+            set(10_001).apply(&mut cfg, Some(&mut pool)).unwrap_err(),
+            error!(ErrorCode::InvalidEarlyExitPenalty)
+        );
+        set(10_000).apply(&mut cfg, Some(&mut pool)).unwrap();
+        assert_eq!(pool.early_exit_penalty_bps, 10_000);
+    }
+
+    #[test]
+    fn admin_ops_fit_the_queued_op() {
+        for op in [
+            AdminOp::Param(ParamChange::SetLockTier {
+                index: 1,
+                tier: LockTier { duration_secs: 1, boost_bps: 10_000 },
+            }),
+            AdminOp::SetStakeBoostTier { index: 0, tier: StakeBoostTier::default() },
+            AdminOp::GrantRole { role: Role::Guardian, grantee: Pubkey::new_unique() },
+        ] {
+            assert!(op.try_to_vec().unwrap().len() <= AdminOp::MAX_LEN);
+        }
+    }
+
+    #[test]
+    fn queued_op_grace_period_saturates() {
+        let queued = |eta| QueuedOp {
+            id: 0,
+            op: AdminOp::DisableTimelock,
+            eta,
+            queued_by: Pubkey::default(),
+            queued_at: 0,
+        };
+        assert!(queued(i64::MAX - 1).check_ready(i64::MAX).is_ok());
+        assert_eq!(
+            queued(100).check_ready(99).unwrap_err(),
+            error!(ErrorCode::TimelockNotReady)
+        );
+        assert_eq!(
+            queued(100).check_ready(101 + TIMELOCK_GRACE_PERIOD).unwrap_err(),
+            error!(ErrorCode::TimelockExpired)
+        );
+    }
+
+    #[test]
+    fn burn_reopens_cannot_be_switched_off_over_the_cap() {
+        let mut cfg = config();
+        cfg.max_supply = 1_000;
+        cfg.set_burn_reopens(true).unwrap();
+        cfg.record_mint(1_000).unwrap();
+        cfg.record_burn(400).unwrap();
+
+        // Burned room is minted again
+        cfg.record_mint(300).unwrap();
+        assert_eq!(cfg.capped_supply(), 900);
+        assert_eq!(
+            cfg.set_burn_reopens(false).unwrap_err(),
+            error!(ErrorCode::SupplyExceeded)
+        );
+        assert!(cfg.burn_reopens_supply);
+
+        let mut fresh = config();
+        fresh.max_supply = 1_000;
+        fresh.set_burn_reopens(true).unwrap();
+        fresh.reserve_supply(600).unwrap();
+        fresh.record_mint(400).unwrap();
+        fresh.set_burn_reopens(false).unwrap();
+        assert_eq!(fresh.record_mint(1).unwrap_err(), error!(ErrorCode::SupplyExceeded));
+    }
 }
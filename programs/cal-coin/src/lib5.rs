@@ -1,22 +1,45 @@
 use anchor_lang::prelude::*;
+use anchor_lang::system_program;
+use anchor_lang::Discriminator;
 use anchor_spl::{
     token_2022::{self as token_2022, ID as TOKEN_2022_PROGRAM_ID},
     associated_token::AssociatedToken,
     token_interface::{Mint, Token2022, TokenAccount},
 };
 use solana_gateway::Gateway;
-use anchor_spl::token_2022::MintTo;
+use anchor_spl::token_2022::{MintTo, TransferChecked};
 use hmac_sha512::HMAC;
 use std::str::FromStr;
 
 /* ─── Time constants ───────────────────────────────────────────────────────────── */
-const UTC_OFFSET: i64   = 7 * 60 * 60;            // −07:00 (America/Vancouver)
-const SECS_PER_DAY: i64 = 24 * 60 * 60;
+const DEFAULT_UTC_OFFSET: i64 = 7 * 60 * 60;      // deployed boundary (17:00 UTC); keep for existing users
+const SECS_PER_DAY: i64       = 24 * 60 * 60;
+const MAX_DST_TRANSITIONS: usize = 8;
 
 /* ─── Rewards constants ────────────────────────────────────────────────────────── */
 const USERNAME_MAX_LEN: usize = 32;
+const USERNAME_COOLDOWN: i64  = 30 * 24 * 60 * 60; // 30 days between renames
 const PERIOD_SECONDS:  i64    = 90 * 60;          // 90-min claim cap
 const DAY_CAP_SECONDS: i64    = 14 * 60 * 60;     // 14-hour daily cap
+const CLAIM_TTL_SECONDS: i64  = 15 * 60;          // claim intent lifetime
+const CLAIM_REASON_CANCELLED: u8 = 0;
+
+/* ─── Patron constants ─────────────────────────────────────────────────────────── */
+const MAX_PATRON_TIERS:   usize = 4;
+const BPS_DENOMINATOR:    u16   = 10_000;
+const DEFAULT_USER_BPS:   u16   = 7_500;          // 0.75 per minute
+const DEFAULT_COMM_BPS:   u16   = 5_000;          // 0.50 per minute
+const LEGACY_PATRON_USER_BPS: u16 = 10_000;       // pre-tier `is_patron` split
+const LEGACY_PATRON_COMM_BPS: u16 = 2_500;
+
+/* ─── Role constants ───────────────────────────────────────────────────────────── */
+const ROLE_SEED: &[u8] = b"role";
+
+/* ─── Timelock constants ───────────────────────────────────────────────────────── */
+const QUEUED_OP_SEED: &[u8]     = b"queued_op";
+const MIN_TIMELOCK_DELAY: i64   = 2 * 24 * 60 * 60;   // 2 days
+const TIMELOCK_GRACE_PERIOD: i64 = 14 * 24 * 60 * 60; // stale after 14 days past the ETA
+const MAX_COMMISSION_BPS: u16   = 2_000;
 
 declare_id!("9matfyqfsoKn9dgnkdf99pGk7dkL2EPuVte9SkQ9AyxV");
 
@@ -45,11 +68,15 @@ pub mod cal_coin {
         cfg.stored_hash         = [0u8; 64];          // first call will be free
         cfg.gateway_updates     = 0;
         cfg.initialized         = false;
+        cfg.day_policy          = DayPolicy::FixedOffset;
+        cfg.utc_offset          = DEFAULT_UTC_OFFSET;
+        cfg.dst_transitions     = [DstTransition::default(); MAX_DST_TRANSITIONS];
         Ok(())
     }
 
     pub fn initialize_mint(ctx: Context<InitializeMint>, _decimals: u8) -> Result<()> {
         let cfg = &mut ctx.accounts.dapp_config;
+        require_role(cfg, &ctx.accounts.owner.key(), Role::Admin, ctx.accounts.role_grant.as_deref())?;
         require!(!cfg.initialized, ErrorCode::AlreadyInitialized);
 
         cfg.token_mint          = ctx.accounts.mint_for_dapp.key();
@@ -58,10 +85,37 @@ pub mod cal_coin {
         Ok(())
     }
 
+    /*═════════════════════════════════════ Roles ════════════════════════════════*/
+
+    /// Give `grantee` one role. Admin only; the owner implicitly holds all.
+    pub fn grant_role(ctx: Context<GrantRole>, role: Role, grantee: Pubkey) -> Result<()> {
+        let admin = ctx.accounts.admin.key();
+        require_role(&ctx.accounts.dapp_config, &admin, Role::Admin, ctx.accounts.role_grant.as_deref())?;
+
+        let g        = &mut ctx.accounts.new_grant;
+        g.role       = role;
+        g.grantee    = grantee;
+        g.granted_by = admin;
+        g.granted_at = Clock::get()?.unix_timestamp;
+
+        emit!(RoleChanged { role, grantee, admin, granted: true });
+        Ok(())
+    }
+
+    /// Close `grantee`'s grant of `role`, refunding its rent to the caller.
+    /// Admin only.
+    pub fn revoke_role(ctx: Context<RevokeRole>, role: Role, grantee: Pubkey) -> Result<()> {
+        let admin = ctx.accounts.admin.key();
+        require_role(&ctx.accounts.dapp_config, &admin, Role::Admin, ctx.accounts.role_grant.as_deref())?;
+
+        emit!(RoleChanged { role, grantee, admin, granted: false });
+        Ok(())
+    }
+
     /*══════════════════════════════════ User flow ═══════════════════════════════*/
 
     pub fn register_user(ctx: Context<RegisterUser>, username: String) -> Result<()> {
-        require!(username.len() <= USERNAME_MAX_LEN, ErrorCode::UsernameTooLong);
+        let username = normalize_username(&username)?;
         let cfg      = &ctx.accounts.dapp_config;
         let user_key = ctx.accounts.user.key();
 
@@ -75,20 +129,95 @@ pub mod cal_coin {
             .map_err(|_| error!(ErrorCode::GatewayCheckFailed))?;
         }
 
+        let now = Clock::get()?.unix_timestamp;
+        let record = &mut ctx.accounts.username_record;
+        record.authority  = user_key;
+        record.username   = username.clone();
+        record.claimed_at = now;
+
         let profile = &mut ctx.accounts.user_profile;
         profile.authority         = user_key;
         profile.username          = username;
         profile.last_username_set = 0;
         profile.last_claimed      = 0;
         profile.last_paid         = 0;
-        profile.claim_flag        = false;
-        profile.is_patron         = false;
+        profile.patron_tier       = 0;
+        profile.patron_until      = 0;
         profile.daily_accumulated = 0;
         profile.daily_reset       = 0;
         Ok(())
     }
 
-    pub fn claim(ctx: Context<Claim>) -> Result<()> {
+    /// Move to a new name. The old `UsernameRecord` is closed (freeing the
+    /// name for others) and a new one is created in the same transaction.
+    pub fn change_username(ctx: Context<ChangeUsername>, new_username: String) -> Result<()> {
+        let new_username = normalize_username(&new_username)?;
+        let now     = Clock::get()?.unix_timestamp;
+        let profile = &mut ctx.accounts.user_profile;
+        require!(
+            now - profile.last_username_set >= USERNAME_COOLDOWN,
+            ErrorCode::UsernameCooldown
+        );
+
+        let record = &mut ctx.accounts.new_record;
+        record.authority  = profile.authority;
+        record.username   = new_username.clone();
+        record.claimed_at = now;
+
+        emit!(UsernameChanged {
+            authority: profile.authority,
+            old:       profile.username.clone(),
+            new:       new_username.clone(),
+        });
+        profile.username          = new_username;
+        profile.last_username_set = now;
+        Ok(())
+    }
+
+    /// Reserve a name for a profile registered before `UsernameRecord`s
+    /// existed. Normally that is the profile's own name; if someone has taken
+    /// it since, pick another and pass `current_record` to show the current
+    /// name is not held by this user. Does not start the rename cooldown.
+    pub fn claim_legacy_username(
+        ctx:      Context<ClaimLegacyUsername>,
+        username: String,
+    ) -> Result<()> {
+        let username = normalize_username(&username)?;
+        let profile  = &mut ctx.accounts.user_profile;
+
+        // Claiming the current name needs no proof: `new_record` init fails if
+        // this user (or anyone) already holds it.
+        if username != profile.username.to_ascii_lowercase() {
+            let current = ctx
+                .accounts
+                .current_record
+                .as_ref()
+                .ok_or(ErrorCode::UsernameAlreadyReserved)?;
+            require!(
+                !holds_record(&current.to_account_info(), &profile.authority)?,
+                ErrorCode::UsernameAlreadyReserved
+            );
+        }
+
+        let record = &mut ctx.accounts.new_record;
+        record.authority  = profile.authority;
+        record.username   = username.clone();
+        record.claimed_at = Clock::get()?.unix_timestamp;
+
+        if profile.username != username {
+            emit!(UsernameChanged {
+                authority: profile.authority,
+                old:       profile.username.clone(),
+                new:       username.clone(),
+            });
+        }
+        profile.username = username;
+        Ok(())
+    }
+
+    /// Open a claim intent for `session_id`. Only one intent may be live at a
+    /// time; an expired one is simply overwritten.
+    pub fn claim(ctx: Context<Claim>, session_id: u64) -> Result<()> {
         let cfg      = &ctx.accounts.dapp_config;
         let user_key = ctx.accounts.user.key();
         let profile  = &mut ctx.accounts.user_profile;
@@ -106,26 +235,67 @@ pub mod cal_coin {
         let now = Clock::get()?.unix_timestamp;
         require!(now - profile.last_paid >= PERIOD_SECONDS, ErrorCode::CooldownNotMet);
 
+        let intent = &mut ctx.accounts.claim_intent;
+        require!(!intent.is_live(now), ErrorCode::ClaimAlreadyPending);
+        intent.authority  = user_key;
+        intent.session_id = session_id;
+        intent.created_at = now;
+        intent.expires_at = now + CLAIM_TTL_SECONDS;
+        intent.active     = true;
+
         profile.last_claimed = now;
-        profile.claim_flag   = true;
+        Ok(())
+    }
+
+    pub fn cancel_claim(ctx: Context<CancelClaim>) -> Result<()> {
+        let intent = &mut ctx.accounts.claim_intent;
+        require!(intent.active, ErrorCode::NoClaimPending);
+        intent.active = false;
+
+        emit!(ClaimClosed {
+            authority:  intent.authority,
+            session_id: intent.session_id,
+            reason:     CLAIM_REASON_CANCELLED,
+        });
+        Ok(())
+    }
+
+    /// Validator refusal of a pending intent; `reason` is an off-chain code
+    /// (non-zero, `0` is reserved for user cancellation).
+    pub fn reject_claim(ctx: Context<RejectClaim>, reason: u8) -> Result<()> {
+        require!(
+            ctx.accounts.validator.key() == ctx.accounts.dapp_config.validator_address,
+            ErrorCode::InvalidValidator
+        );
+        require!(reason != CLAIM_REASON_CANCELLED, ErrorCode::InvalidRejectReason);
+        let intent = &mut ctx.accounts.claim_intent;
+        require!(intent.active, ErrorCode::NoClaimPending);
+        intent.active = false;
+
+        emit!(ClaimClosed {
+            authority:  intent.authority,
+            session_id: intent.session_id,
+            reason,
+        });
         Ok(())
     }
 
     /*═══════════════════════════════════ Payout ═════════════════════════════════*/
 
     pub fn payout<'info>(
-        ctx:      Context<'_, '_, '_, 'info, Payout<'info>>,
-        game_id:  String,
-        game_ts:  String,
-        old_note: [u8; 64],
-        new_hash: [u8; 64],
-        new_hash2:[u8; 64],
-        noise:    [u8; 64],
+        ctx:        Context<'_, '_, '_, 'info, Payout<'info>>,
+        session_id: u64,
+        game_id:    String,
+        game_ts:    String,
+        note:       NoteProof,
     ) -> Result<()> {
+        let NoteProof { old_note, new_hash, new_hash2, noise } = note;
         let cfg     = &mut ctx.accounts.dapp_config;
         let profile = &mut ctx.accounts.user_profile;
 
-        require!(profile.claim_flag, ErrorCode::NoClaimPending);
+        let intent  = &mut ctx.accounts.claim_intent;
+
+        intent.check_redeem(session_id, Clock::get()?.unix_timestamp)?;
         require!(ctx.accounts.validator.key() == cfg.validator_address, ErrorCode::InvalidValidator);
         require!(new_hash == new_hash2, ErrorCode::HashMismatch);
 
@@ -138,7 +308,7 @@ pub mod cal_coin {
             game_ts.as_bytes(),
         ]
         .concat();
-        let tag = HMAC::mac(&data, &ctx.accounts.global_key.key);
+        let tag = HMAC::mac(&data, ctx.accounts.global_key.key);
         require!(tag == cfg.stored_hash, ErrorCode::Unauthorized);
         cfg.stored_hash = new_hash;
 
@@ -154,7 +324,7 @@ pub mod cal_coin {
 
         /*── Local-day cap math ─────────────────────────────────────────────────*/
         let now         = Clock::get()?.unix_timestamp;
-        let local_start = ((now + UTC_OFFSET) / SECS_PER_DAY) * SECS_PER_DAY - UTC_OFFSET;
+        let local_start = cfg.day_start(now, profile.daily_reset);
         if profile.daily_reset != local_start {
             profile.daily_reset       = local_start;
             profile.daily_accumulated = 0;
@@ -167,8 +337,20 @@ pub mod cal_coin {
         let used      = to_credit.min(remaining);
         require!(used > 0, ErrorCode::DailyCapReached);
 
-        let user_rate = if profile.is_patron { 1.0 } else { 0.75 };
-        let comm_rate = if profile.is_patron { 0.25 } else { 0.50 };
+        let (user_bps, comm_bps) = if profile.patron_until > now {
+            let pc_info = ctx.accounts.patron_config.to_account_info();
+            if pc_info.data_is_empty() {
+                (LEGACY_PATRON_USER_BPS, LEGACY_PATRON_COMM_BPS)
+            } else {
+                let pc   = Account::<PatronConfig>::try_from(&pc_info)?;
+                let tier = &pc.tiers[profile.patron_tier as usize];
+                (tier.user_bps, tier.comm_bps)
+            }
+        } else {
+            (DEFAULT_USER_BPS, DEFAULT_COMM_BPS)
+        };
+        let user_rate = user_bps as f64 / BPS_DENOMINATOR as f64;
+        let comm_rate = comm_bps as f64 / BPS_DENOMINATOR as f64;
         let user_amt  = (used as f64 / 60.0 * user_rate) as u64;
         let comm_amt  = (used as f64 / 60.0 * comm_rate) as u64;
 
@@ -207,36 +389,366 @@ pub mod cal_coin {
             game_ts,
             tokens: user_amt,
         });
-        profile.claim_flag        = false;
+        intent.active             = false;
         profile.last_paid         = now;
         profile.daily_accumulated = profile.daily_accumulated.saturating_add(used);
         Ok(())
     }
 
+    /*══════════════════════════════════ Patrons ═════════════════════════════════*/
+
+    /// Admin only.
+    pub fn init_patron_config(ctx: Context<InitPatronConfig>, treasury: Pubkey) -> Result<()> {
+        require_role(
+            &ctx.accounts.dapp_config,
+            &ctx.accounts.owner.key(),
+            Role::Admin,
+            ctx.accounts.role_grant.as_deref(),
+        )?;
+        let pc = &mut ctx.accounts.patron_config;
+        pc.treasury = treasury;
+        pc.tiers    = [PatronTier::default(); MAX_PATRON_TIERS];
+        pc.tiers[0] = PatronTier {                    // legacy 1.0 / 0.25 split
+            price_lamports: 0,
+            price_tokens:   0,
+            duration_secs:  30 * SECS_PER_DAY,
+            user_bps:       LEGACY_PATRON_USER_BPS,
+            comm_bps:       LEGACY_PATRON_COMM_BPS,
+        };
+        Ok(())
+    }
+
+    /// Param-manager only.
+    pub fn set_patron_tier(
+        ctx:   Context<SetPatronTier>,
+        index: u8,
+        tier:  PatronTier,
+    ) -> Result<()> {
+        require_role(
+            &ctx.accounts.dapp_config,
+            &ctx.accounts.owner.key(),
+            Role::ParamManager,
+            ctx.accounts.role_grant.as_deref(),
+        )?;
+        require!((index as usize) < MAX_PATRON_TIERS, ErrorCode::InvalidPatronTier);
+        require!(tier.duration_secs > 0, ErrorCode::InvalidPatronTier);
+        require!(
+            tier.user_bps <= BPS_DENOMINATOR && tier.comm_bps <= BPS_DENOMINATOR,
+            ErrorCode::InvalidPatronTier
+        );
+        ctx.accounts.patron_config.tiers[index as usize] = tier;
+        Ok(())
+    }
+
+    /// Treasurer only.
+    pub fn set_patron_treasury(ctx: Context<SetPatronTier>, treasury: Pubkey) -> Result<()> {
+        require_role(
+            &ctx.accounts.dapp_config,
+            &ctx.accounts.owner.key(),
+            Role::Treasurer,
+            ctx.accounts.role_grant.as_deref(),
+        )?;
+        ctx.accounts.patron_config.treasury = treasury;
+        Ok(())
+    }
+
+    /// Pay for `tier` in SOL or cal_coin. An active subscription of the same
+    /// tier is extended; switching tiers is only allowed once it has lapsed.
+    pub fn become_patron(
+        ctx:     Context<BecomePatron>,
+        tier:    u8,
+        payment: PatronPayment,
+    ) -> Result<()> {
+        require!((tier as usize) < MAX_PATRON_TIERS, ErrorCode::InvalidPatronTier);
+        let t   = ctx.accounts.patron_config.tiers[tier as usize];
+        let now = Clock::get()?.unix_timestamp;
+        let active = ctx.accounts.user_profile.patron_until > now;
+        require!(
+            !active || ctx.accounts.user_profile.patron_tier == tier,
+            ErrorCode::PatronTierStillActive
+        );
+
+        match payment {
+            PatronPayment::Sol => {
+                require!(t.price_lamports > 0, ErrorCode::TierNotForSale);
+                system_program::transfer(
+                    CpiContext::new(
+                        ctx.accounts.system_program.to_account_info(),
+                        system_program::Transfer {
+                            from: ctx.accounts.user.to_account_info(),
+                            to:   ctx.accounts.treasury.to_account_info(),
+                        },
+                    ),
+                    t.price_lamports,
+                )?;
+            }
+            PatronPayment::CalCoin => {
+                require!(t.price_tokens > 0, ErrorCode::TierNotForSale);
+                let user_ata     = ctx.accounts.user_ata.as_ref().ok_or(ErrorCode::InvalidAtaAccount)?;
+                let treasury_ata = ctx.accounts.treasury_ata.as_ref().ok_or(ErrorCode::InvalidAtaAccount)?;
+                token_2022::transfer_checked(
+                    CpiContext::new(
+                        ctx.accounts.token_program.to_account_info(),
+                        TransferChecked {
+                            from:      user_ata.to_account_info(),
+                            mint:      ctx.accounts.token_mint.to_account_info(),
+                            to:        treasury_ata.to_account_info(),
+                            authority: ctx.accounts.user.to_account_info(),
+                        },
+                    ),
+                    t.price_tokens,
+                    ctx.accounts.token_mint.decimals,
+                )?;
+            }
+        }
+
+        let profile = &mut ctx.accounts.user_profile;
+        let start   = if active { profile.patron_until } else { now };
+        profile.patron_tier  = tier;
+        profile.patron_until = start.saturating_add(t.duration_secs);
+
+        emit!(PatronEvent {
+            authority: profile.authority,
+            tier,
+            until:     profile.patron_until,
+        });
+        Ok(())
+    }
+
+    /// Moderator grant (`until` in the future) or revoke (`until == 0`).
+    /// Grants must name a tier configured via `set_patron_tier`.
+    pub fn set_patron(ctx: Context<SetPatron>, tier: u8, until: i64) -> Result<()> {
+        require_role(
+            &ctx.accounts.dapp_config,
+            &ctx.accounts.owner.key(),
+            Role::Moderator,
+            ctx.accounts.role_grant.as_deref(),
+        )?;
+        require!((tier as usize) < MAX_PATRON_TIERS, ErrorCode::InvalidPatronTier);
+        if until != 0 {
+            require!(
+                ctx.accounts.patron_config.tiers[tier as usize].is_configured(),
+                ErrorCode::InvalidPatronTier
+            );
+        }
+        let profile = &mut ctx.accounts.user_profile;
+        profile.patron_tier  = tier;
+        profile.patron_until = until;
+
+        emit!(PatronEvent {
+            authority: profile.authority,
+            tier,
+            until,
+        });
+        Ok(())
+    }
+
+    /*═════════════════════════════════ Day boundary ══════════════════════════════*/
+
+    /// Param-manager only.
+    pub fn set_day_policy(
+        ctx:        Context<SetDayPolicy>,
+        policy:     DayPolicy,
+        utc_offset: i64,
+    ) -> Result<()> {
+        let cfg = &mut ctx.accounts.dapp_config;
+        require_role(cfg, &ctx.accounts.owner.key(), Role::ParamManager, ctx.accounts.role_grant.as_deref())?;
+        require!(utc_offset.abs() < SECS_PER_DAY, ErrorCode::InvalidUtcOffset);
+        cfg.day_policy = policy;
+        cfg.utc_offset = utc_offset;
+        Ok(())
+    }
+
+    /// Replace the DST table. Entries must be sorted by `starts_at`; unused
+    /// slots are left zeroed and ignored. Param-manager only.
+    pub fn set_dst_transitions(
+        ctx:         Context<SetDayPolicy>,
+        transitions: Vec<DstTransition>,
+    ) -> Result<()> {
+        require_role(
+            &ctx.accounts.dapp_config,
+            &ctx.accounts.owner.key(),
+            Role::ParamManager,
+            ctx.accounts.role_grant.as_deref(),
+        )?;
+        require!(transitions.len() <= MAX_DST_TRANSITIONS, ErrorCode::InvalidDstTable);
+        require!(
+            transitions.windows(2).all(|w| w[0].starts_at < w[1].starts_at)
+                && transitions.iter().all(|t| t.starts_at > 0 && t.utc_offset.abs() < SECS_PER_DAY),
+            ErrorCode::InvalidDstTable
+        );
+        let cfg = &mut ctx.accounts.dapp_config;
+        cfg.dst_transitions = [DstTransition::default(); MAX_DST_TRANSITIONS];
+        cfg.dst_transitions[..transitions.len()].copy_from_slice(&transitions);
+        Ok(())
+    }
+
     /*═════════════════════════════ Global key bootstrap ═════════════════════════*/
 
+    /// Admin only.
     pub fn init_global_key(
         ctx: Context<InitGlobalKey>,
         secret: [u8; 64],
     ) -> Result<()> {
+        require_role(
+            &ctx.accounts.dapp_config,
+            &ctx.accounts.owner.key(),
+            Role::Admin,
+            ctx.accounts.role_grant.as_deref(),
+        )?;
         let g = &mut ctx.accounts.global_key;
         require!(g.key == [0u8; 64], ErrorCode::AlreadyInitialized);
-        g.key = secret;
+        g.key   = secret;
+        g.epoch = 0;
+        Ok(())
+    }
+
+    /// Swap the H-MAC secret. The tag is checked against the *current* key;
+    /// `new_hash` must already be derived from `new_secret` so the note chain
+    /// continues under the new key. Admin only.
+    pub fn rotate_global_key(
+        ctx: Context<RotateGlobalKey>,
+        note:       NoteProof,
+        new_secret: [u8; 64],
+    ) -> Result<()> {
+        let NoteProof { old_note, new_hash, new_hash2, noise } = note;
+        let state = &mut ctx.accounts.dapp_config;
+        let g     = &mut ctx.accounts.global_key;
+        require_role(state, &ctx.accounts.owner.key(), Role::Admin, ctx.accounts.role_grant.as_deref())?;
+        require!(new_hash == new_hash2, ErrorCode::HashMismatch);
+        require!(new_secret != [0u8; 64] && new_secret != g.key, ErrorCode::InvalidGlobalKey);
+
+        let data = [
+            &old_note[..],
+            &new_hash[..],
+            &noise[..],
+            &new_secret[..],
+            &g.epoch.to_le_bytes()[..],
+        ]
+        .concat();
+        let tag = HMAC::mac(&data, &g.key);
+        require!(tag == state.stored_hash, ErrorCode::Unauthorized);
+
+        g.key             = new_secret;
+        g.epoch          += 1;
+        state.stored_hash = new_hash;
+
+        emit!(GlobalKeyRotated { epoch: g.epoch });
+        Ok(())
+    }
+
+    /// Decommission the key; every H-MAC guarded instruction fails until a
+    /// new one is initialized. The note chain is reset with it, so the first
+    /// guarded call under the new key is the free bootstrap one again; the
+    /// admin should make it in the same transaction as `init_global_key`.
+    /// Admin only.
+    pub fn close_global_key(
+        ctx: Context<CloseGlobalKey>,
+        old_note: [u8; 64],
+        noise:    [u8; 64],
+    ) -> Result<()> {
+        require_role(
+            &ctx.accounts.dapp_config,
+            &ctx.accounts.owner.key(),
+            Role::Admin,
+            ctx.accounts.role_grant.as_deref(),
+        )?;
+        let g    = &ctx.accounts.global_key;
+        let data = [
+            &old_note[..],
+            &noise[..],
+            &g.epoch.to_le_bytes()[..],
+        ]
+        .concat();
+        let tag = HMAC::mac(&data, &g.key);
+        let state = &mut ctx.accounts.dapp_config;
+        require!(tag == state.stored_hash, ErrorCode::Unauthorized);
+        state.stored_hash = [0u8; 64];
+
+        emit!(GlobalKeyClosed { epoch: g.epoch });
+        Ok(())
+    }
+
+    /*════════════════════════════════ Migrations ════════════════════════════════*/
+
+    /// Rewrite a profile created before patron tiers (`claim_flag` /
+    /// `is_patron` layout) into the current one. Legacy patrons keep the old
+    /// 1.0 / 0.25 split on tier 0 with no expiry.
+    pub fn migrate_user_profile(ctx: Context<MigrateUserProfile>) -> Result<()> {
+        let info = ctx.accounts.user_profile.to_account_info();
+        require!(info.data_len() == LEGACY_USER_PROFILE_LEN, ErrorCode::AlreadyMigrated);
+
+        let legacy = {
+            let data = info.try_borrow_data()?;
+            require!(data[..8] == UserProfile::DISCRIMINATOR, ErrorCode::InvalidAccountLayout);
+            LegacyUserProfile::deserialize(&mut &data[8..])?
+        };
+        require!(legacy.authority == ctx.accounts.user.key(), ErrorCode::Unauthorized);
+
+        grow_account(&info, &ctx.accounts.user, &ctx.accounts.system_program, UserProfile::LEN)?;
+        let mut data = info.try_borrow_mut_data()?;
+        legacy.into_profile().try_serialize(&mut &mut data[..])?;
         Ok(())
     }
 
+    /// Grow a `DappConfig` written by an older layout to the current size and
+    /// give the appended fields their defaults.
+    pub fn migrate_dapp_config(ctx: Context<MigrateDappConfig>) -> Result<()> {
+        let info    = ctx.accounts.dapp_config.to_account_info();
+        let old_len = info.data_len();
+        require!(old_len < DappConfig::LEN, ErrorCode::AlreadyMigrated);
+        {
+            let data = info.try_borrow_data()?;
+            require!(data[..8] == DappConfig::DISCRIMINATOR, ErrorCode::InvalidAccountLayout);
+            require!(
+                data[8..40] == ctx.accounts.owner.key().to_bytes(),  // `owner` is the first field
+                ErrorCode::Unauthorized
+            );
+        }
+
+        grow_account(&info, &ctx.accounts.owner, &ctx.accounts.system_program, DappConfig::LEN)?;
+        let mut cfg = DappConfig::try_deserialize(&mut &info.try_borrow_data()?[..])?;
+        if old_len < DappConfig::LEN_WITH_DAY_POLICY {
+            cfg.day_policy = DayPolicy::FixedOffset;
+            cfg.utc_offset = DEFAULT_UTC_OFFSET;
+        }
+        cfg.try_serialize(&mut &mut info.try_borrow_mut_data()?[..])?;
+        Ok(())
+    }
+
+    /// Grow a pre-rotation `GlobalKey` (no `epoch`) in place; the appended
+    /// epoch reads as 0. Admin only.
+    pub fn migrate_global_key(ctx: Context<MigrateGlobalKey>) -> Result<()> {
+        require_role(
+            &ctx.accounts.dapp_config,
+            &ctx.accounts.owner.key(),
+            Role::Admin,
+            ctx.accounts.role_grant.as_deref(),
+        )?;
+        let info = ctx.accounts.global_key.to_account_info();
+        require!(info.data_len() == LEGACY_GLOBAL_KEY_SPACE, ErrorCode::AlreadyMigrated);
+        require!(
+            info.try_borrow_data()?[..8] == GlobalKey::DISCRIMINATOR,
+            ErrorCode::InvalidAccountLayout
+        );
+        grow_account(&info, &ctx.accounts.owner, &ctx.accounts.system_program, GLOBAL_KEY_SPACE)
+    }
+
     /*════════════════════════════════ Admin setters ═════════════════════════════*/
 
+    // Each setter needs both the caller's role and the next tag of the H-MAC
+    // note chain; the role says who may change it, the tag that EzHard
+    // agreed to this particular change.
+
+    /// Admin only.
     pub fn set_validator_address(
         ctx: Context<SetValidator>,
-        old_note: [u8; 64],
-        new_hash: [u8; 64],
-        new_hash2:[u8; 64],
-        noise:    [u8; 64],
+        note: NoteProof,
         new_validator: Pubkey,
     ) -> Result<()> {
+        let NoteProof { old_note, new_hash, new_hash2, noise } = note;
         let state = &mut ctx.accounts.dapp_config;
-        require!(ctx.accounts.owner.key() == state.owner, ErrorCode::Unauthorized);
+        require_role(state, &ctx.accounts.owner.key(), Role::Admin, ctx.accounts.role_grant.as_deref())?;
         require!(new_hash == new_hash2, ErrorCode::HashMismatch);
 
         let data = [
@@ -246,7 +758,7 @@ pub mod cal_coin {
             &new_validator.to_bytes()[..],
         ]
         .concat();
-        let tag = HMAC::mac(&data, &ctx.accounts.global_key.key);
+        let tag = HMAC::mac(&data, ctx.accounts.global_key.key);
 
         /* first-call-is-free */
         let first_time = state.stored_hash == [0u8; 64];
@@ -259,15 +771,15 @@ pub mod cal_coin {
         Ok(())
     }
 
+    /// Admin only.
     pub fn set_exception_address(
         ctx: Context<SetException>,
-        old_note: [u8; 64],
-        new_hash: [u8; 64],
-        new_hash2:[u8; 64],
-        noise:    [u8; 64],
+        note: NoteProof,
         new_exempt: Pubkey,
     ) -> Result<()> {
+        let NoteProof { old_note, new_hash, new_hash2, noise } = note;
         let state = &mut ctx.accounts.dapp_config;
+        require_role(state, &ctx.accounts.admin.key(), Role::Admin, ctx.accounts.role_grant.as_deref())?;
         require!(new_hash == new_hash2, ErrorCode::HashMismatch);
 
         let data = [
@@ -277,7 +789,7 @@ pub mod cal_coin {
             &new_exempt.to_bytes()[..],
         ]
         .concat();
-        let tag = HMAC::mac(&data, &ctx.accounts.global_key.key);
+        let tag = HMAC::mac(&data, ctx.accounts.global_key.key);
 
         let first_time = state.stored_hash == [0u8; 64];
         if !first_time {
@@ -290,15 +802,19 @@ pub mod cal_coin {
         Ok(())
     }
 
+    /// Queue a commission change as admin op `id`, to run no earlier than
+    /// `eta`. The tag is checked now; `execute_admin_op` applies it after the
+    /// delay. Param-manager only.
     pub fn update_commission_bps(
         ctx: Context<SetCommissionBps>,
-        old_note: [u8; 64],
-        new_hash: [u8; 64],
-        new_hash2:[u8; 64],
-        noise:    [u8; 64],
+        note:     NoteProof,
         new_bps:  u16,
+        id:       u64,
+        eta:      i64,
     ) -> Result<()> {
+        let NoteProof { old_note, new_hash, new_hash2, noise } = note;
         let state = &mut ctx.accounts.dapp_config;
+        require_role(state, &ctx.accounts.admin.key(), Role::ParamManager, ctx.accounts.role_grant.as_deref())?;
         require!(new_hash == new_hash2, ErrorCode::HashMismatch);
 
         let data = [
@@ -308,28 +824,36 @@ pub mod cal_coin {
             &new_bps.to_le_bytes()[..],
         ]
         .concat();
-        let tag = HMAC::mac(&data, &ctx.accounts.global_key.key);
+        let tag = HMAC::mac(&data, ctx.accounts.global_key.key);
 
         let first_time = state.stored_hash == [0u8; 64];
         if !first_time {
             require!(tag == state.stored_hash, ErrorCode::Unauthorized);
         }
-        require!(new_bps <= 2000, ErrorCode::InvalidCommissionBps);
-
-        state.commission_bps = new_bps;
-        state.stored_hash    = new_hash;
-        Ok(())
+        require!(new_bps <= MAX_COMMISSION_BPS, ErrorCode::InvalidCommissionBps);
+        state.stored_hash = new_hash;
+
+        queue_op(
+            &mut ctx.accounts.queued_op,
+            id,
+            AdminOp::SetCommissionBps(new_bps),
+            eta,
+            ctx.accounts.admin.key(),
+        )
     }
 
+    /// Queue a gateway network change as admin op `id`, like
+    /// `update_commission_bps`. Admin only.
     pub fn update_gateway_network(
         ctx: Context<SetGatewayNetwork>,
-        old_note: [u8; 64],
-        new_hash: [u8; 64],
-        new_hash2:[u8; 64],
-        noise:    [u8; 64],
+        note:     NoteProof,
         new_net:  Pubkey,
+        id:       u64,
+        eta:      i64,
     ) -> Result<()> {
+        let NoteProof { old_note, new_hash, new_hash2, noise } = note;
         let state = &mut ctx.accounts.dapp_config;
+        require_role(state, &ctx.accounts.admin.key(), Role::Admin, ctx.accounts.role_grant.as_deref())?;
         require!(new_hash == new_hash2, ErrorCode::HashMismatch);
 
         let data = [
@@ -339,20 +863,133 @@ pub mod cal_coin {
             &new_net.to_bytes()[..],
         ]
         .concat();
-        let tag = HMAC::mac(&data, &ctx.accounts.global_key.key);
+        let tag = HMAC::mac(&data, ctx.accounts.global_key.key);
 
         let first_time = state.stored_hash == [0u8; 64];
         if !first_time {
             require!(tag == state.stored_hash, ErrorCode::Unauthorized);
         }
 
-        state.gatekeeper_network = new_net;
-        state.gateway_updates   += 1;
-        state.stored_hash        = new_hash;
+        state.stored_hash = new_hash;
+
+        queue_op(
+            &mut ctx.accounts.queued_op,
+            id,
+            AdminOp::SetGatewayNetwork(new_net),
+            eta,
+            ctx.accounts.admin.key(),
+        )
+    }
+
+    /*═══════════════════════════════════ Timelock ═══════════════════════════════*/
+
+    /// Apply queued op `id` once its ETA has passed. Anyone may execute; the
+    /// rent goes back to whoever queued it.
+    pub fn execute_admin_op(ctx: Context<ExecuteAdminOp>, id: u64) -> Result<()> {
+        let queued = &ctx.accounts.queued_op;
+        queued.check_ready(Clock::get()?.unix_timestamp)?;
+        queued.op.apply(&mut ctx.accounts.dapp_config)?;
+
+        emit!(AdminOpExecuted { id, op: queued.op, executor: ctx.accounts.executor.key() });
+        Ok(())
+    }
+
+    /// Drop queued op `id` before it runs. Guardian only.
+    pub fn cancel_admin_op(ctx: Context<CancelAdminOp>, id: u64) -> Result<()> {
+        let guardian = ctx.accounts.guardian.key();
+        require_role(&ctx.accounts.dapp_config, &guardian, Role::Guardian, ctx.accounts.role_grant.as_deref())?;
+
+        emit!(AdminOpCancelled { id, op: ctx.accounts.queued_op.op, guardian });
         Ok(())
     }
 }
 
+/*═══════════════════════════════════ Helpers ════════════════════════════════════*/
+
+/// Usernames double as PDA seeds, so they are stored in canonical form:
+/// ASCII-lowercased, then limited to letters, digits and `_`. Account seeds
+/// apply the same `to_ascii_lowercase` to the raw argument.
+fn normalize_username(name: &str) -> Result<String> {
+    let name = name.to_ascii_lowercase();
+    require!(name.len() <= USERNAME_MAX_LEN, ErrorCode::UsernameTooLong);
+    require!(
+        !name.is_empty()
+            && name.bytes().all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'_'),
+        ErrorCode::InvalidUsername
+    );
+    Ok(name)
+}
+
+/// The owner holds every role; anyone else needs a `RoleGrant` for it.
+fn require_role(
+    cfg:    &DappConfig,
+    signer: &Pubkey,
+    role:   Role,
+    grant:  Option<&RoleGrant>,
+) -> Result<()> {
+    if *signer == cfg.owner {
+        return Ok(());
+    }
+    require!(
+        grant.is_some_and(|g| g.grantee == *signer && g.role == role),
+        ErrorCode::MissingRole
+    );
+    Ok(())
+}
+
+/// Fill a freshly created `QueuedOp` and announce it. `eta` must be at least
+/// MIN_TIMELOCK_DELAY away.
+fn queue_op(queued: &mut QueuedOp, id: u64, op: AdminOp, eta: i64, admin: Pubkey) -> Result<()> {
+    let now = Clock::get()?.unix_timestamp;
+    require!(eta >= now.saturating_add(MIN_TIMELOCK_DELAY), ErrorCode::TimelockDelayTooShort);
+
+    queued.id        = id;
+    queued.op        = op;
+    queued.eta       = eta;
+    queued.queued_by = admin;
+    queued.queued_at = now;
+
+    emit!(AdminOpQueued { id, op, eta, queued_by: admin });
+    Ok(())
+}
+
+/// Whether `info` is a live `UsernameRecord` owned by `authority`.
+fn holds_record(info: &AccountInfo, authority: &Pubkey) -> Result<bool> {
+    if info.owner != &crate::ID || info.data_is_empty() {
+        return Ok(false);
+    }
+    let record = UsernameRecord::try_deserialize(&mut &info.try_borrow_data()?[..])?;
+    Ok(record.authority == *authority)
+}
+
+/// Top `info` up to rent exemption at `new_len` from `payer`, then grow it in
+/// place (zero-filled). Migrations use this on accounts written by an older
+/// layout, which `Account<T>` can no longer load.
+fn grow_account<'info>(
+    info:           &AccountInfo<'info>,
+    payer:          &Signer<'info>,
+    system_program: &Program<'info, System>,
+    new_len:        usize,
+) -> Result<()> {
+    let shortfall = Rent::get()?
+        .minimum_balance(new_len)
+        .saturating_sub(info.lamports());
+    if shortfall > 0 {
+        system_program::transfer(
+            CpiContext::new(
+                system_program.to_account_info(),
+                system_program::Transfer {
+                    from: payer.to_account_info(),
+                    to:   info.clone(),
+                },
+            ),
+            shortfall,
+        )?;
+    }
+    info.realloc(new_len, true)?;
+    Ok(())
+}
+
 /*════════════════════════════════ Data Accounts ═════════════════════════════════*/
 
 #[account]
@@ -369,6 +1006,9 @@ pub struct DappConfig {
     pub stored_hash:        [u8; 64],
     pub gateway_updates:    u64,
     pub initialized:        bool,
+    pub day_policy:         DayPolicy,
+    pub utc_offset:         i64,
+    pub dst_transitions:    [DstTransition; MAX_DST_TRANSITIONS],
 }
 impl DappConfig {
     pub const LEN: usize = 8
@@ -378,8 +1018,55 @@ impl DappConfig {
         + 2        // bps
         + 64       // stored_hash
         + 8        // gateway_updates
-        + 1;       // initialized
+        + 1        // initialized
+        + 1        // day_policy
+        + 8        // utc_offset
+        + DstTransition::LEN * MAX_DST_TRANSITIONS;
+
+    /// Size once the day-boundary fields were appended; anything shorter is
+    /// the original layout. Used by `migrate_dapp_config`.
+    pub const LEN_WITH_DAY_POLICY: usize = 8
+        + 32 * 6 + 8 + 1 + 2 + 64 + 8 + 1
+        + 1 + 8 + DstTransition::LEN * MAX_DST_TRANSITIONS;
+
+    /// Start of the daily-cap window containing `now`. `prev` is the user's
+    /// current `daily_reset`, only consulted by the rolling policy.
+    pub fn day_start(&self, now: i64, prev: i64) -> i64 {
+        match self.day_policy {
+            DayPolicy::Rolling24h => {
+                if now - prev >= SECS_PER_DAY { now } else { prev }
+            }
+            DayPolicy::FixedOffset => local_midnight(now, self.utc_offset),
+            DayPolicy::DstTable => {
+                let offset = self
+                    .dst_transitions
+                    .iter()
+                    .take_while(|t| t.starts_at != 0 && t.starts_at <= now)
+                    .last()
+                    .map_or(self.utc_offset, |t| t.utc_offset);
+                local_midnight(now, offset)
+            }
+        }
+    }
+}
+
+fn local_midnight(now: i64, utc_offset: i64) -> i64 {
+    (now + utc_offset).div_euclid(SECS_PER_DAY) * SECS_PER_DAY - utc_offset
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum DayPolicy {
+    FixedOffset,     // calendar day at `utc_offset`
+    Rolling24h,      // 24h from the user's first payout of the window
+    DstTable,        // calendar day at the offset of the latest `dst_transitions` entry
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct DstTransition {
+    pub starts_at:  i64,      // unix ts; 0 marks an unused slot
+    pub utc_offset: i64,      // seconds east of UTC from `starts_at` on
 }
+impl DstTransition { pub const LEN: usize = 8 + 8; }
 
 #[account]
 pub struct MintAuthority { pub bump: u8 }
@@ -392,17 +1079,192 @@ pub struct UserProfile {
     pub last_username_set: i64,
     pub last_claimed:      i64,
     pub last_paid:         i64,
-    pub claim_flag:        bool,
-    pub is_patron:         bool,
+    pub patron_tier:       u8,
+    pub patron_until:      i64,
     pub daily_accumulated: u64,
     pub daily_reset:       i64,
 }
 impl UserProfile {
     pub const LEN: usize = 8 + 32 + 4 + USERNAME_MAX_LEN
-        + 8 * 3 + 1 + 1
+        + 8 * 3 + 1 + 8
         + 8 + 8;
 }
 
+/// `UserProfile` as laid out before patron tiers; only read by
+/// `migrate_user_profile`.
+pub const LEGACY_USER_PROFILE_LEN: usize = 8 + 32 + 4 + USERNAME_MAX_LEN
+    + 8 * 3 + 1 + 1
+    + 8 + 8;
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct LegacyUserProfile {
+    pub authority:         Pubkey,
+    pub username:          String,
+    pub last_username_set: i64,
+    pub last_claimed:      i64,
+    pub last_paid:         i64,
+    pub claim_flag:        bool,
+    pub is_patron:         bool,
+    pub daily_accumulated: u64,
+    pub daily_reset:       i64,
+}
+impl LegacyUserProfile {
+    /// A pending `claim_flag` is dropped; the user opens a fresh intent.
+    pub fn into_profile(self) -> UserProfile {
+        UserProfile {
+            authority:         self.authority,
+            username:          self.username,
+            last_username_set: self.last_username_set,
+            last_claimed:      self.last_claimed,
+            last_paid:         self.last_paid,
+            patron_tier:       0,
+            patron_until:      if self.is_patron { i64::MAX } else { 0 },
+            daily_accumulated: self.daily_accumulated,
+            daily_reset:       self.daily_reset,
+        }
+    }
+}
+
+/// Pending claim → payout handshake, one per user at `["claim_intent", user]`.
+#[account]
+pub struct ClaimIntent {
+    pub authority:  Pubkey,
+    pub session_id: u64,
+    pub created_at: i64,
+    pub expires_at: i64,
+    pub active:     bool,
+}
+impl ClaimIntent {
+    pub const LEN: usize = 8 + 32 + 8 + 8 + 8 + 1;
+
+    pub fn is_live(&self, now: i64) -> bool {
+        self.active && now <= self.expires_at
+    }
+
+    /// A payout may only redeem the live intent opened for the same session.
+    pub fn check_redeem(&self, session_id: u64, now: i64) -> Result<()> {
+        require!(self.active, ErrorCode::NoClaimPending);
+        require!(now <= self.expires_at, ErrorCode::ClaimExpired);
+        require!(self.session_id == session_id, ErrorCode::SessionMismatch);
+        Ok(())
+    }
+}
+
+/// Name → authority lookup; the PDA at `["username", name]` exists iff the
+/// name is taken.
+#[account]
+pub struct UsernameRecord {
+    pub authority:  Pubkey,
+    pub username:   String,
+    pub claimed_at: i64,
+}
+impl UsernameRecord {
+    pub const LEN: usize = 8 + 32 + 4 + USERNAME_MAX_LEN + 8;
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct PatronTier {
+    pub price_lamports: u64,      // 0 → not purchasable in SOL
+    pub price_tokens:   u64,      // 0 → not purchasable in cal_coin
+    pub duration_secs:  i64,
+    pub user_bps:       u16,      // per-minute payout to the user
+    pub comm_bps:       u16,      // per-minute payout to commission
+}
+impl PatronTier {
+    pub const LEN: usize = 8 + 8 + 8 + 2 + 2;
+
+    /// Unset slots are all-zero; `set_patron_tier` never writes a zero duration.
+    pub fn is_configured(&self) -> bool {
+        self.duration_secs > 0
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum PatronPayment {
+    Sol,
+    CalCoin,
+}
+
+#[account]
+pub struct PatronConfig {
+    pub treasury: Pubkey,
+    pub tiers:    [PatronTier; MAX_PATRON_TIERS],
+}
+impl PatronConfig {
+    pub const LEN: usize = 8 + 32 + PatronTier::LEN * MAX_PATRON_TIERS;
+}
+
+/// Privileged roles. The owner implicitly holds all of them.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Role {
+    Admin,          // Mint, global key, validator / exempt / gateway, role grants
+    Pauser,         // Reserved; this program has nothing to pause yet
+    ParamManager,   // Commission, day boundary, patron tiers
+    Moderator,      // Patron grants and revokes
+    Treasurer,      // Patron treasury
+    Validator,      // Reserved; claims are still checked against `validator_address`
+    Guardian,       // Cancelling queued admin ops
+}
+
+/// One role held by one key. PDA: [ROLE_SEED, role, grantee].
+#[account]
+pub struct RoleGrant {
+    pub role:       Role,
+    pub grantee:    Pubkey,
+    pub granted_by: Pubkey,
+    pub granted_at: i64,
+}
+impl RoleGrant {
+    pub const LEN: usize = 8 + 1 + 32 + 32 + 8;
+}
+
+/// Config changes that wait out the timelock.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub enum AdminOp {
+    SetCommissionBps(u16),
+    SetGatewayNetwork(Pubkey),
+}
+impl AdminOp {
+    pub const MAX_LEN: usize = 1 + 32;   // tag + largest payload
+
+    pub fn apply(&self, cfg: &mut DappConfig) -> Result<()> {
+        match *self {
+            AdminOp::SetCommissionBps(bps) => {
+                require!(bps <= MAX_COMMISSION_BPS, ErrorCode::InvalidCommissionBps);
+                cfg.commission_bps = bps;
+            }
+            AdminOp::SetGatewayNetwork(net) => {
+                cfg.gatekeeper_network = net;
+                cfg.gateway_updates   += 1;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// An admin op waiting out the timelock. PDA: [QUEUED_OP_SEED, id_le].
+#[account]
+pub struct QueuedOp {
+    pub id:        u64,
+    pub op:        AdminOp,
+    pub eta:       i64,      // earliest execution time
+    pub queued_by: Pubkey,   // paid for the account; gets the rent back
+    pub queued_at: i64,
+}
+impl QueuedOp {
+    pub const LEN: usize = 8 + 8 + AdminOp::MAX_LEN + 8 + 32 + 8;
+
+    /// Executable from `eta` until TIMELOCK_GRACE_PERIOD after it.
+    pub fn check_ready(&self, now: i64) -> Result<()> {
+        require!(now >= self.eta, ErrorCode::TimelockNotReady);
+        require!(
+            now <= self.eta.saturating_add(TIMELOCK_GRACE_PERIOD),
+            ErrorCode::TimelockExpired
+        );
+        Ok(())
+    }
+}
+
 
 #[derive(Accounts)]
 pub struct InitializeDapp<'info> {
@@ -415,6 +1277,14 @@ pub struct InitializeDapp<'info> {
     )]
     pub dapp_config: Account<'info, DappConfig>,
     #[account(mut)] pub payer: Signer<'info>,
+    #[account(constraint = program.programdata_address()? == Some(program_data.key()))]
+    pub program:        Program<'info, crate::program::CalCoin>,
+    /// The payer becomes the owner, so it must be the upgrade authority
+    #[account(
+        constraint = program_data.upgrade_authority_address == Some(payer.key())
+            @ ErrorCode::Unauthorized
+    )]
+    pub program_data:   Account<'info, ProgramData>,
     pub system_program: Program<'info, System>,
     pub rent:           Sysvar<'info, Rent>,
 }
@@ -424,8 +1294,7 @@ pub struct InitializeMint<'info> {
     #[account(
         mut,
         seeds = [b"dapp_config"],
-        bump,
-        has_one = owner
+        bump
     )]
     pub dapp_config:    Account<'info, DappConfig>,
     #[account(mut)] pub owner: Signer<'info>,
@@ -449,9 +1318,12 @@ pub struct InitializeMint<'info> {
     pub token_program:  Program<'info, Token2022>,
     pub system_program: Program<'info, System>,
     pub rent:           Sysvar<'info, Rent>,
+    /// Caller's RoleGrant; not needed when the caller is the owner
+    pub role_grant: Option<Account<'info, RoleGrant>>,
 }
 
 #[derive(Accounts)]
+#[instruction(username: String)]
 pub struct RegisterUser<'info> {
     #[account(seeds = [b"dapp_config"], bump)]
     pub dapp_config: Account<'info, DappConfig>,
@@ -465,10 +1337,75 @@ pub struct RegisterUser<'info> {
         bump
     )]
     pub user_profile: Account<'info, UserProfile>,
+    #[account(
+        init,
+        payer  = user,
+        space  = UsernameRecord::LEN,
+        seeds  = [b"username", username.to_ascii_lowercase().as_bytes()],
+        bump
+    )]
+    pub username_record: Account<'info, UsernameRecord>,
     pub system_program: Program<'info, System>,
     pub rent:           Sysvar<'info, Rent>,
 }
 
+#[derive(Accounts)]
+#[instruction(new_username: String)]
+pub struct ChangeUsername<'info> {
+    #[account(mut)] pub user: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"user_pda", user.key().as_ref()],
+        bump
+    )]
+    pub user_profile: Account<'info, UserProfile>,
+    #[account(
+        mut,
+        close  = user,
+        seeds  = [b"username", user_profile.username.as_bytes()],
+        bump,
+        constraint = old_record.authority == user.key() @ ErrorCode::Unauthorized
+    )]
+    pub old_record: Account<'info, UsernameRecord>,
+    #[account(
+        init,
+        payer  = user,
+        space  = UsernameRecord::LEN,
+        seeds  = [b"username", new_username.to_ascii_lowercase().as_bytes()],
+        bump
+    )]
+    pub new_record: Account<'info, UsernameRecord>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(username: String)]
+pub struct ClaimLegacyUsername<'info> {
+    #[account(mut)] pub user: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"user_pda", user.key().as_ref()],
+        bump
+    )]
+    pub user_profile: Account<'info, UserProfile>,
+    /// CHECK: record PDA of the profile's current name; only read, and only
+    /// required when claiming a different name
+    #[account(
+        seeds = [b"username", user_profile.username.to_ascii_lowercase().as_bytes()],
+        bump
+    )]
+    pub current_record: Option<UncheckedAccount<'info>>,
+    #[account(
+        init,
+        payer  = user,
+        space  = UsernameRecord::LEN,
+        seeds  = [b"username", username.to_ascii_lowercase().as_bytes()],
+        bump
+    )]
+    pub new_record: Account<'info, UsernameRecord>,
+    pub system_program: Program<'info, System>,
+}
+
 #[derive(Accounts)]
 pub struct Claim<'info> {
     #[account(seeds = [b"dapp_config"], bump)]
@@ -481,6 +1418,35 @@ pub struct Claim<'info> {
         bump
     )]
     pub user_profile: Account<'info, UserProfile>,
+    #[account(
+        init_if_needed,
+        payer  = user,
+        space  = ClaimIntent::LEN,
+        seeds  = [b"claim_intent", user.key().as_ref()],
+        bump
+    )]
+    pub claim_intent:   Account<'info, ClaimIntent>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CancelClaim<'info> {
+    pub user: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"claim_intent", user.key().as_ref()],
+        bump
+    )]
+    pub claim_intent: Account<'info, ClaimIntent>,
+}
+
+#[derive(Accounts)]
+pub struct RejectClaim<'info> {
+    #[account(seeds = [b"dapp_config"], bump)]
+    pub dapp_config: Account<'info, DappConfig>,
+    pub validator: Signer<'info>,
+    #[account(mut)]
+    pub claim_intent: Account<'info, ClaimIntent>,
 }
 
 #[derive(Accounts)]
@@ -495,6 +1461,12 @@ pub struct Payout<'info> {
         bump
     )]
     pub user_profile: Account<'info, UserProfile>,
+    #[account(
+        mut,
+        seeds = [b"claim_intent", user.key().as_ref()],
+        bump
+    )]
+    pub claim_intent: Account<'info, ClaimIntent>,
     #[account(mut)] pub user: Signer<'info>,
     #[account(
         mut,
@@ -508,57 +1480,350 @@ pub struct Payout<'info> {
     pub mint_authority: Account<'info, MintAuthority>,
     #[account(address = TOKEN_2022_PROGRAM_ID)]
     pub token_program: Program<'info, Token2022>,
-    pub global_key:    UncheckedAccount<'info>,
+    #[account(seeds = [b"global_key"], bump)]
+    pub global_key:    Account<'info, GlobalKey>,
+    /// CHECK: address pinned by seeds. Still empty before `init_patron_config`,
+    /// in which case patrons get the legacy split; read as `PatronConfig` after.
+    #[account(seeds = [b"patron_config"], bump)]
+    pub patron_config: UncheckedAccount<'info>,
     pub associated_token_program: Program<'info, AssociatedToken>,
     // ATAs supplied via ctx.remaining_accounts
 }
 
+#[derive(Accounts)]
+pub struct SetDayPolicy<'info> {
+    #[account(mut, seeds = [b"dapp_config"], bump)]
+    pub dapp_config: Account<'info, DappConfig>,
+    pub owner: Signer<'info>,
+    /// Caller's RoleGrant; not needed when the caller is the owner
+    pub role_grant: Option<Account<'info, RoleGrant>>,
+}
+
+#[derive(Accounts)]
+pub struct InitPatronConfig<'info> {
+    #[account(seeds = [b"dapp_config"], bump)]
+    pub dapp_config: Account<'info, DappConfig>,
+    #[account(mut)] pub owner: Signer<'info>,
+    #[account(
+        init,
+        payer  = owner,
+        space  = PatronConfig::LEN,
+        seeds  = [b"patron_config"],
+        bump
+    )]
+    pub patron_config:  Account<'info, PatronConfig>,
+    pub system_program: Program<'info, System>,
+    /// Caller's RoleGrant; not needed when the caller is the owner
+    pub role_grant: Option<Account<'info, RoleGrant>>,
+}
+
+#[derive(Accounts)]
+pub struct SetPatronTier<'info> {
+    #[account(seeds = [b"dapp_config"], bump)]
+    pub dapp_config: Account<'info, DappConfig>,
+    pub owner: Signer<'info>,
+    #[account(mut, seeds = [b"patron_config"], bump)]
+    pub patron_config: Account<'info, PatronConfig>,
+    /// Caller's RoleGrant; not needed when the caller is the owner
+    pub role_grant: Option<Account<'info, RoleGrant>>,
+}
+
+#[derive(Accounts)]
+pub struct BecomePatron<'info> {
+    #[account(seeds = [b"dapp_config"], bump)]
+    pub dapp_config: Account<'info, DappConfig>,
+    #[account(mut)] pub user: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"user_pda", user.key().as_ref()],
+        bump
+    )]
+    pub user_profile:  Account<'info, UserProfile>,
+    #[account(seeds = [b"patron_config"], bump)]
+    pub patron_config: Account<'info, PatronConfig>,
+    /// CHECK: SOL destination, address pinned to `patron_config.treasury`
+    #[account(mut, address = patron_config.treasury)]
+    pub treasury:      UncheckedAccount<'info>,
+    #[account(constraint = token_mint.key() == dapp_config.token_mint)]
+    pub token_mint:    InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        associated_token::mint      = token_mint,
+        associated_token::authority = user
+    )]
+    pub user_ata:      Option<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        associated_token::mint      = token_mint,
+        associated_token::authority = treasury
+    )]
+    pub treasury_ata:  Option<InterfaceAccount<'info, TokenAccount>>,
+    #[account(address = TOKEN_2022_PROGRAM_ID)]
+    pub token_program:  Program<'info, Token2022>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SetPatron<'info> {
+    #[account(seeds = [b"dapp_config"], bump)]
+    pub dapp_config: Account<'info, DappConfig>,
+    pub owner: Signer<'info>,
+    #[account(seeds = [b"patron_config"], bump)]
+    pub patron_config: Account<'info, PatronConfig>,
+    #[account(mut)]
+    pub user_profile: Account<'info, UserProfile>,
+    /// Caller's RoleGrant; not needed when the caller is the owner
+    pub role_grant: Option<Account<'info, RoleGrant>>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateDappConfig<'info> {
+    #[account(mut)] pub owner: Signer<'info>,
+    /// CHECK: may still be in an older layout; discriminator and owner are
+    /// checked by hand in the handler
+    #[account(
+        mut,
+        owner = crate::ID,
+        seeds = [b"dapp_config"],
+        bump
+    )]
+    pub dapp_config:    UncheckedAccount<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateUserProfile<'info> {
+    #[account(mut)] pub user: Signer<'info>,
+    /// CHECK: legacy layout, decoded by hand in the handler
+    #[account(
+        mut,
+        owner = crate::ID,
+        seeds = [b"user_pda", user.key().as_ref()],
+        bump
+    )]
+    pub user_profile:   UncheckedAccount<'info>,
+    pub system_program: Program<'info, System>,
+}
+
 #[derive(Accounts)]
 pub struct SetValidator<'info> {
     #[account(mut)] pub dapp_config: Account<'info, DappConfig>,
     #[account(mut)] pub owner: Signer<'info>,
-    pub global_key: UncheckedAccount<'info>,
+    #[account(seeds = [b"global_key"], bump)]
+    pub global_key: Account<'info, GlobalKey>,
+    /// Caller's RoleGrant; not needed when the caller is the owner
+    pub role_grant: Option<Account<'info, RoleGrant>>,
 }
 
 #[derive(Accounts)]
 pub struct SetException<'info> {
     #[account(mut)] pub dapp_config: Account<'info, DappConfig>,
-    pub global_key: UncheckedAccount<'info>,
+    pub admin: Signer<'info>,
+    #[account(seeds = [b"global_key"], bump)]
+    pub global_key: Account<'info, GlobalKey>,
+    /// Caller's RoleGrant; not needed when the caller is the owner
+    pub role_grant: Option<Account<'info, RoleGrant>>,
 }
 
 #[derive(Accounts)]
+#[instruction(
+    note:      NoteProof,
+    new_bps:  u16,
+    id:        u64,
+)]
 pub struct SetCommissionBps<'info> {
     #[account(mut)] pub dapp_config: Account<'info, DappConfig>,
-    pub global_key: UncheckedAccount<'info>,
+    #[account(mut)] pub admin: Signer<'info>,
+    #[account(seeds = [b"global_key"], bump)]
+    pub global_key: Account<'info, GlobalKey>,
+    #[account(
+        init,
+        payer  = admin,
+        space  = QueuedOp::LEN,
+        seeds  = [QUEUED_OP_SEED, &id.to_le_bytes()],
+        bump
+    )]
+    pub queued_op:      Account<'info, QueuedOp>,
+    pub system_program: Program<'info, System>,
+    /// Caller's RoleGrant; not needed when the caller is the owner
+    pub role_grant: Option<Account<'info, RoleGrant>>,
 }
 
 #[derive(Accounts)]
+#[instruction(
+    note:      NoteProof,
+    new_net:  Pubkey,
+    id:        u64,
+)]
 pub struct SetGatewayNetwork<'info> {
     #[account(mut)] pub dapp_config: Account<'info, DappConfig>,
-    pub global_key: UncheckedAccount<'info>,
+    #[account(mut)] pub admin: Signer<'info>,
+    #[account(seeds = [b"global_key"], bump)]
+    pub global_key: Account<'info, GlobalKey>,
+    #[account(
+        init,
+        payer  = admin,
+        space  = QueuedOp::LEN,
+        seeds  = [QUEUED_OP_SEED, &id.to_le_bytes()],
+        bump
+    )]
+    pub queued_op:      Account<'info, QueuedOp>,
+    pub system_program: Program<'info, System>,
+    /// Caller's RoleGrant; not needed when the caller is the owner
+    pub role_grant: Option<Account<'info, RoleGrant>>,
+}
+
+#[derive(Accounts)]
+#[instruction(id: u64)]
+pub struct ExecuteAdminOp<'info> {
+    #[account(mut, seeds = [b"dapp_config"], bump)]
+    pub dapp_config: Account<'info, DappConfig>,
+    pub executor: Signer<'info>,
+    #[account(
+        mut,
+        close  = queued_by,
+        seeds  = [QUEUED_OP_SEED, &id.to_le_bytes()],
+        bump
+    )]
+    pub queued_op: Account<'info, QueuedOp>,
+    /// CHECK: receives the queued op's rent
+    #[account(mut, address = queued_op.queued_by)]
+    pub queued_by: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(id: u64)]
+pub struct CancelAdminOp<'info> {
+    #[account(seeds = [b"dapp_config"], bump)]
+    pub dapp_config: Account<'info, DappConfig>,
+    pub guardian: Signer<'info>,
+    #[account(
+        mut,
+        close  = queued_by,
+        seeds  = [QUEUED_OP_SEED, &id.to_le_bytes()],
+        bump
+    )]
+    pub queued_op: Account<'info, QueuedOp>,
+    /// CHECK: receives the queued op's rent
+    #[account(mut, address = queued_op.queued_by)]
+    pub queued_by: UncheckedAccount<'info>,
+    /// Caller's guardian RoleGrant; not needed when the caller is the owner
+    pub role_grant: Option<Account<'info, RoleGrant>>,
 }
 
 /*═══════════════════════════════ Global H-MAC key account ═══════════════════════════*/
 
-pub const GLOBAL_KEY_SPACE: usize = 8 + 64;
+pub const GLOBAL_KEY_SPACE: usize = 8 + 64 + 8;
+pub const LEGACY_GLOBAL_KEY_SPACE: usize = 8 + 64;      // before `epoch`
 #[account]
 pub struct GlobalKey {
-    pub key: [u8; 64],
+    pub key:   [u8; 64],
+    pub epoch: u64,        // bumped on every rotation; off-chain EzHard resyncs on change
+}
+
+/// Note-chain arguments of the H-MAC guarded instructions. Borsh encodes it
+/// exactly like the four separate arguments it replaced.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct NoteProof {
+    pub old_note:  [u8; 64],
+    pub new_hash:  [u8; 64],
+    pub new_hash2: [u8; 64],
+    pub noise:     [u8; 64],
 }
 
 #[derive(Accounts)]
 pub struct InitGlobalKey<'info> {
+    #[account(seeds = [b"dapp_config"], bump)]
+    pub dapp_config: Account<'info, DappConfig>,
     #[account(
         init,
-        payer  = payer,
+        payer  = owner,
         space  = GLOBAL_KEY_SPACE,
         seeds  = [b"global_key"],
         bump
     )]
     pub global_key: Account<'info, GlobalKey>,
-    #[account(mut)] pub payer: Signer<'info>,
+    #[account(mut)] pub owner: Signer<'info>,
     pub system_program: Program<'info, System>,
     pub rent:           Sysvar<'info, Rent>,
+    /// Caller's RoleGrant; not needed when the caller is the owner
+    pub role_grant: Option<Account<'info, RoleGrant>>,
+}
+
+#[derive(Accounts)]
+pub struct RotateGlobalKey<'info> {
+    #[account(mut, seeds = [b"dapp_config"], bump)]
+    pub dapp_config: Account<'info, DappConfig>,
+    pub owner: Signer<'info>,
+    #[account(mut, seeds = [b"global_key"], bump)]
+    pub global_key: Account<'info, GlobalKey>,
+    /// Caller's RoleGrant; not needed when the caller is the owner
+    pub role_grant: Option<Account<'info, RoleGrant>>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateGlobalKey<'info> {
+    #[account(seeds = [b"dapp_config"], bump)]
+    pub dapp_config: Account<'info, DappConfig>,
+    #[account(mut)] pub owner: Signer<'info>,
+    /// CHECK: still in the 72-byte layout, which `Account<GlobalKey>` rejects
+    #[account(
+        mut,
+        owner = crate::ID,
+        seeds = [b"global_key"],
+        bump
+    )]
+    pub global_key:     UncheckedAccount<'info>,
+    pub system_program: Program<'info, System>,
+    /// Caller's RoleGrant; not needed when the caller is the owner
+    pub role_grant: Option<Account<'info, RoleGrant>>,
+}
+
+#[derive(Accounts)]
+pub struct CloseGlobalKey<'info> {
+    #[account(mut, seeds = [b"dapp_config"], bump)]
+    pub dapp_config: Account<'info, DappConfig>,
+    #[account(mut)] pub owner: Signer<'info>,
+    #[account(mut, close = owner, seeds = [b"global_key"], bump)]
+    pub global_key: Account<'info, GlobalKey>,
+    /// Caller's RoleGrant; not needed when the caller is the owner
+    pub role_grant: Option<Account<'info, RoleGrant>>,
+}
+
+#[derive(Accounts)]
+#[instruction(role: Role, grantee: Pubkey)]
+pub struct GrantRole<'info> {
+    #[account(seeds = [b"dapp_config"], bump)]
+    pub dapp_config: Account<'info, DappConfig>,
+    #[account(mut)] pub admin: Signer<'info>,
+    #[account(
+        init,
+        payer  = admin,
+        space  = RoleGrant::LEN,
+        seeds  = [ROLE_SEED, &[role as u8], grantee.as_ref()],
+        bump
+    )]
+    pub new_grant:      Account<'info, RoleGrant>,
+    /// Caller's admin RoleGrant; not needed when the caller is the owner
+    pub role_grant:     Option<Account<'info, RoleGrant>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(role: Role, grantee: Pubkey)]
+pub struct RevokeRole<'info> {
+    #[account(seeds = [b"dapp_config"], bump)]
+    pub dapp_config: Account<'info, DappConfig>,
+    #[account(mut)] pub admin: Signer<'info>,
+    #[account(
+        mut,
+        close  = admin,
+        seeds  = [ROLE_SEED, &[role as u8], grantee.as_ref()],
+        bump
+    )]
+    pub revoked_grant: Account<'info, RoleGrant>,
+    /// Caller's admin RoleGrant; not needed when the caller is the owner
+    pub role_grant:    Option<Account<'info, RoleGrant>>,
 }
 
 /*════════════════════════════════ Event & Errors ════════════════════════════════════*/
@@ -571,6 +1836,69 @@ pub struct PayoutEvent {
     pub tokens:   u64,
 }
 
+/// `reason == CLAIM_REASON_CANCELLED` for user cancellation, otherwise the
+/// validator's reject code.
+#[event]
+pub struct ClaimClosed {
+    pub authority:  Pubkey,
+    pub session_id: u64,
+    pub reason:     u8,
+}
+
+#[event]
+pub struct GlobalKeyRotated {
+    pub epoch: u64,
+}
+
+#[event]
+pub struct GlobalKeyClosed {
+    pub epoch: u64,
+}
+
+#[event]
+pub struct UsernameChanged {
+    pub authority: Pubkey,
+    pub old:       String,
+    pub new:       String,
+}
+
+#[event]
+pub struct PatronEvent {
+    pub authority: Pubkey,
+    pub tier:      u8,
+    pub until:     i64,
+}
+
+#[event]
+pub struct RoleChanged {
+    pub role:    Role,
+    pub grantee: Pubkey,
+    pub admin:   Pubkey,
+    pub granted: bool,
+}
+
+#[event]
+pub struct AdminOpQueued {
+    pub id:        u64,
+    pub op:        AdminOp,
+    pub eta:       i64,
+    pub queued_by: Pubkey,
+}
+
+#[event]
+pub struct AdminOpExecuted {
+    pub id:       u64,
+    pub op:       AdminOp,
+    pub executor: Pubkey,
+}
+
+#[event]
+pub struct AdminOpCancelled {
+    pub id:       u64,
+    pub op:       AdminOp,
+    pub guardian: Pubkey,
+}
+
 #[error_code]
 pub enum ErrorCode {
     AlreadyInitialized,
@@ -585,4 +1913,275 @@ pub enum ErrorCode {
     HashMismatch,
     DailyCapReached,
     InvalidValidator,
+    InvalidPatronTier,
+    TierNotForSale,
+    PatronTierStillActive,
+    InvalidUsername,
+    UsernameCooldown,
+    InvalidUtcOffset,
+    InvalidDstTable,
+    ClaimAlreadyPending,
+    ClaimExpired,
+    InvalidRejectReason,
+    InvalidGlobalKey,
+    AlreadyMigrated,
+    InvalidAccountLayout,
+    UsernameAlreadyReserved,
+    SessionMismatch,
+    MissingRole,
+    TimelockDelayTooShort,
+    TimelockNotReady,
+    TimelockExpired,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn legacy_profile(is_patron: bool) -> LegacyUserProfile {
+        LegacyUserProfile {
+            authority:         Pubkey::new_unique(),
+            username:          "alice".to_string(),
+            last_username_set: 11,
+            last_claimed:      22,
+            last_paid:         33,
+            claim_flag:        true,
+            is_patron,
+            daily_accumulated: 44,
+            daily_reset:       55,
+        }
+    }
+
+    /// Encode the way the old program did: discriminator, fields, zero tail.
+    fn legacy_account_data(p: &LegacyUserProfile) -> Vec<u8> {
+        let mut data = UserProfile::DISCRIMINATOR.to_vec();
+        p.serialize(&mut data).unwrap();
+        data.resize(LEGACY_USER_PROFILE_LEN, 0);
+        data
+    }
+
+    #[test]
+    fn legacy_profile_round_trips_into_current_layout() {
+        let old  = legacy_profile(false);
+        let key  = old.authority;
+        let data = legacy_account_data(&old);
+        assert_eq!(data.len(), LEGACY_USER_PROFILE_LEN);
+
+        let decoded = LegacyUserProfile::deserialize(&mut &data[8..]).unwrap();
+        let profile = decoded.into_profile();
+        assert_eq!(profile.authority, key);
+        assert_eq!(profile.username, "alice");
+        assert_eq!(profile.last_paid, 33);
+        assert_eq!(profile.daily_accumulated, 44);
+        assert_eq!(profile.daily_reset, 55);
+        assert_eq!(profile.patron_until, 0);
+
+        let mut out = Vec::new();
+        profile.try_serialize(&mut out).unwrap();
+        assert!(out.len() <= UserProfile::LEN);
+    }
+
+    #[test]
+    fn legacy_patron_keeps_unbounded_tier_zero() {
+        let profile = legacy_profile(true).into_profile();
+        assert_eq!(profile.patron_tier, 0);
+        assert_eq!(profile.patron_until, i64::MAX);
+    }
+
+    #[test]
+    fn usernames_are_lowercased_not_rejected() {
+        assert_eq!(normalize_username("Alice_01").unwrap(), "alice_01");
+        assert_eq!(normalize_username("bob").unwrap(), "bob");
+    }
+
+    #[test]
+    fn usernames_outside_the_seed_alphabet_are_rejected() {
+        assert!(normalize_username("").is_err());
+        assert!(normalize_username("has space").is_err());
+        assert!(normalize_username("dash-name").is_err());
+        assert!(normalize_username(&"a".repeat(USERNAME_MAX_LEN + 1)).is_err());
+        assert!(normalize_username(&"a".repeat(USERNAME_MAX_LEN)).is_ok());
+    }
+
+    fn config(day_policy: DayPolicy, utc_offset: i64) -> DappConfig {
+        DappConfig {
+            owner:               Pubkey::new_unique(),
+            validator_address:   Pubkey::default(),
+            gatekeeper_network:  Pubkey::default(),
+            exempt_address:      Pubkey::default(),
+            exception_count:     0,
+            token_mint:          Pubkey::default(),
+            mint_authority_bump: 0,
+            commission_address:  Pubkey::default(),
+            commission_bps:      0,
+            stored_hash:         [0u8; 64],
+            gateway_updates:     0,
+            initialized:         true,
+            day_policy,
+            utc_offset,
+            dst_transitions:     [DstTransition::default(); MAX_DST_TRANSITIONS],
+        }
+    }
+
+    #[test]
+    fn default_day_boundary_matches_deployed_formula() {
+        // The pre-configurable program used `((now + 7h) / DAY) * DAY - 7h`.
+        let cfg = config(DayPolicy::FixedOffset, DEFAULT_UTC_OFFSET);
+        for now in [1_700_000_000i64, 1_700_040_000, 1_700_061_200, 1_700_100_000] {
+            let old = ((now + 7 * 3600) / SECS_PER_DAY) * SECS_PER_DAY - 7 * 3600;
+            assert_eq!(cfg.day_start(now, 0), old);
+        }
+    }
+
+    #[test]
+    fn rolling_window_resets_after_a_full_day() {
+        let cfg = config(DayPolicy::Rolling24h, 0);
+        assert_eq!(cfg.day_start(1_000 + SECS_PER_DAY - 1, 1_000), 1_000);
+        assert_eq!(cfg.day_start(1_000 + SECS_PER_DAY, 1_000), 1_000 + SECS_PER_DAY);
+    }
+
+    #[test]
+    fn dst_table_uses_latest_started_transition() {
+        let mut cfg = config(DayPolicy::DstTable, 0);
+        cfg.dst_transitions[0] = DstTransition { starts_at: 100, utc_offset: 3600 };
+        cfg.dst_transitions[1] = DstTransition { starts_at: 1_000_000_000_000, utc_offset: 7200 };
+        let now = 1_700_000_000;
+        assert_eq!(cfg.day_start(now, 0), local_midnight(now, 3600));
+    }
+
+    // Migration grows to LEN, so it must never be shorter than a layout it migrates.
+    const _: () = assert!(DappConfig::LEN_WITH_DAY_POLICY <= DappConfig::LEN);
+
+    #[test]
+    fn migrated_config_fits_current_layout() {
+        let mut out = Vec::new();
+        config(DayPolicy::FixedOffset, DEFAULT_UTC_OFFSET).try_serialize(&mut out).unwrap();
+        assert_eq!(out.len(), DappConfig::LEN);
+    }
+
+    fn intent(session_id: u64) -> ClaimIntent {
+        ClaimIntent {
+            authority:  Pubkey::new_unique(),
+            session_id,
+            created_at: 1_000,
+            expires_at: 1_000 + CLAIM_TTL_SECONDS,
+            active:     true,
+        }
+    }
+
+    #[test]
+    fn payout_redeems_only_the_intended_session() {
+        let i = intent(7);
+        assert!(i.check_redeem(7, 1_001).is_ok());
+        assert_eq!(
+            i.check_redeem(8, 1_001).unwrap_err(),
+            error!(ErrorCode::SessionMismatch)
+        );
+    }
+
+    #[test]
+    fn payout_rejects_expired_or_closed_intents() {
+        let mut i = intent(7);
+        assert_eq!(
+            i.check_redeem(7, 1_000 + CLAIM_TTL_SECONDS + 1).unwrap_err(),
+            error!(ErrorCode::ClaimExpired)
+        );
+        i.active = false;
+        assert_eq!(i.check_redeem(7, 1_001).unwrap_err(), error!(ErrorCode::NoClaimPending));
+    }
+
+    #[test]
+    fn legacy_global_key_grows_with_zero_epoch() {
+        let mut data = GlobalKey::DISCRIMINATOR.to_vec();
+        data.extend_from_slice(&[9u8; 64]);
+        assert_eq!(data.len(), LEGACY_GLOBAL_KEY_SPACE);
+
+        data.resize(GLOBAL_KEY_SPACE, 0);                 // what `grow_account` leaves
+        let g = GlobalKey::try_deserialize(&mut &data[..]).unwrap();
+        assert_eq!(g.key, [9u8; 64]);
+        assert_eq!(g.epoch, 0);
+    }
+
+    #[test]
+    fn unset_patron_tier_is_not_configured() {
+        assert!(!PatronTier::default().is_configured());
+        let tier = PatronTier { duration_secs: 30 * SECS_PER_DAY, ..PatronTier::default() };
+        assert!(tier.is_configured());
+    }
+
+    fn grant(role: Role, grantee: Pubkey) -> RoleGrant {
+        RoleGrant { role, grantee, granted_by: Pubkey::new_unique(), granted_at: 0 }
+    }
+
+    #[test]
+    fn owner_holds_every_role_without_a_grant() {
+        let cfg = config(DayPolicy::FixedOffset, DEFAULT_UTC_OFFSET);
+        for role in [Role::Admin, Role::ParamManager, Role::Moderator, Role::Treasurer] {
+            assert!(require_role(&cfg, &cfg.owner, role, None).is_ok());
+        }
+        assert_eq!(
+            require_role(&cfg, &Pubkey::new_unique(), Role::Admin, None).unwrap_err(),
+            error!(ErrorCode::MissingRole)
+        );
+    }
+
+    #[test]
+    fn grant_covers_only_its_role_and_grantee() {
+        let cfg = config(DayPolicy::FixedOffset, DEFAULT_UTC_OFFSET);
+        let pm  = Pubkey::new_unique();
+        let g   = grant(Role::ParamManager, pm);
+        assert!(require_role(&cfg, &pm, Role::ParamManager, Some(&g)).is_ok());
+        assert!(require_role(&cfg, &pm, Role::Admin, Some(&g)).is_err());
+        assert!(require_role(&cfg, &Pubkey::new_unique(), Role::ParamManager, Some(&g)).is_err());
+    }
+
+    #[test]
+    fn queued_ops_apply_after_the_delay_only() {
+        let op = |eta| QueuedOp {
+            id:        1,
+            op:        AdminOp::SetCommissionBps(1_500),
+            eta,
+            queued_by: Pubkey::new_unique(),
+            queued_at: 0,
+        };
+        assert_eq!(op(100).check_ready(99).unwrap_err(), error!(ErrorCode::TimelockNotReady));
+        assert_eq!(
+            op(100).check_ready(101 + TIMELOCK_GRACE_PERIOD).unwrap_err(),
+            error!(ErrorCode::TimelockExpired)
+        );
+        assert!(op(i64::MAX - 1).check_ready(i64::MAX).is_ok());   // no overflow near the end
+
+        let mut cfg = config(DayPolicy::FixedOffset, DEFAULT_UTC_OFFSET);
+        op(100).op.apply(&mut cfg).unwrap();
+        assert_eq!(cfg.commission_bps, 1_500);
+        let net = Pubkey::new_unique();
+        AdminOp::SetGatewayNetwork(net).apply(&mut cfg).unwrap();
+        assert_eq!((cfg.gatekeeper_network, cfg.gateway_updates), (net, 1));
+        assert_eq!(
+            AdminOp::SetCommissionBps(MAX_COMMISSION_BPS + 1).apply(&mut cfg).unwrap_err(),
+            error!(ErrorCode::InvalidCommissionBps)
+        );
+    }
+
+    #[test]
+    fn queued_op_fits_its_space() {
+        let mut out = Vec::new();
+        QueuedOp {
+            id:        0,
+            op:        AdminOp::SetGatewayNetwork(Pubkey::new_unique()),
+            eta:       0,
+            queued_by: Pubkey::new_unique(),
+            queued_at: 0,
+        }
+        .try_serialize(&mut out)
+        .unwrap();
+        assert_eq!(out.len(), QueuedOp::LEN);
+    }
+
+    #[test]
+    fn role_grant_fits_its_space() {
+        let mut out = Vec::new();
+        grant(Role::Validator, Pubkey::new_unique()).try_serialize(&mut out).unwrap();
+        assert_eq!(out.len(), RoleGrant::LEN);
+    }
 }
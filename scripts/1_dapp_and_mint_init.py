@@ -25,6 +25,9 @@ SPL_TOKEN_2022_ID = Pubkey.from_string("TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPx
 # Rent sysvar
 RENT_SYSVAR_ID    =  Pubkey.from_string("SysvarRent111111111111111111111111111111111")
 
+# Upgradeable loader (initialize_dapp checks the program's upgrade authority)
+BPF_LOADER_UPGRADEABLE_ID = Pubkey.from_string("BPFLoaderUpgradeab1e11111111111111111111111")
+
 # Where to write any on-chain addresses (PDAs or mints) for later.
 OUTPUT_DIR   = Path("./keys")
 OUTPUT_DIR.mkdir(exist_ok=True)
@@ -57,6 +60,7 @@ async def main(decimals: int = 9):
     #   let (mint_authority, _) = Pubkey::find_program_address(&[b"mint_authority"], &PROGRAM_ID);
     dapp_pda, _   = Pubkey.find_program_address([b"dapp_config"], PROGRAM_ID)
     mint_auth, _  = Pubkey.find_program_address([b"mint_authority"], PROGRAM_ID)
+    program_data, _ = Pubkey.find_program_address([bytes(PROGRAM_ID)], BPF_LOADER_UPGRADEABLE_ID)
 
     print("Derived PDAs:")
     print("  dapp_config PDA   =", dapp_pda)
@@ -71,7 +75,9 @@ async def main(decimals: int = 9):
         ctx=Context(
             accounts={
                 "dapp_config":    dapp_pda,
-                "payer":          user,
+                "payer":          user,             # ← must be the upgrade authority
+                "program":        PROGRAM_ID,
+                "program_data":   program_data,
                 "system_program": SYS_PROGRAM_ID,
                 "rent":           RENT_SYSVAR_ID,
             },
@@ -100,6 +106,7 @@ async def main(decimals: int = 9):
                 "token_program":  SPL_TOKEN_2022_ID,
                 "system_program": SYS_PROGRAM_ID,
                 "rent":           RENT_SYSVAR_ID,
+                "role_grant":     PROGRAM_ID,       # ← none: payer is the owner
             },
             signers=[wallet.payer, mint_kp],
         )
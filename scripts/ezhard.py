@@ -7,7 +7,9 @@ KEYS.mkdir(exist_ok=True)
 
 class EzHard:
     def __init__(self):
-        self.gkey = bytes.fromhex((KEYS / "global_key.txt").read_text().strip())
+        self.gkey  = bytes.fromhex((KEYS / "global_key.txt").read_text().strip())
+        epoch_file = KEYS / "global_key_epoch.txt"
+        self.epoch = int(epoch_file.read_text().strip()) if epoch_file.exists() else 0
 
     # ---------- public helpers --------------------------------------------------
     def current_old_note(self) -> Tuple[int, bytes]:
@@ -36,3 +38,32 @@ class EzHard:
     def commit_fail(self, n: int):
         p = KEYS / f"oldhash{n}_pending.txt"
         p.rename(KEYS / f"oldhash{n}_failed.txt")  # keep for forensics
+
+    # ---------- key rotation ----------------------------------------------------
+    def check_epoch(self, onchain_epoch: int):
+        """Raise if the local key is stale; re-run get_global_key.py to resync."""
+        if onchain_epoch != self.epoch:
+            raise RuntimeError(
+                f"global_key epoch mismatch: local={self.epoch} on-chain={onchain_epoch}"
+            )
+
+    def prepare_rotation(self, tag: bytes = b"rot") -> Tuple[int, bytes, bytes, bytes]:
+        """
+        Like prepare_new, but the new hash is derived from a fresh secret so the
+        chain continues under the rotated key. Returns (next_n, new_note,
+        new_hash, new_secret); call commit_rotation/commit_fail afterwards.
+        """
+        n, _ = self.current_old_note()
+        new_secret = secrets.token_bytes(64)
+        new_note   = secrets.token_bytes(64)
+        new_hash   = hmac.new(new_secret, new_note + tag, hashlib.sha512).digest()
+        (KEYS / f"oldhash{n+1}_pending.txt").write_text(new_note.hex())
+        (KEYS / "global_key_pending.txt").write_text(new_secret.hex())
+        return n + 1, new_note, new_hash, new_secret
+
+    def commit_rotation(self, n: int):
+        self.commit_success(n)
+        (KEYS / "global_key_pending.txt").rename(KEYS / "global_key.txt")
+        self.gkey   = bytes.fromhex((KEYS / "global_key.txt").read_text().strip())
+        self.epoch += 1
+        (KEYS / "global_key_epoch.txt").write_text(f"{self.epoch}\n")
@@ -79,6 +79,7 @@ async def main():
                     "dapp_config": dapp_pda,
                     "owner":       user,
                     "global_key":  global_key,
+                    "role_grant":  PROGRAM_ID,   # none: caller is the owner
                 },
                 signers=[wallet.payer],
             ),
@@ -108,6 +109,7 @@ async def main():
                     "dapp_config": dapp_pda,
                     "owner":       user,
                     "global_key":  global_key,
+                    "role_grant":  PROGRAM_ID,   # none: caller is the owner
                 },
                 signers=[wallet.payer],
             ),
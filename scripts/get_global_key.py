@@ -1,4 +1,4 @@
-# get_global_key.py  — reads the 64-byte secret from the PDA
+# get_global_key.py  — reads the 64-byte secret and its epoch from the PDA
 import asyncio, pathlib
 from solders.pubkey import Pubkey
 from solana.rpc.async_api import AsyncClient
@@ -6,6 +6,7 @@ from solana.rpc.async_api import AsyncClient
 PROGRAM_ID = Pubkey.from_string("9matfyqfsoKn9dgnkdf99pGk7dkL2EPuVte9SkQ9AyxV")
 RPC_URL    = "https://api.devnet.solana.com"
 OUT_FILE   = pathlib.Path("keys/global_key.txt")
+EPOCH_FILE = pathlib.Path("keys/global_key_epoch.txt")
 
 async def main():
     pda, _ = Pubkey.find_program_address([b"global_key"], PROGRAM_ID)
@@ -18,10 +19,13 @@ async def main():
         data = acc.value.data                      # already raw bytes
 
     secret = data[8:72]                            # skip 8-byte discriminator
+    epoch  = int.from_bytes(data[72:80], "little")
     print("secret (hex):", secret.hex())
+    print("epoch:", epoch)
 
     OUT_FILE.parent.mkdir(exist_ok=True)
     OUT_FILE.write_text(secret.hex() + "\n")
+    EPOCH_FILE.write_text(f"{epoch}\n")
     print("→ wrote", OUT_FILE.resolve())
 
 if __name__ == "__main__":
@@ -1,16 +1,186 @@
 import * as anchor from "@coral-xyz/anchor";
-import { Program } from "@coral-xyz/anchor";
+import { Program, BN } from "@coral-xyz/anchor";
+import { Keypair, PublicKey, SystemProgram, SYSVAR_RENT_PUBKEY } from "@solana/web3.js";
+import { assert } from "chai";
+import { keccak_256 } from "@noble/hashes/sha3";
 import { CalCoin } from "../target/types/cal_coin";
 
+const TOKEN_2022_PROGRAM_ID = new PublicKey("TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb");
+const ASSOCIATED_TOKEN_PROGRAM_ID = new PublicKey("ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL");
+const BPF_LOADER_UPGRADEABLE_ID = new PublicKey("BPFLoaderUpgradeab1e11111111111111111111111");
+
+function airdropLeaf(index: number, claimant: PublicKey, amount: BN): Buffer {
+  return Buffer.from(
+    keccak_256(
+      Buffer.concat([
+        new BN(index).toArrayLike(Buffer, "le", 4),
+        claimant.toBuffer(),
+        amount.toArrayLike(Buffer, "le", 8),
+      ])
+    )
+  );
+}
+
+function ata(owner: PublicKey, mint: PublicKey): PublicKey {
+  return PublicKey.findProgramAddressSync(
+    [owner.toBuffer(), TOKEN_2022_PROGRAM_ID.toBuffer(), mint.toBuffer()],
+    ASSOCIATED_TOKEN_PROGRAM_ID
+  )[0];
+}
+
 describe("cal-coin", () => {
   // Configure the client to use the local cluster.
   anchor.setProvider(anchor.AnchorProvider.env());
 
   const program = anchor.workspace.CalCoin as Program<CalCoin>;
+  const provider = anchor.getProvider() as anchor.AnchorProvider;
+  const owner = provider.wallet.publicKey;
+  const mint = Keypair.generate();
+
+  const pda = (...seeds: Buffer[]) =>
+    PublicKey.findProgramAddressSync(seeds, program.programId)[0];
+  const dappConfig = pda(Buffer.from("dapp_config"));
+  const mintAuthority = pda(Buffer.from("mint_authority"));
+
+  it("initializes the dapp and its mint", async () => {
+    const [programData] = PublicKey.findProgramAddressSync(
+      [program.programId.toBuffer()],
+      BPF_LOADER_UPGRADEABLE_ID
+    );
+    await program.methods
+      .initializeDapp()
+      .accounts({
+        dappConfig,
+        payer: owner,
+        program: program.programId,
+        programData,
+        systemProgram: SystemProgram.programId,
+        rent: SYSVAR_RENT_PUBKEY,
+      })
+      .rpc();
+
+    await program.methods
+      .initializeMint(9)
+      .accounts({
+        dappConfig,
+        mintAuthority,
+        mintForDapp: mint.publicKey,
+        payer: owner,
+        tokenProgram: TOKEN_2022_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+        rent: SYSVAR_RENT_PUBKEY,
+        roleGrant: null,
+      })
+      .signers([mint])
+      .rpc();
+
+    const cfg = await program.account.dappConfig.fetch(dappConfig);
+    assert.ok(cfg.initialized);
+    assert.ok(cfg.tokenMint.equals(mint.publicKey));
+  });
+
+  it("takes the config writable wherever the faucet mints", () => {
+    // record_mint bumps total_minted and total_claims; on a read-only config
+    // Anchor drops those writes and faucet mints escape max_supply.
+    for (const name of ["claim", "claimAndStake"]) {
+      const ix = program.idl.instructions.find((i) => i.name === name);
+      const cfg = ix.accounts.find((a) => a.name === "dappConfig") as { isMut: boolean };
+      assert.ok(cfg.isMut, `${name} must take dappConfig as mut`);
+    }
+  });
+
+  it("mints a released vesting schedule with the mint authority PDA", async () => {
+    const total = new BN(5_000_000_000);
+    const vesting = pda(Buffer.from("vesting"), owner.toBuffer());
+    const start = new BN(Math.floor(Date.now() / 1000) - 60);
+
+    await program.methods
+      .createVesting(owner, total, start, new BN(0), new BN(1), false)
+      .accounts({
+        dappConfig,
+        owner,
+        vesting,
+        systemProgram: SystemProgram.programId,
+        roleGrant: null,
+      })
+      .rpc();
+    let cfg = await program.account.dappConfig.fetch(dappConfig);
+    assert.ok(cfg.reservedSupply.eq(total));
+
+    const beneficiaryAta = ata(owner, mint.publicKey);
+    await program.methods
+      .releaseVested()
+      .accounts({
+        dappConfig,
+        beneficiary: owner,
+        vesting,
+        tokenMint: mint.publicKey,
+        mintAuthority,
+        beneficiaryAta,
+        tokenProgram: TOKEN_2022_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .rpc();
+
+    const balance = await provider.connection.getTokenAccountBalance(beneficiaryAta);
+    assert.equal(balance.value.amount, total.toString());
+    cfg = await program.account.dappConfig.fetch(dappConfig);
+    assert.ok(cfg.totalMinted.eq(total));
+    assert.ok(cfg.reservedSupply.isZero());
+  });
+
+  it("mints an airdrop claim out of the distributor's reservation", async () => {
+    const id = new BN(1);
+    const amount = new BN(2_000_000_000);
+    const mine = airdropLeaf(0, owner, amount);
+    const other = airdropLeaf(1, Keypair.generate().publicKey, amount);
+    const [lo, hi] = Buffer.compare(mine, other) <= 0 ? [mine, other] : [other, mine];
+    const root = Buffer.from(keccak_256(Buffer.concat([lo, hi])));
+
+    const distributor = pda(Buffer.from("distributor"), id.toArrayLike(Buffer, "le", 8));
+    const before = await program.account.dappConfig.fetch(dappConfig);
+    await program.methods
+      .createDistributor(
+        id,
+        Array.from(root),
+        amount.muln(2),
+        2,
+        new BN(Math.floor(Date.now() / 1000) + 3600)
+      )
+      .accounts({
+        dappConfig,
+        owner,
+        distributor,
+        systemProgram: SystemProgram.programId,
+        roleGrant: null,
+      })
+      .rpc();
+
+    const claimantAta = ata(owner, mint.publicKey);
+    const balanceBefore = await provider.connection.getTokenAccountBalance(claimantAta);
+    await program.methods
+      .claimAirdrop(id, 0, amount, [Array.from(other)])
+      .accounts({
+        dappConfig,
+        claimant: owner,
+        distributor,
+        tokenMint: mint.publicKey,
+        mintAuthority,
+        claimantAta,
+        tokenProgram: TOKEN_2022_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .rpc();
 
-  it("Is initialized!", async () => {
-    // Add your test here.
-    const tx = await program.methods.initialize().rpc();
-    console.log("Your transaction signature", tx);
+    const balance = await provider.connection.getTokenAccountBalance(claimantAta);
+    assert.equal(
+      new BN(balance.value.amount).sub(new BN(balanceBefore.value.amount)).toString(),
+      amount.toString()
+    );
+    const cfg = await program.account.dappConfig.fetch(dappConfig);
+    assert.ok(cfg.totalMinted.sub(before.totalMinted).eq(amount));
+    assert.ok(cfg.reservedSupply.sub(before.reservedSupply).eq(amount));
   });
 });